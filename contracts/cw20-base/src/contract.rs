@@ -360,11 +360,12 @@ pub fn migrate(
 
 #[cfg(test)]
 mod tests {
-    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info, MockApi, MockStorage};
     use cosmwasm_std::{coins, from_binary, Api, CosmosMsg, Order, StdError, WasmMsg};
 
     use cw2::ContractVersion;
     use cw20::{AllowanceResponse, Expiration};
+    use cw_multi_test::{App, ContractWrapper, SimpleBank, SimpleStaking};
 
     use crate::migrations::generate_v01_test_data;
     use crate::state::allowances_read;
@@ -1016,4 +1017,73 @@ mod tests {
         };
         assert_eq!(allow, expect);
     }
+
+    // stands in for the v0.1 code, it only sets up the state as v0.1 left it
+    fn init_v01(
+        deps: DepsMut,
+        _env: Env,
+        _info: MessageInfo,
+        _msg: InitMsg,
+    ) -> StdResult<InitResponse> {
+        generate_v01_test_data(deps.storage, deps.api)?;
+        Ok(InitResponse::default())
+    }
+
+    #[test]
+    fn migrate_from_v01_across_code_ids() {
+        let api = Box::new(MockApi::default());
+        let mut app = App::new(
+            api,
+            mock_env().block,
+            SimpleBank {},
+            SimpleStaking::default(),
+            || Box::new(MockStorage::new()),
+        );
+        let v01_id = app.store_code(Box::new(ContractWrapper::new(handle, init_v01, query)));
+        let current_id = app.store_code(Box::new(
+            ContractWrapper::new(handle, init, query).with_migrate(migrate),
+        ));
+
+        let admin = HumanAddr::from("admin");
+        let init_msg = InitMsg {
+            name: "Sample Coin".to_string(),
+            symbol: "SAMP".to_string(),
+            decimals: 2,
+            initial_balances: vec![],
+            mint: None,
+        };
+        let token = app
+            .instantiate_contract(v01_id, &admin, &init_msg, &[], "SAMP", Some(admin.clone()))
+            .unwrap();
+        let version = cw2::query_contract_info(&app, &token).unwrap();
+        assert_eq!(version.version, "v0.1.0");
+
+        app.migrate_contract(&admin, &token, current_id, &MigrateMsg {})
+            .unwrap();
+        let version = cw2::query_contract_info(&app, &token).unwrap();
+        assert_eq!(version.version, CONTRACT_VERSION);
+
+        // the migrated state is served by the new code
+        let balance: BalanceResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &token,
+                &QueryMsg::Balance {
+                    address: "user2".into(),
+                },
+            )
+            .unwrap();
+        assert_eq!(balance.balance, Uint128(654321));
+        let allowance: AllowanceResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &token,
+                &QueryMsg::Allowance {
+                    owner: "user2".into(),
+                    spender: "spender1".into(),
+                },
+            )
+            .unwrap();
+        assert_eq!(allowance.expires, Expiration::AtTime(1598647517));
+    }
 }
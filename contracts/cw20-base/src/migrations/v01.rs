@@ -102,7 +102,7 @@ pub mod testing {
     /// Maybe we add export and import functions for MockStorage to generate JSON test vectors?
     /// Maybe we embed the entire v0.1 code here to generate state??
     #[allow(dead_code)]
-    pub fn generate_v01_test_data<S: Storage, A: Api>(storage: &mut S, api: &A) -> StdResult<()> {
+    pub fn generate_v01_test_data(storage: &mut dyn Storage, api: &dyn Api) -> StdResult<()> {
         // TokenInfo:
         // name: Sample Coin
         // symbol: SAMP
@@ -3,7 +3,7 @@
 use cosmwasm_std::testing::{mock_env, MockApi, MockStorage};
 use cosmwasm_std::{coins, to_binary, HumanAddr, Uint128};
 use cw20::{Cw20CoinHuman, Cw20Contract, Cw20HandleMsg};
-use cw_multi_test::{App, Contract, ContractWrapper, SimpleBank};
+use cw_multi_test::{App, Contract, ContractWrapper, SimpleBank, SimpleStaking};
 
 use crate::msg::{CreateMsg, DetailsResponse, HandleMsg, InitMsg, QueryMsg, ReceiveMsg};
 
@@ -12,7 +12,9 @@ fn mock_app() -> App {
     let api = Box::new(MockApi::default());
     let bank = SimpleBank {};
 
-    App::new(api, env.block, bank, || Box::new(MockStorage::new()))
+    App::new(api, env.block, bank, SimpleStaking::default(), || {
+        Box::new(MockStorage::new())
+    })
 }
 
 pub fn contract_escrow() -> Box<dyn Contract> {
@@ -58,13 +60,13 @@ fn escrow_happy_path_cw20_tokens() {
         mint: None,
     };
     let cash_addr = router
-        .instantiate_contract(cw20_id, &owner, &msg, &[], "CASH")
+        .instantiate_contract(cw20_id, &owner, &msg, &[], "CASH", None)
         .unwrap();
 
     // set up reflect contract
     let escrow_id = router.store_code(contract_escrow());
     let escrow_addr = router
-        .instantiate_contract(escrow_id, &owner, &InitMsg {}, &[], "Escrow")
+        .instantiate_contract(escrow_id, &owner, &InitMsg {}, &[], "Escrow", None)
         .unwrap();
 
     // they are different
@@ -100,8 +102,14 @@ fn escrow_happy_path_cw20_tokens() {
     let res = router
         .execute_contract(&owner, &cash_addr, &send_msg, &[])
         .unwrap();
-    println!("{:?}", res.attributes);
     assert_eq!(6, res.attributes.len());
+    // the token sends, then the escrow receives and creates
+    assert_eq!(2, res.events.len());
+    res.assert_event(&cash_addr, "action", "send");
+    res.assert_event(&cash_addr, "to", escrow_addr.as_str());
+    res.assert_event(&escrow_addr, "action", "create");
+    res.assert_event(&escrow_addr, "id", &id);
+    assert!(!res.has_event(&cash_addr, "action", "create"));
 
     // ensure balances updated
     let owner_balance = cash.balance(&router, owner.clone()).unwrap();
@@ -127,9 +135,12 @@ fn escrow_happy_path_cw20_tokens() {
 
     // release escrow
     let approve_msg = HandleMsg::Approve { id: id.clone() };
-    let _ = router
+    let res = router
         .execute_contract(&arb, &escrow_addr, &approve_msg, &[])
         .unwrap();
+    res.assert_event(&escrow_addr, "action", "approve");
+    res.assert_event(&cash_addr, "action", "transfer");
+    res.assert_event(&cash_addr, "to", ben.as_str());
 
     // ensure balances updated - release to ben
     let owner_balance = cash.balance(&router, owner.clone()).unwrap();
@@ -451,7 +451,7 @@ mod tests {
     use cw2::{query_contract_info, ContractVersion};
     use cw4::{Cw4HandleMsg, Member};
     use cw4_group::helpers::Cw4GroupContract;
-    use cw_multi_test::{next_block, App, Contract, ContractWrapper, SimpleBank};
+    use cw_multi_test::{next_block, App, Contract, ContractWrapper, SimpleBank, SimpleStaking};
 
     use super::*;
     use crate::msg::Threshold;
@@ -494,7 +494,9 @@ mod tests {
         let api = Box::new(MockApi::default());
         let bank = SimpleBank {};
 
-        App::new(api, env.block, bank, || Box::new(MockStorage::new()))
+        App::new(api, env.block, bank, SimpleStaking::default(), || {
+            Box::new(MockStorage::new())
+        })
     }
 
     // uploads code and returns address of group contract
@@ -504,7 +506,7 @@ mod tests {
             admin: Some(OWNER.into()),
             members,
         };
-        app.instantiate_contract(group_id, OWNER, &msg, &[], "group")
+        app.instantiate_contract(group_id, OWNER, &msg, &[], "group", None)
             .unwrap()
     }
 
@@ -520,7 +522,7 @@ mod tests {
             threshold,
             max_voting_period,
         };
-        app.instantiate_contract(flex_id, OWNER, &msg, &[], "flex")
+        app.instantiate_contract(flex_id, OWNER, &msg, &[], "flex", None)
             .unwrap()
     }
 
@@ -623,7 +625,8 @@ mod tests {
             threshold: Threshold::AbsoluteCount { weight: 0 },
             max_voting_period,
         };
-        let res = app.instantiate_contract(flex_id, OWNER, &init_msg, &[], "zero required weight");
+        let res =
+            app.instantiate_contract(flex_id, OWNER, &init_msg, &[], "zero required weight", None);
 
         // Verify
         assert_eq!(
@@ -637,7 +640,8 @@ mod tests {
             threshold: Threshold::AbsoluteCount { weight: 100 },
             max_voting_period,
         };
-        let res = app.instantiate_contract(flex_id, OWNER, &init_msg, &[], "high required weight");
+        let res =
+            app.instantiate_contract(flex_id, OWNER, &init_msg, &[], "high required weight", None);
 
         // Verify
         assert_eq!(
@@ -652,7 +656,7 @@ mod tests {
             max_voting_period,
         };
         let flex_addr = app
-            .instantiate_contract(flex_id, OWNER, &init_msg, &[], "all good")
+            .instantiate_contract(flex_id, OWNER, &init_msg, &[], "all good", None)
             .unwrap();
 
         // Verify contract version set properly
@@ -104,29 +104,33 @@ pub fn update_members(
 ) -> Result<MemberChangedHookMsg, ContractError> {
     ADMIN.assert_admin(deps.as_ref(), &sender)?;
 
-    let mut total = TOTAL.load(deps.storage)?;
-    let mut diffs: Vec<MemberDiff> = vec![];
-
-    // add all new members and update total
+    let mut addrs = Vec::with_capacity(to_add.len() + to_remove.len());
+    let mut raws = Vec::with_capacity(addrs.capacity());
+    let mut weights = Vec::with_capacity(addrs.capacity());
     for add in to_add.into_iter() {
-        let raw = deps.api.canonical_address(&add.addr)?;
-        MEMBERS.update(deps.storage, &raw, height, |old| -> StdResult<_> {
-            total -= old.unwrap_or_default();
-            total += add.weight;
-            diffs.push(MemberDiff::new(add.addr, old, Some(add.weight)));
-            Ok(add.weight)
-        })?;
+        raws.push(deps.api.canonical_address(&add.addr)?);
+        addrs.push(add.addr);
+        weights.push(Some(add.weight));
     }
-
     for remove in to_remove.into_iter() {
-        let raw = deps.api.canonical_address(&remove)?;
-        let old = MEMBERS.may_load(deps.storage, &raw)?;
-        // Only process this if they were actually in the list before
-        if let Some(weight) = old {
-            diffs.push(MemberDiff::new(remove, Some(weight), None));
-            total -= weight;
-            MEMBERS.remove(deps.storage, &raw, height)?;
+        raws.push(deps.api.canonical_address(&remove)?);
+        addrs.push(remove);
+        weights.push(None);
+    }
+
+    // removing a non-member is a no-op and produces no diff
+    let items = raws.iter().map(|raw| raw.as_slice()).zip(weights.clone());
+    let olds = MEMBERS.update_many(deps.storage, items, height)?;
+
+    let mut total = TOTAL.load(deps.storage)?;
+    let mut diffs: Vec<MemberDiff> = vec![];
+    for ((addr, old), new) in addrs.into_iter().zip(olds).zip(weights) {
+        if old.is_none() && new.is_none() {
+            continue;
         }
+        total -= old.unwrap_or_default();
+        total += new.unwrap_or_default();
+        diffs.push(MemberDiff::new(addr, old, new));
     }
 
     TOTAL.save(deps.storage, &total)?;
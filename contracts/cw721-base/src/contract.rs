@@ -500,7 +500,8 @@ fn query_tokens(
     let tokens: Result<Vec<String>, _> = tokens()
         .idx
         .owner
-        .pks(deps.storage, &owner_raw, start, None, Order::Ascending)
+        .prefix(owner_raw.to_vec())
+        .keys(deps.storage, start, None, Order::Ascending)
         .take(limit)
         .map(String::from_utf8)
         .collect();
@@ -1,9 +1,7 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{
-    HumanAddr, Querier, QuerierWrapper, QueryRequest, StdResult, Storage, WasmQuery,
-};
+use cosmwasm_std::{HumanAddr, Querier, QuerierWrapper, StdResult, Storage};
 use cw_storage_plus::Item;
 
 pub const CONTRACT: Item<ContractVersion> = Item::new("contract_info");
@@ -48,11 +46,7 @@ pub fn query_contract_info<Q: Querier, T: Into<HumanAddr>>(
     querier: &Q,
     contract_addr: T,
 ) -> StdResult<ContractVersion> {
-    let req = QueryRequest::Wasm(WasmQuery::Raw {
-        contract_addr: contract_addr.into(),
-        key: CONTRACT.as_slice().into(),
-    });
-    QuerierWrapper::new(querier).query(&req)
+    CONTRACT.query_must(&QuerierWrapper::new(querier), contract_addr.into())
 }
 
 #[cfg(test)]
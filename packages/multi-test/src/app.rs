@@ -1,33 +1,114 @@
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
 use serde::Serialize;
+use std::fmt;
 
 #[cfg(test)]
 use cosmwasm_std::testing::{mock_env, MockApi};
 use cosmwasm_std::{
     from_slice, to_binary, Api, Attribute, BankMsg, Binary, BlockInfo, Coin, ContractResult,
-    CosmosMsg, Empty, HandleResponse, HumanAddr, InitResponse, MessageInfo, Querier, QuerierResult,
-    QuerierWrapper, QueryRequest, SystemError, SystemResult, WasmMsg,
+    CosmosMsg, CustomQuery, Empty, HandleResponse, HumanAddr, InitResponse, MessageInfo,
+    MigrateResponse, Querier, QuerierResult, QuerierWrapper, QueryRequest, StakingMsg, SystemError,
+    SystemResult, Validator, WasmMsg,
 };
 
 use crate::bank::{Bank, BankCache, BankOps, BankRouter};
-use crate::wasm::{Contract, StorageFactory, WasmCache, WasmOps, WasmRouter};
+use crate::custom_handler::{
+    CustomCache, CustomHandler, CustomOps, CustomRouter, FailingCustomHandler,
+};
+use crate::staking::{
+    Staking, StakingCache, StakingOps, StakingRouter, StakingTransfer, BONDED_POOL,
+};
+use crate::wasm::{
+    CodeData, Contract, ContractData, MetadataQuery, StorageFactory, WasmCache, WasmOps, WasmRouter,
+};
+
+/// The creator of code stored with App::store_code
+pub const CODE_CREATOR: &str = "code_creator";
+
+/// The attributes one contract returned when it processed a message
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    /// The entry point that was called: "instantiate", "execute" or "migrate"
+    pub ty: String,
+    pub contract: HumanAddr,
+    pub attributes: Vec<Attribute>,
+}
+
+impl Event {
+    pub fn new<T: Into<String>, U: Into<HumanAddr>>(ty: T, contract: U) -> Self {
+        Event {
+            ty: ty.into(),
+            contract: contract.into(),
+            attributes: vec![],
+        }
+    }
+
+    /// Returns the value of the first attribute with this key
+    pub fn attr(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|a| a.key == key)
+            .map(|a| a.value.as_str())
+    }
+}
 
 #[derive(Default, Clone, Debug)]
 pub struct AppResponse {
+    /// All attributes of all events, flattened in the order they were emitted
     pub attributes: Vec<Attribute>,
+    /// One event for each contract call, the outer call comes before those of its messages
+    pub events: Vec<Event>,
     pub data: Option<Binary>,
 }
 
+impl AppResponse {
+    /// All events emitted by the given contract
+    pub fn events_from<T: Into<HumanAddr>>(&self, contract: T) -> Vec<&Event> {
+        let contract = contract.into();
+        self.events
+            .iter()
+            .filter(|e| e.contract == contract)
+            .collect()
+    }
+
+    /// Returns true if the contract emitted an event with this attribute
+    pub fn has_event<T: Into<HumanAddr>>(&self, contract: T, key: &str, value: &str) -> bool {
+        self.events_from(contract).iter().any(|e| {
+            e.attributes
+                .iter()
+                .any(|a| a.key == key && a.value == value)
+        })
+    }
+
+    /// Panics with all events listed, unless the contract emitted an event with this attribute
+    pub fn assert_event<T: Into<HumanAddr>>(&self, contract: T, key: &str, value: &str) {
+        let contract = contract.into();
+        if !self.has_event(&contract, key, value) {
+            panic!(
+                "No event from {} with {}={}, events: {:?}",
+                contract, key, value, self.events
+            );
+        }
+    }
+}
+
 // This can be InitResponse, HandleResponse, MigrationResponse
 #[derive(Default, Clone)]
-pub struct ActionResponse {
-    // TODO: allow T != Empty
-    pub messages: Vec<CosmosMsg<Empty>>,
+pub struct ActionResponse<C = Empty>
+where
+    C: Clone + fmt::Debug + PartialEq + JsonSchema,
+{
+    pub messages: Vec<CosmosMsg<C>>,
     pub attributes: Vec<Attribute>,
     pub data: Option<Binary>,
 }
 
-impl From<HandleResponse<Empty>> for ActionResponse {
-    fn from(input: HandleResponse<Empty>) -> Self {
+impl<C> From<HandleResponse<C>> for ActionResponse<C>
+where
+    C: Clone + fmt::Debug + PartialEq + JsonSchema,
+{
+    fn from(input: HandleResponse<C>) -> Self {
         ActionResponse {
             messages: input.messages,
             attributes: input.attributes,
@@ -36,8 +117,24 @@ impl From<HandleResponse<Empty>> for ActionResponse {
     }
 }
 
-impl ActionResponse {
-    fn init(input: InitResponse<Empty>, address: HumanAddr) -> Self {
+impl<C> From<MigrateResponse<C>> for ActionResponse<C>
+where
+    C: Clone + fmt::Debug + PartialEq + JsonSchema,
+{
+    fn from(input: MigrateResponse<C>) -> Self {
+        ActionResponse {
+            messages: input.messages,
+            attributes: input.attributes,
+            data: input.data,
+        }
+    }
+}
+
+impl<C> ActionResponse<C>
+where
+    C: Clone + fmt::Debug + PartialEq + JsonSchema,
+{
+    fn init(input: InitResponse<C>, address: HumanAddr) -> Self {
         ActionResponse {
             messages: input.messages,
             attributes: input.attributes,
@@ -49,14 +146,28 @@ impl ActionResponse {
 /// Router is a persisted state. You can query this.
 /// Execution generally happens on the RouterCache, which then can be atomically committed or rolled back.
 /// We offer .execute() as a wrapper around cache, execute, commit/rollback process
-pub struct App {
-    wasm: WasmRouter,
+///
+/// `C` and `Q` are the custom message and query types of the chain, which are processed
+/// by the `CustomHandler`. They are `Empty` unless you build the App with `App::new_custom`.
+pub struct App<C = Empty, Q = Empty>
+where
+    C: Clone + fmt::Debug + PartialEq + JsonSchema,
+{
+    wasm: WasmRouter<C>,
     bank: BankRouter,
+    staking: StakingRouter,
+    custom: CustomRouter<C, Q>,
+    // picks the custom queries the App answers itself, see `with_metadata_queries`
+    metadata_query: Option<fn(&Q) -> Option<MetadataQuery>>,
 }
 
-impl Querier for App {
+impl<C, Q> Querier for App<C, Q>
+where
+    C: Clone + fmt::Debug + PartialEq + JsonSchema + 'static,
+    Q: CustomQuery + DeserializeOwned + 'static,
+{
     fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
-        let request: QueryRequest<Empty> = match from_slice(bin_request) {
+        let request: QueryRequest<Q> = match from_slice(bin_request) {
             Ok(v) => v,
             Err(e) => {
                 return SystemResult::Err(SystemError::InvalidRequest {
@@ -71,19 +182,64 @@ impl Querier for App {
 }
 
 impl App {
-    pub fn new<B: Bank + 'static>(
+    pub fn new<B: Bank + 'static, S: Staking + 'static>(
         api: Box<dyn Api>,
         block: BlockInfo,
         bank: B,
+        staking: S,
         storage_factory: StorageFactory,
     ) -> Self {
+        App::new_custom(
+            api,
+            block,
+            bank,
+            staking,
+            FailingCustomHandler::default(),
+            storage_factory,
+        )
+    }
+}
+
+impl<C, Q> App<C, Q>
+where
+    C: Clone + fmt::Debug + PartialEq + JsonSchema + 'static,
+    Q: CustomQuery + DeserializeOwned + 'static,
+{
+    /// Like `new`, but with a handler for the custom messages and queries of the chain
+    pub fn new_custom<B, S, H>(
+        api: Box<dyn Api>,
+        block: BlockInfo,
+        bank: B,
+        staking: S,
+        custom: H,
+        storage_factory: StorageFactory,
+    ) -> Self
+    where
+        B: Bank + 'static,
+        S: Staking + 'static,
+        H: CustomHandler<C, Q> + 'static,
+    {
         App {
             wasm: WasmRouter::new(api, block, storage_factory),
             bank: BankRouter::new(bank, storage_factory()),
+            staking: StakingRouter::new(staking, storage_factory()),
+            custom: CustomRouter::new(custom, storage_factory()),
+            metadata_query: None,
         }
     }
 
-    pub fn cache(&'_ self) -> AppCache<'_> {
+    /// Lets contracts read the data of `code_data` and `contract_data` with a custom query.
+    /// Every custom query for which `extract` returns a `MetadataQuery` is answered by the App,
+    /// all others still go to the `CustomHandler`. If the chain has no custom queries of its
+    /// own, use `MetadataQuery` as `Q`:
+    ///
+    /// `App::<Empty, MetadataQuery>::new_custom(...).with_metadata_queries(|q| Some(q.clone()))`
+    pub fn with_metadata_queries(mut self, extract: fn(&Q) -> Option<MetadataQuery>) -> Self {
+        self.metadata_query = Some(extract);
+        self
+    }
+
+    pub fn cache(&'_ self) -> AppCache<'_, C, Q> {
         AppCache::new(self)
     }
 
@@ -97,6 +253,19 @@ impl App {
         self.wasm.update_block(action);
     }
 
+    /// Runs the staking end block logic for the current block and pays out all unbonding
+    /// tokens that matured up to the block time. Call this after moving the block forward.
+    /// On error, nothing is changed.
+    pub fn end_block(&mut self) -> Result<(), String> {
+        let block = self.wasm.block_info();
+        let mut cache = self.cache();
+        let transfers = cache.staking.end_block(&block)?;
+        cache.apply_transfers(transfers)?;
+        let ops = cache.prepare();
+        ops.commit(self);
+        Ok(())
+    }
+
     /// Returns a copy of the current block_info
     pub fn block_info(&self) -> BlockInfo {
         self.wasm.block_info()
@@ -111,10 +280,38 @@ impl App {
         self.bank.set_balance(account, amount)
     }
 
+    /// This is an "admin" function to register a validator that accounts can delegate to
+    pub fn add_validator(&mut self, validator: Validator) -> Result<(), String> {
+        self.staking.add_validator(validator)
+    }
+
     /// This registers contract code (like uploading wasm bytecode on a chain),
     /// so it can later be used to instantiate a contract.
-    pub fn store_code(&mut self, code: Box<dyn Contract>) -> u64 {
-        self.wasm.store_code(code) as u64
+    /// The code is stored as uploaded by CODE_CREATOR.
+    pub fn store_code(&mut self, code: Box<dyn Contract<C>>) -> u64 {
+        self.store_code_with_creator(CODE_CREATOR.into(), code)
+    }
+
+    /// Like store_code, but records who uploaded the code
+    pub fn store_code_with_creator(
+        &mut self,
+        creator: HumanAddr,
+        code: Box<dyn Contract<C>>,
+    ) -> u64 {
+        self.wasm.store_code(creator, code) as u64
+    }
+
+    /// Returns the creator and checksum of the stored code.
+    /// Contracts can query it if the App was built `with_metadata_queries`.
+    pub fn code_data(&self, code_id: u64) -> Result<CodeData, String> {
+        self.wasm.code_data(code_id as usize)
+    }
+
+    /// Returns the code id, creator, admin and label of the contract.
+    /// cosmwasm-std 0.13 has no `WasmQuery::ContractInfo`, but contracts can query it as a custom
+    /// query if the App was built `with_metadata_queries`.
+    pub fn contract_data(&self, address: &HumanAddr) -> Result<ContractData, String> {
+        self.wasm.contract_data(address)
     }
 
     /// Simple helper so we get access to all the QuerierWrapper helpers,
@@ -125,16 +322,30 @@ impl App {
 
     /// Handles arbitrary QueryRequest, this is wrapped by the Querier interface, but this
     /// is nicer to use.
-    pub fn query(&self, request: QueryRequest<Empty>) -> Result<Binary, String> {
+    pub fn query(&self, request: QueryRequest<Q>) -> Result<Binary, String> {
         match request {
             QueryRequest::Wasm(req) => self.wasm.query(self, req),
             QueryRequest::Bank(req) => self.bank.query(req),
-            _ => unimplemented!(),
+            QueryRequest::Staking(req) => self.staking.query(&self.wasm.block_info(), req),
+            QueryRequest::Custom(req) => match self.metadata_query.and_then(|f| f(&req)) {
+                Some(query) => self.query_metadata(query),
+                None => self.custom.query(&self.wasm.block_info(), req),
+            },
         }
     }
 
+    fn query_metadata(&self, query: MetadataQuery) -> Result<Binary, String> {
+        let res = match query {
+            MetadataQuery::CodeInfo { code_id } => to_binary(&self.code_data(code_id)?),
+            MetadataQuery::ContractInfo { contract_addr } => {
+                to_binary(&self.contract_data(&contract_addr)?)
+            }
+        };
+        res.map_err(|e| e.to_string())
+    }
+
     /// Create a contract and get the new address.
-    /// This is just a helper around execute()
+    /// The admin, if set, is the only one allowed to migrate the contract later.
     pub fn instantiate_contract<T: Serialize, U: Into<String>, V: Into<HumanAddr>>(
         &mut self,
         code_id: u64,
@@ -142,20 +353,40 @@ impl App {
         init_msg: &T,
         send_funds: &[Coin],
         label: U,
+        admin: Option<HumanAddr>,
     ) -> Result<HumanAddr, String> {
         // instantiate contract
         let init_msg = to_binary(init_msg).map_err(|e| e.to_string())?;
-        let msg: CosmosMsg = WasmMsg::Instantiate {
-            code_id,
-            msg: init_msg,
-            send: send_funds.to_vec(),
-            label: Some(label.into()),
-        }
-        .into();
-        let res = self.execute(sender.into(), msg)?;
+        let sender = sender.into();
+        let label = Some(label.into());
+        let res = self.with_cache(|cache| {
+            cache.instantiate(
+                sender,
+                code_id as usize,
+                init_msg,
+                send_funds.to_vec(),
+                label,
+                admin,
+            )
+        })?;
         parse_contract_addr(&res.data)
     }
 
+    /// Migrates the contract to the code stored under new_code_id, calling its migrate
+    /// entry point. Only the admin of the contract can do this.
+    pub fn migrate_contract<T: Serialize, U: Into<HumanAddr>>(
+        &mut self,
+        sender: U,
+        contract_addr: U,
+        new_code_id: u64,
+        migrate_msg: &T,
+    ) -> Result<AppResponse, String> {
+        let msg = to_binary(migrate_msg).map_err(|e| e.to_string())?;
+        let sender = sender.into();
+        let contract_addr = contract_addr.into();
+        self.with_cache(|cache| cache.migrate(sender, contract_addr, new_code_id as usize, msg))
+    }
+
     /// Execute a contract and process all returned messages.
     /// This is just a helper around execute()
     pub fn execute_contract<T: Serialize, U: Into<HumanAddr>>(
@@ -178,11 +409,7 @@ impl App {
     /// Runs arbitrary CosmosMsg.
     /// This will create a cache before the execution, so no state changes are persisted if this
     /// returns an error, but all are persisted on success.
-    pub fn execute(
-        &mut self,
-        sender: HumanAddr,
-        msg: CosmosMsg<Empty>,
-    ) -> Result<AppResponse, String> {
+    pub fn execute(&mut self, sender: HumanAddr, msg: CosmosMsg<C>) -> Result<AppResponse, String> {
         let mut all = self.execute_multi(sender, vec![msg])?;
         let res = all.pop().unwrap();
         Ok(res)
@@ -194,21 +421,27 @@ impl App {
     pub fn execute_multi(
         &mut self,
         sender: HumanAddr,
-        msgs: Vec<CosmosMsg<Empty>>,
+        msgs: Vec<CosmosMsg<C>>,
     ) -> Result<Vec<AppResponse>, String> {
-        // we need to do some caching of storage here, once in the entry point:
-        // meaning, wrap current state, all writes go to a cache, only when execute
-        // returns a success do we flush it (otherwise drop it)
+        // run all messages, stops at first error
+        self.with_cache(|cache| {
+            msgs.into_iter()
+                .map(|msg| cache.execute(sender.clone(), msg))
+                .collect()
+        })
+    }
 
+    // we need to do some caching of storage here, once in the entry point:
+    // meaning, wrap current state, all writes go to a cache, only when the action
+    // returns a success do we flush it (otherwise drop it)
+    fn with_cache<F, T>(&mut self, action: F) -> Result<T, String>
+    where
+        F: FnOnce(&mut AppCache<C, Q>) -> Result<T, String>,
+    {
         let mut cache = self.cache();
+        let res = action(&mut cache);
 
-        // run all messages, stops at first error
-        let res: Result<Vec<AppResponse>, String> = msgs
-            .into_iter()
-            .map(|msg| cache.execute(sender.clone(), msg))
-            .collect();
-
-        // this only happens if all messages run successfully
+        // this only happens if the action was successful
         if res.is_ok() {
             let ops = cache.prepare();
             ops.commit(self);
@@ -217,30 +450,48 @@ impl App {
     }
 }
 
-pub struct AppCache<'a> {
-    router: &'a App,
-    wasm: WasmCache<'a>,
+pub struct AppCache<'a, C = Empty, Q = Empty>
+where
+    C: Clone + fmt::Debug + PartialEq + JsonSchema,
+{
+    router: &'a App<C, Q>,
+    wasm: WasmCache<'a, C>,
     bank: BankCache<'a>,
+    staking: StakingCache<'a>,
+    custom: CustomCache<'a, C, Q>,
 }
 
 pub struct AppOps {
     wasm: WasmOps,
     bank: BankOps,
+    staking: StakingOps,
+    custom: CustomOps,
 }
 
 impl AppOps {
-    pub fn commit(self, router: &mut App) {
+    pub fn commit<C, Q>(self, router: &mut App<C, Q>)
+    where
+        C: Clone + fmt::Debug + PartialEq + JsonSchema,
+    {
         self.bank.commit(&mut router.bank);
+        self.staking.commit(&mut router.staking);
+        self.custom.commit(&mut router.custom);
         self.wasm.commit(&mut router.wasm);
     }
 }
 
-impl<'a> AppCache<'a> {
-    fn new(router: &'a App) -> Self {
+impl<'a, C, Q> AppCache<'a, C, Q>
+where
+    C: Clone + fmt::Debug + PartialEq + JsonSchema + 'static,
+    Q: CustomQuery + DeserializeOwned + 'static,
+{
+    fn new(router: &'a App<C, Q>) -> Self {
         AppCache {
             router,
             wasm: router.wasm.cache(),
             bank: router.bank.cache(),
+            staking: router.staking.cache(),
+            custom: router.custom.cache(),
         }
     }
 
@@ -251,6 +502,8 @@ impl<'a> AppCache<'a> {
         AppOps {
             wasm: self.wasm.prepare(),
             bank: self.bank.prepare(),
+            staking: self.staking.prepare(),
+            custom: self.custom.prepare(),
         }
     }
 
@@ -260,37 +513,51 @@ impl<'a> AppCache<'a> {
     ///
     /// For normal use cases, you can use Router::execute() or Router::execute_multi().
     /// This is designed to be handled internally as part of larger process flows.
-    fn execute(&mut self, sender: HumanAddr, msg: CosmosMsg<Empty>) -> Result<AppResponse, String> {
+    fn execute(&mut self, sender: HumanAddr, msg: CosmosMsg<C>) -> Result<AppResponse, String> {
         match msg {
-            CosmosMsg::Wasm(msg) => {
-                let (resender, res) = self.handle_wasm(sender, msg)?;
-                let mut attributes = res.attributes;
-                // recurse in all messages
-                for resend in res.messages {
-                    let subres = self.execute(resender.clone(), resend)?;
-                    // ignore the data now, just like in wasmd
-                    // append the events
-                    attributes.extend_from_slice(&subres.attributes);
-                }
-                Ok(AppResponse {
-                    attributes,
-                    data: res.data,
-                })
-            }
+            CosmosMsg::Wasm(msg) => self.handle_wasm(sender, msg),
             CosmosMsg::Bank(msg) => {
                 self.bank.execute(sender, msg)?;
                 Ok(AppResponse::default())
             }
-            _ => unimplemented!(),
+            CosmosMsg::Staking(msg) => self.handle_staking(sender, msg),
+            CosmosMsg::Custom(msg) => {
+                let block = self.router.wasm.block_info();
+                self.custom.execute(&block, sender, msg)
+            }
         }
     }
 
-    // this returns the contract address as well, so we can properly resend the data
-    fn handle_wasm(
+    // executes all messages returned by the contract as coming from it,
+    // ty is the entry point that returned them
+    fn process_response(
         &mut self,
-        sender: HumanAddr,
-        msg: WasmMsg,
-    ) -> Result<(HumanAddr, ActionResponse), String> {
+        contract: HumanAddr,
+        ty: &str,
+        res: ActionResponse<C>,
+    ) -> Result<AppResponse, String> {
+        let mut attributes = res.attributes.clone();
+        let mut events = vec![Event {
+            ty: ty.to_string(),
+            contract: contract.clone(),
+            attributes: res.attributes,
+        }];
+        // recurse in all messages
+        for resend in res.messages {
+            let subres = self.execute(contract.clone(), resend)?;
+            // ignore the data now, just like in wasmd
+            // append the events
+            attributes.extend_from_slice(&subres.attributes);
+            events.extend(subres.events);
+        }
+        Ok(AppResponse {
+            attributes,
+            events,
+            data: res.data,
+        })
+    }
+
+    fn handle_wasm(&mut self, sender: HumanAddr, msg: WasmMsg) -> Result<AppResponse, String> {
         match msg {
             WasmMsg::Execute {
                 contract_addr,
@@ -307,33 +574,96 @@ impl<'a> AppCache<'a> {
                 let res =
                     self.wasm
                         .handle(contract_addr.clone(), self.router, info, msg.to_vec())?;
-                Ok((contract_addr, res.into()))
+                self.process_response(contract_addr, "execute", res.into())
             }
+            // WasmMsg has no admin field, so contracts instantiated by other contracts
+            // can never be migrated
             WasmMsg::Instantiate {
                 code_id,
                 msg,
                 send,
-                label: _,
-            } => {
-                let contract_addr = self.wasm.register_contract(code_id as usize)?;
-                // move the cash
-                self.send(&sender, &contract_addr, &send)?;
-                // then call the contract
-                let info = MessageInfo {
-                    sender,
-                    sent_funds: send,
-                };
-                let res = self
-                    .wasm
-                    .init(contract_addr.clone(), self.router, info, msg.to_vec())?;
-                Ok((
-                    contract_addr.clone(),
-                    ActionResponse::init(res, contract_addr),
-                ))
-            }
+                label,
+            } => self.instantiate(sender, code_id as usize, msg, send, label, None),
         }
     }
 
+    fn instantiate(
+        &mut self,
+        sender: HumanAddr,
+        code_id: usize,
+        msg: Binary,
+        send: Vec<Coin>,
+        label: Option<String>,
+        admin: Option<HumanAddr>,
+    ) -> Result<AppResponse, String> {
+        let contract_addr = self.wasm.register_contract(
+            code_id,
+            sender.clone(),
+            admin,
+            label.unwrap_or_default(),
+        )?;
+        // move the cash
+        self.send(&sender, &contract_addr, &send)?;
+        // then call the contract
+        let info = MessageInfo {
+            sender,
+            sent_funds: send,
+        };
+        let res = self
+            .wasm
+            .init(contract_addr.clone(), self.router, info, msg.to_vec())?;
+        let res = ActionResponse::init(res, contract_addr.clone());
+        self.process_response(contract_addr, "instantiate", res)
+    }
+
+    fn migrate(
+        &mut self,
+        sender: HumanAddr,
+        contract_addr: HumanAddr,
+        new_code_id: usize,
+        msg: Binary,
+    ) -> Result<AppResponse, String> {
+        let info = MessageInfo {
+            sender,
+            sent_funds: vec![],
+        };
+        let res = self.wasm.migrate(
+            contract_addr.clone(),
+            self.router,
+            info,
+            new_code_id,
+            msg.to_vec(),
+        )?;
+        self.process_response(contract_addr, "migrate", res.into())
+    }
+
+    fn handle_staking(
+        &mut self,
+        sender: HumanAddr,
+        msg: StakingMsg,
+    ) -> Result<AppResponse, String> {
+        let block = self.router.wasm.block_info();
+        let transfers = self.staking.execute(&block, sender, msg)?;
+        self.apply_transfers(transfers)?;
+        Ok(AppResponse::default())
+    }
+
+    // moves the tokens the staking module asked for
+    fn apply_transfers(&mut self, transfers: Vec<StakingTransfer>) -> Result<(), String> {
+        for transfer in transfers {
+            match transfer {
+                StakingTransfer::Bond { from, amount } => {
+                    self.send(from, BONDED_POOL, &[amount])?;
+                }
+                StakingTransfer::Unbond { to, amount } => {
+                    self.send(BONDED_POOL, to, &[amount])?;
+                }
+                StakingTransfer::Mint { to, amount } => self.bank.mint(to, vec![amount])?,
+            };
+        }
+        Ok(())
+    }
+
     fn send<T: Into<HumanAddr>, U: Into<HumanAddr>>(
         &mut self,
         sender: T,
@@ -367,18 +697,23 @@ pub fn parse_contract_addr(data: &Option<Binary>) -> Result<HumanAddr, String> {
 mod test {
     use super::*;
     use crate::test_helpers::{
-        contract_payout, contract_reflect, EmptyMsg, PayoutMessage, ReflectMessage, ReflectResponse,
+        contract_custom, contract_metadata, contract_payout, contract_payout_custom,
+        contract_reflect, EmptyMsg, NameQuery, PayoutMessage, ReflectMessage, ReflectResponse,
+        SetNameMsg,
     };
-    use crate::SimpleBank;
+    use crate::{SimpleBank, SimpleStaking};
     use cosmwasm_std::testing::MockStorage;
-    use cosmwasm_std::{attr, coin, coins};
+    use cosmwasm_std::Storage;
+    use cosmwasm_std::{attr, coin, coins, AllDelegationsResponse, Decimal, StakingQuery};
 
     fn mock_router() -> App {
         let env = mock_env();
         let api = Box::new(MockApi::default());
         let bank = SimpleBank {};
 
-        App::new(api, env.block, bank, || Box::new(MockStorage::new()))
+        App::new(api, env.block, bank, SimpleStaking::default(), || {
+            Box::new(MockStorage::new())
+        })
     }
 
     fn get_balance(router: &App, addr: &HumanAddr) -> Vec<Coin> {
@@ -449,7 +784,7 @@ mod test {
             payout: coin(5, "eth"),
         };
         let contract_addr = router
-            .instantiate_contract(code_id, &owner, &msg, &coins(23, "eth"), "Payout")
+            .instantiate_contract(code_id, &owner, &msg, &coins(23, "eth"), "Payout", None)
             .unwrap();
 
         // sender funds deducted
@@ -496,13 +831,13 @@ mod test {
             payout: coin(5, "eth"),
         };
         let payout_addr = router
-            .instantiate_contract(payout_id, &owner, &msg, &coins(23, "eth"), "Payout")
+            .instantiate_contract(payout_id, &owner, &msg, &coins(23, "eth"), "Payout", None)
             .unwrap();
 
         // set up reflect contract
         let reflect_id = router.store_code(contract_reflect());
         let reflect_addr = router
-            .instantiate_contract(reflect_id, &owner, &EmptyMsg {}, &[], "Reflect")
+            .instantiate_contract(reflect_id, &owner, &EmptyMsg {}, &[], "Reflect", None)
             .unwrap();
 
         // reflect account is empty
@@ -533,6 +868,25 @@ mod test {
         assert_eq!(1, res.attributes.len());
         assert_eq!(&attr("action", "payout"), &res.attributes[0]);
 
+        // and the events tell which contract emitted them
+        assert_eq!(
+            res.events,
+            vec![
+                Event::new("execute", &reflect_addr),
+                Event {
+                    ty: "execute".to_string(),
+                    contract: payout_addr.clone(),
+                    attributes: vec![attr("action", "payout")],
+                },
+            ]
+        );
+        res.assert_event(&payout_addr, "action", "payout");
+        assert!(!res.has_event(&reflect_addr, "action", "payout"));
+        assert_eq!(
+            res.events_from(&payout_addr)[0].attr("action"),
+            Some("payout")
+        );
+
         // ensure transfer was executed with reflect as sender
         let funds = get_balance(&router, &reflect_addr);
         assert_eq!(funds, coins(5, "eth"));
@@ -565,6 +919,7 @@ mod test {
                 &EmptyMsg {},
                 &coins(40, "eth"),
                 "Reflect",
+                None,
             )
             .unwrap();
 
@@ -630,4 +985,397 @@ mod test {
             .unwrap();
         assert_eq!(2, qres.count);
     }
+
+    #[test]
+    fn stake_and_unbond() {
+        let mut router = mock_router();
+        let delegator = HumanAddr::from("delegator");
+        router
+            .set_bank_balance(delegator.clone(), coins(1000, "stake"))
+            .unwrap();
+        router
+            .add_validator(Validator {
+                address: "validator".into(),
+                commission: Decimal::zero(),
+                max_commission: Decimal::percent(10),
+                max_change_rate: Decimal::percent(1),
+            })
+            .unwrap();
+
+        let msg = CosmosMsg::Staking(StakingMsg::Delegate {
+            validator: "validator".into(),
+            amount: coin(600, "stake"),
+        });
+        router.execute(delegator.clone(), msg).unwrap();
+        assert_eq!(get_balance(&router, &delegator), coins(400, "stake"));
+        assert_eq!(
+            get_balance(&router, &BONDED_POOL.into()),
+            coins(600, "stake")
+        );
+
+        // a year later we get 10% rewards
+        let unbonding_time = SimpleStaking::default().unbonding_time;
+        router.update_block(|b| b.time += 60 * 60 * 24 * 365);
+        let msg = CosmosMsg::Staking(StakingMsg::Withdraw {
+            validator: "validator".into(),
+            recipient: None,
+        });
+        router.execute(delegator.clone(), msg).unwrap();
+        assert_eq!(get_balance(&router, &delegator), coins(460, "stake"));
+
+        // undelegated tokens are only returned after the unbonding time
+        let msg = CosmosMsg::Staking(StakingMsg::Undelegate {
+            validator: "validator".into(),
+            amount: coin(600, "stake"),
+        });
+        router.execute(delegator.clone(), msg).unwrap();
+        let query = QueryRequest::Staking(StakingQuery::AllDelegations {
+            delegator: delegator.clone(),
+        });
+        let res: AllDelegationsResponse = from_slice(&router.query(query).unwrap()).unwrap();
+        assert_eq!(res.delegations, vec![]);
+        assert_eq!(get_balance(&router, &delegator), coins(460, "stake"));
+
+        router.update_block(|b| b.time += unbonding_time);
+        assert_eq!(get_balance(&router, &delegator), coins(460, "stake"));
+        router.end_block().unwrap();
+        assert_eq!(get_balance(&router, &delegator), coins(1060, "stake"));
+        assert_eq!(get_balance(&router, &BONDED_POOL.into()), vec![]);
+    }
+
+    #[test]
+    fn migrate_contract() {
+        let mut router = mock_router();
+        let owner = HumanAddr::from("owner");
+        let random = HumanAddr::from("random");
+
+        let payout_id = router.store_code(contract_payout());
+        let reflect_id = router.store_code(contract_reflect());
+        let msg = PayoutMessage {
+            payout: coin(5, "eth"),
+        };
+        let contract_addr = router
+            .instantiate_contract(payout_id, &owner, &msg, &[], "Payout", Some(owner.clone()))
+            .unwrap();
+        let no_admin_addr = router
+            .instantiate_contract(payout_id, &owner, &msg, &[], "Payout", None)
+            .unwrap();
+        assert_eq!(
+            router.contract_data(&contract_addr).unwrap(),
+            ContractData {
+                code_id: payout_id,
+                creator: owner.clone(),
+                admin: Some(owner.clone()),
+                label: "Payout".to_string(),
+            }
+        );
+        assert_eq!(router.contract_data(&no_admin_addr).unwrap().admin, None);
+        router.contract_data(&random).unwrap_err();
+
+        let new_msg = PayoutMessage {
+            payout: coin(7, "eth"),
+        };
+        // only the admin can migrate
+        let err = router
+            .migrate_contract(&random, &contract_addr, payout_id, &new_msg)
+            .unwrap_err();
+        assert_eq!(err, "Only admin can migrate contract");
+        let err = router
+            .migrate_contract(&owner, &no_admin_addr, payout_id, &new_msg)
+            .unwrap_err();
+        assert_eq!(err, "Only admin can migrate contract");
+
+        // the new code must exist and support migration
+        router
+            .migrate_contract(&owner, &contract_addr, reflect_id + 1, &new_msg)
+            .unwrap_err();
+        let err = router
+            .migrate_contract(&owner, &contract_addr, reflect_id, &EmptyMsg {})
+            .unwrap_err();
+        assert_eq!(err, "Contract does not support migration");
+
+        // failed migrations are rolled back, this is still the payout code
+        let payout: PayoutMessage = router
+            .wrap()
+            .query_wasm_smart(&contract_addr, &EmptyMsg {})
+            .unwrap();
+        assert_eq!(payout.payout, coin(5, "eth"));
+        let data = router.contract_data(&contract_addr).unwrap();
+        assert_eq!(data.code_id, payout_id);
+
+        // migrate to newly uploaded code
+        let new_id = router.store_code_with_creator(owner.clone(), contract_payout());
+        let res = router
+            .migrate_contract(&owner, &contract_addr, new_id, &new_msg)
+            .unwrap();
+        assert_eq!(res.attributes, vec![attr("action", "migrate")]);
+        let payout: PayoutMessage = router
+            .wrap()
+            .query_wasm_smart(&contract_addr, &EmptyMsg {})
+            .unwrap();
+        assert_eq!(payout.payout, coin(7, "eth"));
+        let data = router.contract_data(&contract_addr).unwrap();
+        assert_eq!(data.code_id, new_id);
+        assert_eq!(data.creator, owner);
+    }
+
+    #[test]
+    fn code_data() {
+        let mut router = mock_router();
+        let owner = HumanAddr::from("owner");
+
+        let payout_id = router.store_code(contract_payout());
+        let reflect_id = router.store_code_with_creator(owner.clone(), contract_reflect());
+
+        let payout = router.code_data(payout_id).unwrap();
+        assert_eq!(payout.creator, HumanAddr::from(CODE_CREATOR));
+        assert_eq!(payout.checksum.len(), 64);
+        let reflect = router.code_data(reflect_id).unwrap();
+        assert_eq!(reflect.creator, owner);
+        assert_ne!(payout.checksum, reflect.checksum);
+
+        router.code_data(reflect_id + 1).unwrap_err();
+    }
+
+    #[test]
+    fn contracts_query_metadata() {
+        let env = mock_env();
+        let api = Box::new(MockApi::default());
+        let mut router: App<Empty, MetadataQuery> = App::new_custom(
+            api,
+            env.block,
+            SimpleBank {},
+            SimpleStaking::default(),
+            FailingCustomHandler::default(),
+            || Box::new(MockStorage::new()),
+        )
+        .with_metadata_queries(|q| Some(q.clone()));
+
+        let owner = HumanAddr::from("owner");
+        let code_id = router.store_code(contract_metadata());
+        let contract_addr = router
+            .instantiate_contract(
+                code_id,
+                &owner,
+                &EmptyMsg {},
+                &[],
+                "Metadata",
+                Some(owner.clone()),
+            )
+            .unwrap();
+
+        // the contract reads its own metadata through the App
+        let query = MetadataQuery::ContractInfo {
+            contract_addr: contract_addr.clone(),
+        };
+        let data: ContractData = router
+            .wrap()
+            .query_wasm_smart(&contract_addr, &query)
+            .unwrap();
+        assert_eq!(data, router.contract_data(&contract_addr).unwrap());
+        assert_eq!(data.admin, Some(owner));
+
+        let query = MetadataQuery::CodeInfo { code_id };
+        let data: CodeData = router
+            .wrap()
+            .query_wasm_smart(&contract_addr, &query)
+            .unwrap();
+        assert_eq!(data, router.code_data(code_id).unwrap());
+
+        // unknown contracts are an error
+        let query = MetadataQuery::ContractInfo {
+            contract_addr: "random".into(),
+        };
+        router
+            .wrap()
+            .query_wasm_smart::<ContractData, _, _>(&contract_addr, &query)
+            .unwrap_err();
+
+        // without with_metadata_queries, the query goes to the custom handler
+        let mut router: App<Empty, MetadataQuery> = App::new_custom(
+            Box::new(MockApi::default()),
+            mock_env().block,
+            SimpleBank {},
+            SimpleStaking::default(),
+            FailingCustomHandler::default(),
+            || Box::new(MockStorage::new()),
+        );
+        let code_id = router.store_code(contract_metadata());
+        let contract_addr = router
+            .instantiate_contract(code_id, "owner", &EmptyMsg {}, &[], "Metadata", None)
+            .unwrap();
+        let query = MetadataQuery::CodeInfo { code_id };
+        let err = router
+            .wrap()
+            .query_wasm_smart::<CodeData, _, _>(&contract_addr, &query)
+            .unwrap_err();
+        assert!(err.to_string().contains("Unexpected custom query"));
+    }
+
+    // stores the names set by SetNameMsg under the sender address
+    struct NameHandler {}
+
+    impl CustomHandler<SetNameMsg, NameQuery> for NameHandler {
+        fn handle(
+            &self,
+            storage: &mut dyn Storage,
+            _block: &BlockInfo,
+            sender: HumanAddr,
+            msg: SetNameMsg,
+        ) -> Result<AppResponse, String> {
+            if msg.name.is_empty() {
+                return Err("Empty name".to_string());
+            }
+            storage.set(sender.as_bytes(), msg.name.as_bytes());
+            Ok(AppResponse::default())
+        }
+
+        fn query(
+            &self,
+            storage: &dyn Storage,
+            _block: &BlockInfo,
+            request: NameQuery,
+        ) -> Result<Binary, String> {
+            let name = storage
+                .get(request.address.as_bytes())
+                .map(|v| String::from_utf8(v).unwrap())
+                .unwrap_or_default();
+            to_binary(&name).map_err(|e| e.to_string())
+        }
+    }
+
+    #[test]
+    fn custom_messages_and_queries() {
+        let env = mock_env();
+        let api = Box::new(MockApi::default());
+        let mut router: App<SetNameMsg, NameQuery> = App::new_custom(
+            api,
+            env.block,
+            SimpleBank {},
+            SimpleStaking::default(),
+            NameHandler {},
+            || Box::new(MockStorage::new()),
+        );
+
+        let owner = HumanAddr::from("owner");
+        let code_id = router.store_code(contract_custom());
+        let contract_addr = router
+            .instantiate_contract(code_id, &owner, &EmptyMsg {}, &[], "Custom", None)
+            .unwrap();
+
+        // the contract sends the custom message, which is processed by the handler
+        let msg = SetNameMsg {
+            name: "alice".to_string(),
+        };
+        let res = router
+            .execute_contract(&owner, &contract_addr, &msg, &[])
+            .unwrap();
+        assert_eq!(res.attributes, vec![attr("action", "set_name")]);
+
+        // the contract can query the chain
+        let query = NameQuery {
+            address: contract_addr.to_string(),
+        };
+        let name: String = router
+            .wrap()
+            .query_wasm_smart(&contract_addr, &query)
+            .unwrap();
+        assert_eq!(name, "alice");
+
+        // errors in the handler roll back the whole transaction
+        let msg = SetNameMsg {
+            name: "".to_string(),
+        };
+        let err = router
+            .execute_contract(&owner, &contract_addr, &msg, &[])
+            .unwrap_err();
+        assert_eq!(err, "Empty name");
+
+        // an App without custom handler rejects custom messages
+        let mut router = mock_router();
+        let err = router
+            .execute(owner.clone(), CosmosMsg::Custom(Empty {}))
+            .unwrap_err();
+        assert_eq!(err, "Unexpected custom message");
+    }
+
+    #[test]
+    fn empty_contract_in_custom_app() {
+        let env = mock_env();
+        let api = Box::new(MockApi::default());
+        let mut router: App<SetNameMsg, NameQuery> = App::new_custom(
+            api,
+            env.block,
+            SimpleBank {},
+            SimpleStaking::default(),
+            NameHandler {},
+            || Box::new(MockStorage::new()),
+        );
+        let owner = HumanAddr::from("owner");
+        router
+            .set_bank_balance(owner.clone(), coins(100, "eth"))
+            .unwrap();
+
+        // both contracts live in the same App
+        let custom_id = router.store_code(contract_custom());
+        let custom_addr = router
+            .instantiate_contract(custom_id, &owner, &EmptyMsg {}, &[], "Custom", None)
+            .unwrap();
+        let payout_id = router.store_code(contract_payout_custom());
+        let msg = PayoutMessage {
+            payout: coin(5, "eth"),
+        };
+        let payout_addr = router
+            .instantiate_contract(
+                payout_id,
+                &owner,
+                &msg,
+                &coins(23, "eth"),
+                "Payout",
+                Some(owner.clone()),
+            )
+            .unwrap();
+
+        // the bank message of the Empty contract is executed
+        let random = HumanAddr::from("random");
+        let res = router
+            .execute_contract(&random, &payout_addr, &EmptyMsg {}, &[])
+            .unwrap();
+        assert_eq!(res.attributes, vec![attr("action", "payout")]);
+        assert_eq!(
+            router.wrap().query_all_balances(&random).unwrap(),
+            coins(5, "eth")
+        );
+
+        // and so is the custom message of the other one
+        let msg = SetNameMsg {
+            name: "bob".to_string(),
+        };
+        router
+            .execute_contract(&owner, &custom_addr, &msg, &[])
+            .unwrap();
+        let query = NameQuery {
+            address: custom_addr.to_string(),
+        };
+        let name: String = router
+            .wrap()
+            .query_wasm_smart(&custom_addr, &query)
+            .unwrap();
+        assert_eq!(name, "bob");
+
+        // the Empty contract can be migrated too
+        let msg = PayoutMessage {
+            payout: coin(7, "eth"),
+        };
+        router
+            .migrate_contract(&owner, &payout_addr, payout_id, &msg)
+            .unwrap();
+        router
+            .execute_contract(&random, &payout_addr, &EmptyMsg {}, &[])
+            .unwrap();
+        assert_eq!(
+            router.wrap().query_all_balances(&random).unwrap(),
+            coins(12, "eth")
+        );
+    }
 }
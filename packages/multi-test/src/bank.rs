@@ -89,6 +89,19 @@ impl<'a> BankCache<'a> {
     pub fn execute(&mut self, sender: HumanAddr, msg: BankMsg) -> Result<(), String> {
         self.router.bank.handle(&mut self.state, sender, msg)
     }
+
+    /// Creates new tokens for the recipient, eg. to pay out staking rewards
+    pub fn mint(&mut self, recipient: HumanAddr, amount: Vec<Coin>) -> Result<(), String> {
+        let query = BankQuery::AllBalances {
+            address: recipient.clone(),
+        };
+        let raw = self.router.bank.query(&self.state, query)?;
+        let balance: AllBalanceResponse = from_slice(&raw).map_err(|e| e.to_string())?;
+        let total = NativeBalance(balance.amount) + NativeBalance(amount);
+        self.router
+            .bank
+            .set_balance(&mut self.state, recipient, total.into_vec())
+    }
 }
 
 #[derive(Default)]
@@ -0,0 +1,115 @@
+use std::marker::PhantomData;
+
+use cosmwasm_std::{Binary, BlockInfo, Empty, HumanAddr, Storage};
+
+use crate::app::AppResponse;
+use crate::transactions::{RepLog, StorageTransaction};
+
+/// CustomHandler processes the chain specific messages (`CosmosMsg::Custom`) and queries
+/// (`QueryRequest::Custom`) of an `App<C, Q>`, just like `Bank` does for the bank module.
+/// It gets its own storage, which is committed or rolled back along with the rest of the App.
+pub trait CustomHandler<C = Empty, Q = Empty> {
+    fn handle(
+        &self,
+        storage: &mut dyn Storage,
+        block: &BlockInfo,
+        sender: HumanAddr,
+        msg: C,
+    ) -> Result<AppResponse, String>;
+
+    fn query(&self, storage: &dyn Storage, block: &BlockInfo, request: Q)
+        -> Result<Binary, String>;
+}
+
+/// FailingCustomHandler returns an error on every custom message and query.
+/// This is used for an App without custom messages.
+pub struct FailingCustomHandler<C = Empty, Q = Empty>(PhantomData<(C, Q)>);
+
+impl<C, Q> Default for FailingCustomHandler<C, Q> {
+    fn default() -> Self {
+        FailingCustomHandler(PhantomData)
+    }
+}
+
+impl<C, Q> CustomHandler<C, Q> for FailingCustomHandler<C, Q> {
+    fn handle(
+        &self,
+        _storage: &mut dyn Storage,
+        _block: &BlockInfo,
+        _sender: HumanAddr,
+        _msg: C,
+    ) -> Result<AppResponse, String> {
+        Err("Unexpected custom message".to_string())
+    }
+
+    fn query(
+        &self,
+        _storage: &dyn Storage,
+        _block: &BlockInfo,
+        _request: Q,
+    ) -> Result<Binary, String> {
+        Err("Unexpected custom query".to_string())
+    }
+}
+
+pub struct CustomRouter<C = Empty, Q = Empty> {
+    handler: Box<dyn CustomHandler<C, Q>>,
+    storage: Box<dyn Storage>,
+}
+
+impl<C, Q> CustomRouter<C, Q> {
+    pub fn new<H: CustomHandler<C, Q> + 'static>(handler: H, storage: Box<dyn Storage>) -> Self {
+        CustomRouter {
+            handler: Box::new(handler),
+            storage,
+        }
+    }
+
+    pub fn cache(&'_ self) -> CustomCache<'_, C, Q> {
+        CustomCache::new(self)
+    }
+
+    pub fn query(&self, block: &BlockInfo, request: Q) -> Result<Binary, String> {
+        self.handler.query(self.storage.as_ref(), block, request)
+    }
+}
+
+pub struct CustomCache<'a, C = Empty, Q = Empty> {
+    router: &'a CustomRouter<C, Q>,
+    state: StorageTransaction<'a>,
+}
+
+pub struct CustomOps(RepLog);
+
+impl CustomOps {
+    pub fn commit<C, Q>(self, router: &mut CustomRouter<C, Q>) {
+        self.0.commit(router.storage.as_mut())
+    }
+}
+
+impl<'a, C, Q> CustomCache<'a, C, Q> {
+    fn new(router: &'a CustomRouter<C, Q>) -> Self {
+        CustomCache {
+            router,
+            state: StorageTransaction::new(router.storage.as_ref()),
+        }
+    }
+
+    /// When we want to commit the CustomCache, we need a 2 step process to satisfy Rust reference counting:
+    /// 1. prepare() consumes CustomCache, releasing &CustomRouter, and creating a self-owned update info.
+    /// 2. CustomOps::commit() can now take &mut CustomRouter and updates the underlying state
+    pub fn prepare(self) -> CustomOps {
+        CustomOps(self.state.prepare())
+    }
+
+    pub fn execute(
+        &mut self,
+        block: &BlockInfo,
+        sender: HumanAddr,
+        msg: C,
+    ) -> Result<AppResponse, String> {
+        self.router
+            .handler
+            .handle(&mut self.state, block, sender, msg)
+    }
+}
@@ -1,9 +1,22 @@
 mod app;
 mod bank;
+mod custom_handler;
+mod staking;
 mod test_helpers;
 mod transactions;
 mod wasm;
 
-pub use crate::app::{parse_contract_addr, App, AppCache, AppOps};
+pub use crate::app::{
+    parse_contract_addr, App, AppCache, AppOps, AppResponse, Event, CODE_CREATOR,
+};
 pub use crate::bank::{Bank, BankCache, BankOps, SimpleBank};
-pub use crate::wasm::{next_block, Contract, ContractWrapper, WasmCache, WasmOps, WasmRouter};
+pub use crate::custom_handler::{
+    CustomCache, CustomHandler, CustomOps, CustomRouter, FailingCustomHandler,
+};
+pub use crate::staking::{
+    SimpleStaking, Staking, StakingCache, StakingOps, StakingRouter, StakingTransfer, BONDED_POOL,
+};
+pub use crate::wasm::{
+    next_block, CodeData, Contract, ContractData, ContractWrapper, MetadataQuery, WasmCache,
+    WasmOps, WasmRouter,
+};
@@ -0,0 +1,798 @@
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    coin, to_binary, AllDelegationsResponse, Binary, BlockInfo, BondedDenomResponse, Coin, Decimal,
+    Delegation, FullDelegation, HumanAddr, Order, StakingMsg, StakingQuery, StdResult, Storage,
+    Uint128, Validator, ValidatorsResponse,
+};
+use cw_storage_plus::{Bound, Map, U64Key};
+
+use crate::transactions::{RepLog, StorageTransaction};
+
+/// All bonded and unbonding tokens are held by this account in the bank
+pub const BONDED_POOL: &str = "bonded_pool";
+
+const YEAR: u64 = 60 * 60 * 24 * 365;
+
+/// A movement of tokens the staking module needs the bank to perform
+#[derive(Clone, Debug, PartialEq)]
+pub enum StakingTransfer {
+    /// Move tokens from the account into the BONDED_POOL
+    Bond { from: HumanAddr, amount: Coin },
+    /// Move tokens from the BONDED_POOL back to the account
+    Unbond { to: HumanAddr, amount: Coin },
+    /// Create new tokens for the account, this is how rewards are paid
+    Mint { to: HumanAddr, amount: Coin },
+}
+
+/// Staking is a minimal contract-like interface that implements the staking and distribution
+/// modules. It is initialized outside of the trait
+pub trait Staking {
+    /// Processes a message from sender and returns the token transfers needed to settle it
+    fn handle(
+        &self,
+        storage: &mut dyn Storage,
+        block: &BlockInfo,
+        sender: HumanAddr,
+        msg: StakingMsg,
+    ) -> Result<Vec<StakingTransfer>, String>;
+
+    fn query(
+        &self,
+        storage: &dyn Storage,
+        block: &BlockInfo,
+        request: StakingQuery,
+    ) -> Result<Binary, String>;
+
+    /// Called whenever the block changes, returns the unbonding tokens that are now released
+    fn end_block(
+        &self,
+        storage: &mut dyn Storage,
+        block: &BlockInfo,
+    ) -> Result<Vec<StakingTransfer>, String>;
+
+    // this is an "admin" function to let us set up validators
+    fn add_validator(&self, storage: &mut dyn Storage, validator: Validator) -> Result<(), String>;
+
+    fn clone(&self) -> Box<dyn Staking>;
+}
+
+pub struct StakingRouter {
+    staking: Box<dyn Staking>,
+    storage: Box<dyn Storage>,
+}
+
+impl StakingRouter {
+    pub fn new<S: Staking + 'static>(staking: S, storage: Box<dyn Storage>) -> Self {
+        StakingRouter {
+            staking: Box::new(staking),
+            storage,
+        }
+    }
+
+    // this is an "admin" function to let us set up validators
+    pub fn add_validator(&mut self, validator: Validator) -> Result<(), String> {
+        self.staking.add_validator(self.storage.as_mut(), validator)
+    }
+
+    pub fn cache(&'_ self) -> StakingCache<'_> {
+        StakingCache::new(self)
+    }
+
+    pub fn query(&self, block: &BlockInfo, request: StakingQuery) -> Result<Binary, String> {
+        self.staking.query(self.storage.as_ref(), block, request)
+    }
+}
+
+pub struct StakingCache<'a> {
+    router: &'a StakingRouter,
+    state: StorageTransaction<'a>,
+}
+
+pub struct StakingOps(RepLog);
+
+impl StakingOps {
+    pub fn commit(self, router: &mut StakingRouter) {
+        self.0.commit(router.storage.as_mut())
+    }
+}
+
+impl<'a> StakingCache<'a> {
+    fn new(router: &'a StakingRouter) -> Self {
+        StakingCache {
+            router,
+            state: StorageTransaction::new(router.storage.as_ref()),
+        }
+    }
+
+    /// When we want to commit the StakingCache, we need a 2 step process to satisfy Rust reference counting:
+    /// 1. prepare() consumes StakingCache, releasing &StakingRouter, and creating a self-owned update info.
+    /// 2. StakingOps::commit() can now take &mut StakingRouter and updates the underlying state
+    pub fn prepare(self) -> StakingOps {
+        StakingOps(self.state.prepare())
+    }
+
+    pub fn execute(
+        &mut self,
+        block: &BlockInfo,
+        sender: HumanAddr,
+        msg: StakingMsg,
+    ) -> Result<Vec<StakingTransfer>, String> {
+        self.router
+            .staking
+            .handle(&mut self.state, block, sender, msg)
+    }
+
+    pub fn end_block(&mut self, block: &BlockInfo) -> Result<Vec<StakingTransfer>, String> {
+        self.router.staking.end_block(&mut self.state, block)
+    }
+}
+
+/// Same layout as the cosmwasm_std response to StakingQuery::Delegation, which is not exported
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct DelegationResponse {
+    pub delegation: Option<FullDelegation>,
+}
+
+/// One delegation, rewards are accrued up to last_update and paid out whenever the amount changes
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+struct Stake {
+    amount: Uint128,
+    rewards: Uint128,
+    last_update: u64,
+}
+
+const VALIDATORS: Map<&[u8], Validator> = Map::new("validators");
+// (delegator, validator) -> stake
+const DELEGATIONS: Map<(&[u8], &[u8]), Stake> = Map::new("delegations");
+// (release time, delegator) -> amount
+const UNBONDING: Map<(U64Key, &[u8]), Uint128> = Map::new("unbonding");
+
+/// SimpleStaking pays every delegation a fixed yearly rate `apr`, minus the commission of
+/// the validator. Undelegated tokens are released `unbonding_time` seconds later.
+/// Redelegation is immediate and there is no slashing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimpleStaking {
+    pub bonded_denom: String,
+    /// in seconds
+    pub unbonding_time: u64,
+    pub apr: Decimal,
+}
+
+impl Default for SimpleStaking {
+    fn default() -> Self {
+        SimpleStaking {
+            bonded_denom: "stake".to_string(),
+            // 21 days, like the Cosmos Hub
+            unbonding_time: 60 * 60 * 24 * 21,
+            apr: Decimal::percent(10),
+        }
+    }
+}
+
+impl SimpleStaking {
+    fn validator(&self, storage: &dyn Storage, address: &HumanAddr) -> Result<Validator, String> {
+        VALIDATORS
+            .may_load(storage, address.as_bytes())
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Validator {} not found", address))
+    }
+
+    fn check_denom(&self, amount: &Coin) -> Result<(), String> {
+        if amount.denom != self.bonded_denom {
+            Err(format!(
+                "Can only stake {}, not {}",
+                self.bonded_denom, amount.denom
+            ))
+        } else if amount.amount.is_zero() {
+            Err("Amount must be greater than zero".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Loads the delegation with all rewards accrued up to the block time
+    fn load_stake(
+        &self,
+        storage: &dyn Storage,
+        block: &BlockInfo,
+        delegator: &HumanAddr,
+        validator: &Validator,
+    ) -> Result<Option<Stake>, String> {
+        let stake = DELEGATIONS
+            .may_load(
+                storage,
+                (delegator.as_bytes(), validator.address.as_bytes()),
+            )
+            .map_err(|e| e.to_string())?;
+        stake
+            .map(|mut s| {
+                let elapsed = block.time.saturating_sub(s.last_update);
+                let reward = s.amount.multiply_ratio(elapsed, YEAR) * self.apr;
+                let commission = reward * validator.commission;
+                s.rewards += (reward - commission).map_err(|e| e.to_string())?;
+                s.last_update = block.time;
+                Ok(s)
+            })
+            .transpose()
+    }
+
+    /// Saves the delegation, removing it when there is nothing delegated anymore
+    fn save_stake(
+        &self,
+        storage: &mut dyn Storage,
+        delegator: &HumanAddr,
+        validator: &HumanAddr,
+        stake: &Stake,
+    ) -> Result<(), String> {
+        let key = (delegator.as_bytes(), validator.as_bytes());
+        if stake.amount.is_zero() {
+            DELEGATIONS.remove(storage, key);
+            Ok(())
+        } else {
+            DELEGATIONS
+                .save(storage, key, stake)
+                .map_err(|e| e.to_string())
+        }
+    }
+
+    /// Adds to the delegation and returns the rewards accrued so far, which must be paid out
+    fn add_stake(
+        &self,
+        storage: &mut dyn Storage,
+        block: &BlockInfo,
+        delegator: &HumanAddr,
+        validator: &HumanAddr,
+        amount: Uint128,
+    ) -> Result<Uint128, String> {
+        let validator = self.validator(storage, validator)?;
+        let mut stake = self
+            .load_stake(storage, block, delegator, &validator)?
+            .unwrap_or(Stake {
+                last_update: block.time,
+                ..Stake::default()
+            });
+        stake.amount += amount;
+        let rewards = std::mem::take(&mut stake.rewards);
+        self.save_stake(storage, delegator, &validator.address, &stake)?;
+        Ok(rewards)
+    }
+
+    /// Removes from the delegation and returns the rewards accrued so far, which must be paid out
+    fn remove_stake(
+        &self,
+        storage: &mut dyn Storage,
+        block: &BlockInfo,
+        delegator: &HumanAddr,
+        validator: &HumanAddr,
+        amount: Uint128,
+    ) -> Result<Uint128, String> {
+        let validator = self.validator(storage, validator)?;
+        let mut stake = self
+            .load_stake(storage, block, delegator, &validator)?
+            .ok_or_else(|| format!("No delegation to {}", validator.address))?;
+        stake.amount = (stake.amount - amount).map_err(|e| e.to_string())?;
+        let rewards = std::mem::take(&mut stake.rewards);
+        self.save_stake(storage, delegator, &validator.address, &stake)?;
+        Ok(rewards)
+    }
+
+    /// Mints the withdrawn rewards to the account, if there are any
+    fn pay_rewards(&self, to: HumanAddr, rewards: Uint128) -> Option<StakingTransfer> {
+        if rewards.is_zero() {
+            None
+        } else {
+            Some(StakingTransfer::Mint {
+                to,
+                amount: coin(rewards.u128(), &self.bonded_denom),
+            })
+        }
+    }
+
+    fn full_delegation(
+        &self,
+        storage: &dyn Storage,
+        block: &BlockInfo,
+        delegator: HumanAddr,
+        validator: HumanAddr,
+    ) -> Result<Option<FullDelegation>, String> {
+        let validator = match VALIDATORS
+            .may_load(storage, validator.as_bytes())
+            .map_err(|e| e.to_string())?
+        {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        let stake = self.load_stake(storage, block, &delegator, &validator)?;
+        Ok(stake.map(|s| {
+            let amount = coin(s.amount.u128(), &self.bonded_denom);
+            let accumulated_rewards = if s.rewards.is_zero() {
+                vec![]
+            } else {
+                vec![coin(s.rewards.u128(), &self.bonded_denom)]
+            };
+            FullDelegation {
+                delegator,
+                validator: validator.address,
+                can_redelegate: amount.clone(),
+                amount,
+                accumulated_rewards,
+            }
+        }))
+    }
+}
+
+impl Staking for SimpleStaking {
+    fn handle(
+        &self,
+        storage: &mut dyn Storage,
+        block: &BlockInfo,
+        sender: HumanAddr,
+        msg: StakingMsg,
+    ) -> Result<Vec<StakingTransfer>, String> {
+        match msg {
+            StakingMsg::Delegate { validator, amount } => {
+                self.check_denom(&amount)?;
+                let rewards = self.add_stake(storage, block, &sender, &validator, amount.amount)?;
+                let mut transfers: Vec<_> = self
+                    .pay_rewards(sender.clone(), rewards)
+                    .into_iter()
+                    .collect();
+                transfers.push(StakingTransfer::Bond {
+                    from: sender,
+                    amount,
+                });
+                Ok(transfers)
+            }
+            StakingMsg::Undelegate { validator, amount } => {
+                self.check_denom(&amount)?;
+                let rewards =
+                    self.remove_stake(storage, block, &sender, &validator, amount.amount)?;
+                // the tokens stay in the pool until they are released in end_block
+                let release = U64Key::from(block.time + self.unbonding_time);
+                UNBONDING
+                    .update(
+                        storage,
+                        (release, sender.as_bytes()),
+                        |old| -> StdResult<_> { Ok(old.unwrap_or_default() + amount.amount) },
+                    )
+                    .map_err(|e| e.to_string())?;
+                Ok(self.pay_rewards(sender, rewards).into_iter().collect())
+            }
+            StakingMsg::Redelegate {
+                src_validator,
+                dst_validator,
+                amount,
+            } => {
+                self.check_denom(&amount)?;
+                let mut rewards =
+                    self.remove_stake(storage, block, &sender, &src_validator, amount.amount)?;
+                rewards +=
+                    self.add_stake(storage, block, &sender, &dst_validator, amount.amount)?;
+                Ok(self.pay_rewards(sender, rewards).into_iter().collect())
+            }
+            StakingMsg::Withdraw {
+                validator,
+                recipient,
+            } => {
+                let validator = self.validator(storage, &validator)?;
+                let mut stake = self
+                    .load_stake(storage, block, &sender, &validator)?
+                    .ok_or_else(|| format!("No delegation to {}", validator.address))?;
+                let rewards = std::mem::take(&mut stake.rewards);
+                self.save_stake(storage, &sender, &validator.address, &stake)?;
+                Ok(self
+                    .pay_rewards(recipient.unwrap_or(sender), rewards)
+                    .into_iter()
+                    .collect())
+            }
+        }
+    }
+
+    fn query(
+        &self,
+        storage: &dyn Storage,
+        block: &BlockInfo,
+        request: StakingQuery,
+    ) -> Result<Binary, String> {
+        let res = match request {
+            StakingQuery::BondedDenom {} => to_binary(&BondedDenomResponse {
+                denom: self.bonded_denom.clone(),
+            }),
+            StakingQuery::AllDelegations { delegator } => {
+                let stakes: StdResult<Vec<_>> = DELEGATIONS
+                    .prefix(delegator.as_bytes())
+                    .range(storage, None, None, Order::Ascending)
+                    .collect();
+                let delegations = stakes
+                    .map_err(|e| e.to_string())?
+                    .into_iter()
+                    .map(|(validator, s)| Delegation {
+                        delegator: delegator.clone(),
+                        validator: HumanAddr::from(String::from_utf8_lossy(&validator).to_string()),
+                        amount: coin(s.amount.u128(), &self.bonded_denom),
+                    })
+                    .collect();
+                to_binary(&AllDelegationsResponse { delegations })
+            }
+            StakingQuery::Delegation {
+                delegator,
+                validator,
+            } => {
+                let delegation = self.full_delegation(storage, block, delegator, validator)?;
+                to_binary(&DelegationResponse { delegation })
+            }
+            StakingQuery::Validators {} => {
+                let validators: StdResult<Vec<_>> = VALIDATORS
+                    .range(storage, None, None, Order::Ascending)
+                    .map(|item| item.map(|(_, v)| v))
+                    .collect();
+                to_binary(&ValidatorsResponse {
+                    validators: validators.map_err(|e| e.to_string())?,
+                })
+            }
+        };
+        res.map_err(|e| e.to_string())
+    }
+
+    fn end_block(
+        &self,
+        storage: &mut dyn Storage,
+        block: &BlockInfo,
+    ) -> Result<Vec<StakingTransfer>, String> {
+        // everything released up to and including this block time
+        let max = Bound::exclusive_prefix(U64Key::from(block.time + 1));
+        let matured: StdResult<Vec<_>> = UNBONDING
+            .range_de(storage, None, Some(max), Order::Ascending)
+            .collect();
+        let matured = matured.map_err(|e| e.to_string())?;
+
+        let mut transfers = vec![];
+        for ((release, delegator), amount) in matured {
+            UNBONDING.remove(storage, (release.into(), &delegator));
+            transfers.push(StakingTransfer::Unbond {
+                to: HumanAddr::from(String::from_utf8_lossy(&delegator).to_string()),
+                amount: coin(amount.u128(), &self.bonded_denom),
+            });
+        }
+        Ok(transfers)
+    }
+
+    fn add_validator(&self, storage: &mut dyn Storage, validator: Validator) -> Result<(), String> {
+        let key = validator.address.clone();
+        if VALIDATORS
+            .may_load(storage, key.as_bytes())
+            .map_err(|e| e.to_string())?
+            .is_some()
+        {
+            return Err(format!("Validator {} already exists", key));
+        }
+        VALIDATORS
+            .save(storage, key.as_bytes(), &validator)
+            .map_err(|e| e.to_string())
+    }
+
+    fn clone(&self) -> Box<dyn Staking> {
+        Box::new(Clone::clone(self))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use cosmwasm_std::testing::{mock_env, MockStorage};
+    use cosmwasm_std::{coins, from_slice};
+
+    fn validator(address: &str, commission: u64) -> Validator {
+        Validator {
+            address: address.into(),
+            commission: Decimal::percent(commission),
+            max_commission: Decimal::percent(20),
+            max_change_rate: Decimal::percent(1),
+        }
+    }
+
+    fn setup() -> (SimpleStaking, MockStorage) {
+        let mut store = MockStorage::new();
+        let staking = SimpleStaking::default();
+        staking
+            .add_validator(&mut store, validator("val1", 0))
+            .unwrap();
+        staking
+            .add_validator(&mut store, validator("val2", 10))
+            .unwrap();
+        (staking, store)
+    }
+
+    fn query_delegation(
+        staking: &SimpleStaking,
+        store: &MockStorage,
+        block: &BlockInfo,
+        delegator: &str,
+        validator: &str,
+    ) -> Option<FullDelegation> {
+        let req = StakingQuery::Delegation {
+            delegator: delegator.into(),
+            validator: validator.into(),
+        };
+        let raw = staking.query(store, block, req).unwrap();
+        let res: DelegationResponse = from_slice(&raw).unwrap();
+        res.delegation
+    }
+
+    #[test]
+    fn delegate_and_query() {
+        let (staking, mut store) = setup();
+        let block = mock_env().block;
+        let alice = HumanAddr::from("alice");
+
+        // cannot add a validator twice
+        staking
+            .add_validator(&mut store, validator("val1", 5))
+            .unwrap_err();
+
+        let msg = StakingMsg::Delegate {
+            validator: "val1".into(),
+            amount: coin(100, "stake"),
+        };
+        let transfers = staking
+            .handle(&mut store, &block, alice.clone(), msg)
+            .unwrap();
+        assert_eq!(
+            transfers,
+            vec![StakingTransfer::Bond {
+                from: alice.clone(),
+                amount: coin(100, "stake")
+            }]
+        );
+
+        // wrong denom and unknown validator fail
+        let msg = StakingMsg::Delegate {
+            validator: "val1".into(),
+            amount: coin(100, "eth"),
+        };
+        staking
+            .handle(&mut store, &block, alice.clone(), msg)
+            .unwrap_err();
+        let msg = StakingMsg::Delegate {
+            validator: "val3".into(),
+            amount: coin(100, "stake"),
+        };
+        staking
+            .handle(&mut store, &block, alice.clone(), msg)
+            .unwrap_err();
+
+        let raw = staking
+            .query(
+                &store,
+                &block,
+                StakingQuery::AllDelegations {
+                    delegator: alice.clone(),
+                },
+            )
+            .unwrap();
+        let res: AllDelegationsResponse = from_slice(&raw).unwrap();
+        assert_eq!(
+            res.delegations,
+            vec![Delegation {
+                delegator: alice.clone(),
+                validator: "val1".into(),
+                amount: coin(100, "stake"),
+            }]
+        );
+
+        let raw = staking
+            .query(&store, &block, StakingQuery::Validators {})
+            .unwrap();
+        let res: ValidatorsResponse = from_slice(&raw).unwrap();
+        assert_eq!(res.validators.len(), 2);
+
+        assert_eq!(
+            None,
+            query_delegation(&staking, &store, &block, "alice", "val2")
+        );
+    }
+
+    #[test]
+    fn rewards_accrue_minus_commission() {
+        let (staking, mut store) = setup();
+        let mut block = mock_env().block;
+        let alice = HumanAddr::from("alice");
+
+        for val in &["val1", "val2"] {
+            let msg = StakingMsg::Delegate {
+                validator: (*val).into(),
+                amount: coin(1000, "stake"),
+            };
+            staking
+                .handle(&mut store, &block, alice.clone(), msg)
+                .unwrap();
+        }
+
+        // 10% after one year, val2 keeps 10% of it
+        block.time += YEAR;
+        let full = query_delegation(&staking, &store, &block, "alice", "val1").unwrap();
+        assert_eq!(full.accumulated_rewards, coins(100, "stake"));
+        let full = query_delegation(&staking, &store, &block, "alice", "val2").unwrap();
+        assert_eq!(full.accumulated_rewards, coins(90, "stake"));
+
+        let msg = StakingMsg::Withdraw {
+            validator: "val2".into(),
+            recipient: Some("bob".into()),
+        };
+        let transfers = staking
+            .handle(&mut store, &block, alice.clone(), msg)
+            .unwrap();
+        assert_eq!(
+            transfers,
+            vec![StakingTransfer::Mint {
+                to: "bob".into(),
+                amount: coin(90, "stake")
+            }]
+        );
+        let full = query_delegation(&staking, &store, &block, "alice", "val2").unwrap();
+        assert_eq!(full.accumulated_rewards, vec![]);
+    }
+
+    #[test]
+    fn undelegate_and_redelegate() {
+        let (staking, mut store) = setup();
+        let mut block = mock_env().block;
+        let alice = HumanAddr::from("alice");
+
+        let msg = StakingMsg::Delegate {
+            validator: "val1".into(),
+            amount: coin(1000, "stake"),
+        };
+        staking
+            .handle(&mut store, &block, alice.clone(), msg)
+            .unwrap();
+
+        let msg = StakingMsg::Redelegate {
+            src_validator: "val1".into(),
+            dst_validator: "val2".into(),
+            amount: coin(400, "stake"),
+        };
+        staking
+            .handle(&mut store, &block, alice.clone(), msg)
+            .unwrap();
+        let full = query_delegation(&staking, &store, &block, "alice", "val2").unwrap();
+        assert_eq!(full.amount, coin(400, "stake"));
+
+        // cannot undelegate more than delegated
+        let msg = StakingMsg::Undelegate {
+            validator: "val1".into(),
+            amount: coin(601, "stake"),
+        };
+        staking
+            .handle(&mut store, &block, alice.clone(), msg)
+            .unwrap_err();
+
+        let msg = StakingMsg::Undelegate {
+            validator: "val1".into(),
+            amount: coin(600, "stake"),
+        };
+        let transfers = staking
+            .handle(&mut store, &block, alice.clone(), msg)
+            .unwrap();
+        assert_eq!(transfers, vec![]);
+        assert_eq!(
+            None,
+            query_delegation(&staking, &store, &block, "alice", "val1")
+        );
+
+        // nothing released before the unbonding time passed
+        block.time += staking.unbonding_time - 1;
+        assert_eq!(staking.end_block(&mut store, &block).unwrap(), vec![]);
+        block.time += 1;
+        assert_eq!(
+            staking.end_block(&mut store, &block).unwrap(),
+            vec![StakingTransfer::Unbond {
+                to: alice.clone(),
+                amount: coin(600, "stake")
+            }]
+        );
+        // only once
+        assert_eq!(staking.end_block(&mut store, &block).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn changing_delegation_pays_rewards() {
+        let (staking, mut store) = setup();
+        let mut block = mock_env().block;
+        let alice = HumanAddr::from("alice");
+
+        let msg = StakingMsg::Delegate {
+            validator: "val1".into(),
+            amount: coin(1000, "stake"),
+        };
+        staking
+            .handle(&mut store, &block, alice.clone(), msg)
+            .unwrap();
+
+        // delegating more pays out the rewards so far
+        block.time += YEAR;
+        let msg = StakingMsg::Delegate {
+            validator: "val1".into(),
+            amount: coin(1000, "stake"),
+        };
+        let transfers = staking
+            .handle(&mut store, &block, alice.clone(), msg)
+            .unwrap();
+        assert_eq!(
+            transfers,
+            vec![
+                StakingTransfer::Mint {
+                    to: alice.clone(),
+                    amount: coin(100, "stake")
+                },
+                StakingTransfer::Bond {
+                    from: alice.clone(),
+                    amount: coin(1000, "stake")
+                }
+            ]
+        );
+        let full = query_delegation(&staking, &store, &block, "alice", "val1").unwrap();
+        assert_eq!(full.amount, coin(2000, "stake"));
+        assert_eq!(full.accumulated_rewards, vec![]);
+
+        // so does redelegating, from both validators
+        block.time += YEAR / 2;
+        let msg = StakingMsg::Redelegate {
+            src_validator: "val1".into(),
+            dst_validator: "val2".into(),
+            amount: coin(1000, "stake"),
+        };
+        let transfers = staking
+            .handle(&mut store, &block, alice.clone(), msg)
+            .unwrap();
+        assert_eq!(
+            transfers,
+            vec![StakingTransfer::Mint {
+                to: alice.clone(),
+                amount: coin(100, "stake")
+            }]
+        );
+
+        // a full undelegation after some time pays out and leaves no delegation
+        block.time += YEAR;
+        let msg = StakingMsg::Undelegate {
+            validator: "val2".into(),
+            amount: coin(1000, "stake"),
+        };
+        let transfers = staking
+            .handle(&mut store, &block, alice.clone(), msg)
+            .unwrap();
+        assert_eq!(
+            transfers,
+            vec![StakingTransfer::Mint {
+                to: alice.clone(),
+                amount: coin(90, "stake")
+            }]
+        );
+        assert_eq!(
+            None,
+            query_delegation(&staking, &store, &block, "alice", "val2")
+        );
+        let raw = staking
+            .query(
+                &store,
+                &block,
+                StakingQuery::AllDelegations {
+                    delegator: alice.clone(),
+                },
+            )
+            .unwrap();
+        let res: AllDelegationsResponse = from_slice(&raw).unwrap();
+        assert_eq!(res.delegations.len(), 1);
+
+        // no rewards were accrued on the undelegated tokens
+        block.time += YEAR;
+        let msg = StakingMsg::Withdraw {
+            validator: "val2".into(),
+            recipient: None,
+        };
+        staking.handle(&mut store, &block, alice, msg).unwrap_err();
+    }
+}
@@ -1,10 +1,13 @@
 #![cfg(test)]
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
-use crate::wasm::{Contract, ContractWrapper};
+use crate::wasm::{CodeData, Contract, ContractData, ContractWrapper, MetadataQuery};
 use cosmwasm_std::{
-    attr, from_slice, to_binary, to_vec, BankMsg, Binary, Coin, CosmosMsg, Deps, DepsMut, Empty,
-    Env, HandleResponse, InitResponse, MessageInfo, StdError,
+    attr, from_slice, to_binary, to_vec, BankMsg, Binary, Coin, CosmosMsg, CustomQuery, Deps,
+    DepsMut, Empty, Env, HandleResponse, InitResponse, MessageInfo, MigrateResponse, QueryRequest,
+    StdError,
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -83,8 +86,35 @@ fn query_payout(deps: Deps, _env: Env, _msg: EmptyMsg) -> Result<Binary, StdErro
     Ok(bin.into())
 }
 
+// changes the payout
+fn migrate_payout(
+    deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    msg: PayoutMessage,
+) -> Result<MigrateResponse, StdError> {
+    let bin = to_vec(&msg)?;
+    deps.storage.set(PAYOUT_KEY, &bin);
+    Ok(MigrateResponse {
+        messages: vec![],
+        attributes: vec![attr("action", "migrate")],
+        data: None,
+    })
+}
+
 pub fn contract_payout() -> Box<dyn Contract> {
-    let contract = ContractWrapper::new(handle_payout, init_payout, query_payout);
+    let contract =
+        ContractWrapper::new(handle_payout, init_payout, query_payout).with_migrate(migrate_payout);
+    Box::new(contract)
+}
+
+/// The payout contract, for an App with custom messages
+pub fn contract_payout_custom<C>() -> Box<dyn Contract<C>>
+where
+    C: Clone + fmt::Debug + PartialEq + JsonSchema + 'static,
+{
+    let contract = ContractWrapper::new_with_empty(handle_payout, init_payout, query_payout)
+        .with_migrate_empty(migrate_payout);
     Box::new(contract)
 }
 
@@ -143,3 +173,71 @@ pub fn contract_reflect() -> Box<dyn Contract> {
     let contract = ContractWrapper::new(handle_reflect, init_reflect, query_reflect);
     Box::new(contract)
 }
+
+/// A custom message of our test chain, it stores a name for the sender
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct SetNameMsg {
+    pub name: String,
+}
+
+/// A custom query of our test chain, returning the name stored for the address
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct NameQuery {
+    pub address: String,
+}
+
+impl CustomQuery for NameQuery {}
+
+fn init_custom(
+    _deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    _msg: EmptyMsg,
+) -> Result<InitResponse<SetNameMsg>, StdError> {
+    Ok(InitResponse::default())
+}
+
+// sends the name to the chain
+fn handle_custom(
+    _deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    msg: SetNameMsg,
+) -> Result<HandleResponse<SetNameMsg>, StdError> {
+    let res = HandleResponse {
+        messages: vec![CosmosMsg::Custom(msg)],
+        attributes: vec![attr("action", "set_name")],
+        data: None,
+    };
+    Ok(res)
+}
+
+// asks the chain for the name
+fn query_custom(deps: Deps, _env: Env, msg: NameQuery) -> Result<Binary, StdError> {
+    let name: String = deps.querier.custom_query(&QueryRequest::Custom(msg))?;
+    to_binary(&name)
+}
+
+pub fn contract_custom() -> Box<dyn Contract<SetNameMsg>> {
+    let contract = ContractWrapper::new(handle_custom, init_custom, query_custom);
+    Box::new(contract)
+}
+
+// asks the chain for code or contract metadata and returns it
+fn query_metadata(deps: Deps, _env: Env, msg: MetadataQuery) -> Result<Binary, StdError> {
+    match &msg {
+        MetadataQuery::CodeInfo { .. } => {
+            let data: CodeData = deps.querier.custom_query(&QueryRequest::Custom(msg))?;
+            to_binary(&data)
+        }
+        MetadataQuery::ContractInfo { .. } => {
+            let data: ContractData = deps.querier.custom_query(&QueryRequest::Custom(msg))?;
+            to_binary(&data)
+        }
+    }
+}
+
+pub fn contract_metadata() -> Box<dyn Contract> {
+    let contract = ContractWrapper::new(handle_error, init_reflect, query_metadata);
+    Box::new(contract)
+}
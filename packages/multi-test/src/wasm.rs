@@ -1,22 +1,31 @@
+use schemars::JsonSchema;
 use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::fmt;
 use std::ops::Deref;
 
 use crate::transactions::{RepLog, StorageTransaction};
 use cosmwasm_std::{
-    from_slice, Api, Binary, BlockInfo, ContractInfo, Deps, DepsMut, Env, HandleResponse,
-    HumanAddr, InitResponse, MessageInfo, Querier, QuerierWrapper, Storage, WasmQuery,
+    from_slice, Api, Binary, BlockInfo, ContractInfo, CosmosMsg, CustomQuery, Deps, DepsMut, Empty,
+    Env, HandleResponse, HumanAddr, InitResponse, MessageInfo, MigrateResponse, Querier,
+    QuerierWrapper, Storage, WasmQuery,
 };
 
-/// Interface to call into a Contract
-pub trait Contract {
+/// Interface to call into a Contract.
+/// `C` is the custom message type the contract may return, see `CustomHandler`
+pub trait Contract<C = Empty>
+where
+    C: Clone + fmt::Debug + PartialEq + JsonSchema,
+{
     fn handle(
         &self,
         deps: DepsMut,
         env: Env,
         info: MessageInfo,
         msg: Vec<u8>,
-    ) -> Result<HandleResponse, String>;
+    ) -> Result<HandleResponse<C>, String>;
 
     fn init(
         &self,
@@ -24,32 +33,54 @@ pub trait Contract {
         env: Env,
         info: MessageInfo,
         msg: Vec<u8>,
-    ) -> Result<InitResponse, String>;
+    ) -> Result<InitResponse<C>, String>;
 
     fn query(&self, deps: Deps, env: Env, msg: Vec<u8>) -> Result<Binary, String>;
+
+    /// Called on the new code when the contract is migrated to it
+    fn migrate(
+        &self,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        msg: Vec<u8>,
+    ) -> Result<MigrateResponse<C>, String>;
 }
 
 type ContractFn<T, R, E> = fn(deps: DepsMut, env: Env, info: MessageInfo, msg: T) -> Result<R, E>;
 
 type QueryFn<T, E> = fn(deps: Deps, env: Env, msg: T) -> Result<Binary, E>;
 
-/// Wraps the exported functions from a contract and provides the normalized format
-/// TODO: Allow to customize return values (CustomMsg beyond Empty)
-pub struct ContractWrapper<T1, T2, T3, E1, E2, E3>
+/// An entry point that returns either the custom message type `C` of the App (`R`),
+/// or `Empty` (`RE`), which is converted into `R` when called
+enum EntryFn<T, R, RE, E> {
+    Custom(ContractFn<T, R, E>),
+    Empty(ContractFn<T, RE, E>),
+}
+
+/// Wraps the exported functions from a contract and provides the normalized format.
+/// `C` is the custom message type returned in the responses, which is `Empty` for most contracts.
+/// `T4` and `E4` are the message and error types of the migrate entry point, if set.
+/// Use `new_with_empty` to run a contract returning `Empty` messages in an App with custom messages.
+pub struct ContractWrapper<T1, T2, T3, E1, E2, E3, C = Empty, T4 = Empty, E4 = String>
 where
     T1: DeserializeOwned,
     T2: DeserializeOwned,
     T3: DeserializeOwned,
+    T4: DeserializeOwned,
     E1: std::fmt::Display,
     E2: std::fmt::Display,
     E3: std::fmt::Display,
+    E4: std::fmt::Display,
+    C: Clone + fmt::Debug + PartialEq + JsonSchema,
 {
-    handle_fn: ContractFn<T1, HandleResponse, E1>,
-    init_fn: ContractFn<T2, InitResponse, E2>,
+    handle_fn: EntryFn<T1, HandleResponse<C>, HandleResponse, E1>,
+    init_fn: EntryFn<T2, InitResponse<C>, InitResponse, E2>,
     query_fn: QueryFn<T3, E3>,
+    migrate_fn: Option<EntryFn<T4, MigrateResponse<C>, MigrateResponse, E4>>,
 }
 
-impl<T1, T2, T3, E1, E2, E3> ContractWrapper<T1, T2, T3, E1, E2, E3>
+impl<T1, T2, T3, E1, E2, E3, C> ContractWrapper<T1, T2, T3, E1, E2, E3, C>
 where
     T1: DeserializeOwned,
     T2: DeserializeOwned,
@@ -57,28 +88,99 @@ where
     E1: std::fmt::Display,
     E2: std::fmt::Display,
     E3: std::fmt::Display,
+    C: Clone + fmt::Debug + PartialEq + JsonSchema,
 {
     pub fn new(
+        handle_fn: ContractFn<T1, HandleResponse<C>, E1>,
+        init_fn: ContractFn<T2, InitResponse<C>, E2>,
+        query_fn: QueryFn<T3, E3>,
+    ) -> Self {
+        ContractWrapper {
+            handle_fn: EntryFn::Custom(handle_fn),
+            init_fn: EntryFn::Custom(init_fn),
+            query_fn,
+            migrate_fn: None,
+        }
+    }
+
+    /// Like `new`, but for a contract that returns `Empty` messages, so it can be stored in
+    /// an App with custom messages. Its responses cannot contain `CosmosMsg::Custom`.
+    pub fn new_with_empty(
         handle_fn: ContractFn<T1, HandleResponse, E1>,
         init_fn: ContractFn<T2, InitResponse, E2>,
         query_fn: QueryFn<T3, E3>,
     ) -> Self {
         ContractWrapper {
-            handle_fn,
-            init_fn,
+            handle_fn: EntryFn::Empty(handle_fn),
+            init_fn: EntryFn::Empty(init_fn),
             query_fn,
+            migrate_fn: None,
+        }
+    }
+
+    /// Adds the migrate entry point, without it the contract cannot be migrated to
+    pub fn with_migrate<T4, E4>(
+        self,
+        migrate_fn: ContractFn<T4, MigrateResponse<C>, E4>,
+    ) -> ContractWrapper<T1, T2, T3, E1, E2, E3, C, T4, E4>
+    where
+        T4: DeserializeOwned,
+        E4: std::fmt::Display,
+    {
+        ContractWrapper {
+            handle_fn: self.handle_fn,
+            init_fn: self.init_fn,
+            query_fn: self.query_fn,
+            migrate_fn: Some(EntryFn::Custom(migrate_fn)),
+        }
+    }
+
+    /// Like `with_migrate`, for a migrate entry point that returns `Empty` messages
+    pub fn with_migrate_empty<T4, E4>(
+        self,
+        migrate_fn: ContractFn<T4, MigrateResponse, E4>,
+    ) -> ContractWrapper<T1, T2, T3, E1, E2, E3, C, T4, E4>
+    where
+        T4: DeserializeOwned,
+        E4: std::fmt::Display,
+    {
+        ContractWrapper {
+            handle_fn: self.handle_fn,
+            init_fn: self.init_fn,
+            query_fn: self.query_fn,
+            migrate_fn: Some(EntryFn::Empty(migrate_fn)),
         }
     }
 }
 
-impl<T1, T2, T3, E1, E2, E3> Contract for ContractWrapper<T1, T2, T3, E1, E2, E3>
+/// Converts the messages of a contract returning `Empty` into the custom message type of the App
+fn customize_messages<C>(messages: Vec<CosmosMsg>) -> Result<Vec<CosmosMsg<C>>, String>
+where
+    C: Clone + fmt::Debug + PartialEq + JsonSchema,
+{
+    messages
+        .into_iter()
+        .map(|msg| match msg {
+            CosmosMsg::Bank(msg) => Ok(CosmosMsg::Bank(msg)),
+            CosmosMsg::Staking(msg) => Ok(CosmosMsg::Staking(msg)),
+            CosmosMsg::Wasm(msg) => Ok(CosmosMsg::Wasm(msg)),
+            CosmosMsg::Custom(_) => Err("Cannot convert an Empty custom message".to_string()),
+        })
+        .collect()
+}
+
+impl<T1, T2, T3, E1, E2, E3, C, T4, E4> Contract<C>
+    for ContractWrapper<T1, T2, T3, E1, E2, E3, C, T4, E4>
 where
     T1: DeserializeOwned,
     T2: DeserializeOwned,
     T3: DeserializeOwned,
+    T4: DeserializeOwned,
     E1: std::fmt::Display,
     E2: std::fmt::Display,
     E3: std::fmt::Display,
+    E4: std::fmt::Display,
+    C: Clone + fmt::Debug + PartialEq + JsonSchema,
 {
     fn handle(
         &self,
@@ -86,10 +188,21 @@ where
         env: Env,
         info: MessageInfo,
         msg: Vec<u8>,
-    ) -> Result<HandleResponse, String> {
+    ) -> Result<HandleResponse<C>, String> {
         let msg: T1 = from_slice(&msg).map_err(|e| e.to_string())?;
-        let res = (self.handle_fn)(deps, env, info, msg);
-        res.map_err(|e| e.to_string())
+        match &self.handle_fn {
+            EntryFn::Custom(handle_fn) => {
+                handle_fn(deps, env, info, msg).map_err(|e| e.to_string())
+            }
+            EntryFn::Empty(handle_fn) => {
+                let res = handle_fn(deps, env, info, msg).map_err(|e| e.to_string())?;
+                Ok(HandleResponse {
+                    messages: customize_messages(res.messages)?,
+                    attributes: res.attributes,
+                    data: res.data,
+                })
+            }
+        }
     }
 
     fn init(
@@ -98,10 +211,18 @@ where
         env: Env,
         info: MessageInfo,
         msg: Vec<u8>,
-    ) -> Result<InitResponse, String> {
+    ) -> Result<InitResponse<C>, String> {
         let msg: T2 = from_slice(&msg).map_err(|e| e.to_string())?;
-        let res = (self.init_fn)(deps, env, info, msg);
-        res.map_err(|e| e.to_string())
+        match &self.init_fn {
+            EntryFn::Custom(init_fn) => init_fn(deps, env, info, msg).map_err(|e| e.to_string()),
+            EntryFn::Empty(init_fn) => {
+                let res = init_fn(deps, env, info, msg).map_err(|e| e.to_string())?;
+                Ok(InitResponse {
+                    messages: customize_messages(res.messages)?,
+                    attributes: res.attributes,
+                })
+            }
+        }
     }
 
     fn query(&self, deps: Deps, env: Env, msg: Vec<u8>) -> Result<Binary, String> {
@@ -109,16 +230,97 @@ where
         let res = (self.query_fn)(deps, env, msg);
         res.map_err(|e| e.to_string())
     }
+
+    fn migrate(
+        &self,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        msg: Vec<u8>,
+    ) -> Result<MigrateResponse<C>, String> {
+        let migrate_fn = self
+            .migrate_fn
+            .as_ref()
+            .ok_or_else(|| "Contract does not support migration".to_string())?;
+        let msg: T4 = from_slice(&msg).map_err(|e| e.to_string())?;
+        match migrate_fn {
+            EntryFn::Custom(migrate_fn) => {
+                migrate_fn(deps, env, info, msg).map_err(|e| e.to_string())
+            }
+            EntryFn::Empty(migrate_fn) => {
+                let res = migrate_fn(deps, env, info, msg).map_err(|e| e.to_string())?;
+                Ok(MigrateResponse {
+                    messages: customize_messages(res.messages)?,
+                    attributes: res.attributes,
+                    data: res.data,
+                })
+            }
+        }
+    }
+}
+
+/// Metadata of uploaded code
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CodeData {
+    pub creator: HumanAddr,
+    /// There is no wasm bytecode to hash here, so this is the hex encoded sha256 of the
+    /// code id and creator. It is a unique identifier of the stored code, not a hash of
+    /// its content: storing the same contract twice gives two different values.
+    pub checksum: String,
+}
+
+impl CodeData {
+    fn new(code_id: usize, creator: HumanAddr) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.input((code_id as u64).to_be_bytes());
+        hasher.input(creator.as_bytes());
+        CodeData {
+            creator,
+            checksum: hex::encode(hasher.result()),
+        }
+    }
+}
+
+/// Metadata of an instantiated contract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ContractData {
+    pub code_id: u64,
+    /// the account that instantiated the contract
+    pub creator: HumanAddr,
+    /// only the admin may migrate the contract
+    pub admin: Option<HumanAddr>,
+    pub label: String,
 }
 
-struct ContractData {
+/// Asks the App for the metadata of stored code or of a contract. Contracts can send it as a
+/// custom query, if the App was built `with_metadata_queries`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataQuery {
+    /// Returns CodeData
+    CodeInfo { code_id: u64 },
+    /// Returns ContractData
+    ContractInfo { contract_addr: HumanAddr },
+}
+
+impl CustomQuery for MetadataQuery {}
+
+struct ContractInstance {
     code_id: usize,
+    creator: HumanAddr,
+    admin: Option<HumanAddr>,
+    label: String,
     storage: Box<dyn Storage>,
 }
 
-impl ContractData {
-    fn new(code_id: usize, storage: Box<dyn Storage>) -> Self {
-        ContractData { code_id, storage }
+impl ContractInstance {
+    fn data(&self) -> ContractData {
+        ContractData {
+            code_id: self.code_id as u64,
+            creator: self.creator.clone(),
+            admin: self.admin.clone(),
+            label: self.label.clone(),
+        }
     }
 }
 
@@ -129,20 +331,28 @@ pub fn next_block(block: &mut BlockInfo) {
 
 pub type StorageFactory = fn() -> Box<dyn Storage>;
 
-pub struct WasmRouter {
+pub struct WasmRouter<C = Empty>
+where
+    C: Clone + fmt::Debug + PartialEq + JsonSchema,
+{
     // WasmState - cache this, pass in separate?
-    handlers: HashMap<usize, Box<dyn Contract>>,
-    contracts: HashMap<HumanAddr, ContractData>,
+    handlers: HashMap<usize, Box<dyn Contract<C>>>,
+    codes: HashMap<usize, CodeData>,
+    contracts: HashMap<HumanAddr, ContractInstance>,
     // WasmConst
     block: BlockInfo,
     api: Box<dyn Api>,
     storage_factory: StorageFactory,
 }
 
-impl WasmRouter {
+impl<C> WasmRouter<C>
+where
+    C: Clone + fmt::Debug + PartialEq + JsonSchema,
+{
     pub fn new(api: Box<dyn Api>, block: BlockInfo, storage_factory: StorageFactory) -> Self {
         WasmRouter {
             handlers: HashMap::new(),
+            codes: HashMap::new(),
             contracts: HashMap::new(),
             block,
             api,
@@ -164,13 +374,28 @@ impl WasmRouter {
         self.block.clone()
     }
 
-    pub fn store_code(&mut self, code: Box<dyn Contract>) -> usize {
+    pub fn store_code(&mut self, creator: HumanAddr, code: Box<dyn Contract<C>>) -> usize {
         let idx = self.handlers.len() + 1;
         self.handlers.insert(idx, code);
+        self.codes.insert(idx, CodeData::new(idx, creator));
         idx
     }
 
-    pub fn cache(&'_ self) -> WasmCache<'_> {
+    pub fn code_data(&self, code_id: usize) -> Result<CodeData, String> {
+        self.codes
+            .get(&code_id)
+            .cloned()
+            .ok_or_else(|| "Unregistered code id".to_string())
+    }
+
+    pub fn contract_data(&self, address: &HumanAddr) -> Result<ContractData, String> {
+        self.contracts
+            .get(address)
+            .map(ContractInstance::data)
+            .ok_or_else(|| "Unregistered contract address".to_string())
+    }
+
+    pub fn cache(&'_ self) -> WasmCache<'_, C> {
         WasmCache::new(self)
     }
 
@@ -219,7 +444,7 @@ impl WasmRouter {
         action: F,
     ) -> Result<T, String>
     where
-        F: FnOnce(&Box<dyn Contract>, Deps, Env) -> Result<T, String>,
+        F: FnOnce(&Box<dyn Contract<C>>, Deps, Env) -> Result<T, String>,
     {
         let contract = self
             .contracts
@@ -253,9 +478,12 @@ impl WasmRouter {
 ///
 /// In Router, we use this exclusively in all the calls in execute (not self.wasm)
 /// In Querier, we use self.wasm
-pub struct WasmCache<'a> {
+pub struct WasmCache<'a, C = Empty>
+where
+    C: Clone + fmt::Debug + PartialEq + JsonSchema,
+{
     // and this into one with reference
-    router: &'a WasmRouter,
+    router: &'a WasmRouter<C>,
     state: WasmCacheState<'a>,
 }
 
@@ -264,19 +492,25 @@ pub struct WasmCache<'a> {
 /// while still getting an immutable reference to router.
 /// (We cannot take &mut WasmCache)
 pub struct WasmCacheState<'a> {
-    contracts: HashMap<HumanAddr, ContractData>,
+    contracts: HashMap<HumanAddr, ContractInstance>,
     contract_diffs: HashMap<HumanAddr, StorageTransaction<'a>>,
+    // new code ids of existing contracts that were migrated
+    code_changes: HashMap<HumanAddr, usize>,
 }
 
 /// This is a set of data from the WasmCache with no external reference,
 /// which can be used to commit to the underlying WasmRouter.
 pub struct WasmOps {
-    new_contracts: HashMap<HumanAddr, ContractData>,
+    new_contracts: HashMap<HumanAddr, ContractInstance>,
     contract_diffs: Vec<(HumanAddr, RepLog)>,
+    code_changes: HashMap<HumanAddr, usize>,
 }
 
 impl WasmOps {
-    pub fn commit(self, router: &mut WasmRouter) {
+    pub fn commit<C>(self, router: &mut WasmRouter<C>)
+    where
+        C: Clone + fmt::Debug + PartialEq + JsonSchema,
+    {
         self.new_contracts.into_iter().for_each(|(k, v)| {
             router.contracts.insert(k, v);
         });
@@ -284,16 +518,23 @@ impl WasmOps {
             let storage = router.contracts.get_mut(&k).unwrap().storage.as_mut();
             ops.commit(storage);
         });
+        self.code_changes.into_iter().for_each(|(k, code_id)| {
+            router.contracts.get_mut(&k).unwrap().code_id = code_id;
+        });
     }
 }
 
-impl<'a> WasmCache<'a> {
-    fn new(router: &'a WasmRouter) -> Self {
+impl<'a, C> WasmCache<'a, C>
+where
+    C: Clone + fmt::Debug + PartialEq + JsonSchema,
+{
+    fn new(router: &'a WasmRouter<C>) -> Self {
         WasmCache {
             router,
             state: WasmCacheState {
                 contracts: HashMap::new(),
                 contract_diffs: HashMap::new(),
+                code_changes: HashMap::new(),
             },
         }
     }
@@ -308,12 +549,24 @@ impl<'a> WasmCache<'a> {
     /// This just creates an address and empty storage instance, returning the new address
     /// You must call init after this to set up the contract properly.
     /// These are separated into two steps to have cleaner return values.
-    pub fn register_contract(&mut self, code_id: usize) -> Result<HumanAddr, String> {
+    pub fn register_contract(
+        &mut self,
+        code_id: usize,
+        creator: HumanAddr,
+        admin: Option<HumanAddr>,
+        label: String,
+    ) -> Result<HumanAddr, String> {
         if !self.router.handlers.contains_key(&code_id) {
             return Err("Cannot init contract with unregistered code id".to_string());
         }
         let addr = self.next_address();
-        let info = ContractData::new(code_id, (self.router.storage_factory)());
+        let info = ContractInstance {
+            code_id,
+            creator,
+            admin,
+            label,
+            storage: (self.router.storage_factory)(),
+        };
         self.state.contracts.insert(addr.clone(), info);
         Ok(addr)
     }
@@ -331,7 +584,7 @@ impl<'a> WasmCache<'a> {
         querier: &dyn Querier,
         info: MessageInfo,
         msg: Vec<u8>,
-    ) -> Result<HandleResponse, String> {
+    ) -> Result<HandleResponse<C>, String> {
         let parent = &self.router.handlers;
         let contracts = &self.router.contracts;
         let env = self.router.get_env(address.clone());
@@ -358,7 +611,7 @@ impl<'a> WasmCache<'a> {
         querier: &dyn Querier,
         info: MessageInfo,
         msg: Vec<u8>,
-    ) -> Result<InitResponse, String> {
+    ) -> Result<InitResponse<C>, String> {
         let parent = &self.router.handlers;
         let contracts = &self.router.contracts;
         let env = self.router.get_env(address.clone());
@@ -378,6 +631,52 @@ impl<'a> WasmCache<'a> {
             },
         )
     }
+
+    /// Switches the contract to new_code_id and calls migrate on the new code.
+    /// Only the admin set on instantiation may do this.
+    pub fn migrate(
+        &mut self,
+        address: HumanAddr,
+        querier: &dyn Querier,
+        info: MessageInfo,
+        new_code_id: usize,
+        msg: Vec<u8>,
+    ) -> Result<MigrateResponse<C>, String> {
+        let parent = &self.router.handlers;
+        let contracts = &self.router.contracts;
+        let env = self.router.get_env(address.clone());
+        let api = self.router.api.as_ref();
+
+        let admin = match self.state.contracts.get(&address) {
+            Some(c) => c.admin.as_ref(),
+            None => contracts
+                .get(&address)
+                .ok_or_else(|| "Unregistered contract address".to_string())?
+                .admin
+                .as_ref(),
+        };
+        if admin != Some(&info.sender) {
+            return Err("Only admin can migrate contract".to_string());
+        }
+        if !parent.contains_key(&new_code_id) {
+            return Err("Cannot migrate contract to unregistered code id".to_string());
+        }
+        self.state.set_code_id(&address, new_code_id);
+
+        self.state.with_storage(
+            querier,
+            contracts,
+            address,
+            env,
+            api,
+            |code_id, deps, env| {
+                let handler = parent
+                    .get(&code_id)
+                    .ok_or_else(|| "Unregistered code id".to_string())?;
+                handler.migrate(deps, env, info, msg)
+            },
+        )
+    }
 }
 
 impl<'a> WasmCacheState<'a> {
@@ -391,12 +690,22 @@ impl<'a> WasmCacheState<'a> {
         WasmOps {
             new_contracts: self.contracts,
             contract_diffs: diffs,
+            code_changes: self.code_changes,
+        }
+    }
+
+    fn set_code_id(&mut self, addr: &HumanAddr, code_id: usize) {
+        match self.contracts.get_mut(addr) {
+            Some(c) => c.code_id = code_id,
+            None => {
+                self.code_changes.insert(addr.clone(), code_id);
+            }
         }
     }
 
     fn get_contract<'b>(
         &'b mut self,
-        parent: &'a HashMap<HumanAddr, ContractData>,
+        parent: &'a HashMap<HumanAddr, ContractInstance>,
         addr: &HumanAddr,
     ) -> Option<(usize, &'b mut dyn Storage)> {
         // if we created this transaction
@@ -404,7 +713,7 @@ impl<'a> WasmCacheState<'a> {
             return Some((x.code_id, x.storage.as_mut()));
         }
         if let Some(c) = parent.get(addr) {
-            let code_id = c.code_id;
+            let code_id = self.code_changes.get(addr).copied().unwrap_or(c.code_id);
             if self.contract_diffs.contains_key(addr) {
                 let storage = self.contract_diffs.get_mut(addr).unwrap();
                 return Some((code_id, storage));
@@ -421,7 +730,7 @@ impl<'a> WasmCacheState<'a> {
     fn with_storage<F, T>(
         &mut self,
         querier: &dyn Querier,
-        parent: &'a HashMap<HumanAddr, ContractData>,
+        parent: &'a HashMap<HumanAddr, ContractInstance>,
         address: HumanAddr,
         env: Env,
         api: &dyn Api,
@@ -459,20 +768,24 @@ mod test {
     #[test]
     fn register_contract() {
         let mut router = mock_router();
-        let code_id = router.store_code(contract_error());
+        let code_id = router.store_code("creator".into(), contract_error());
         let mut cache = router.cache();
 
         // cannot register contract with unregistered codeId
-        cache.register_contract(code_id + 1).unwrap_err();
+        cache
+            .register_contract(code_id + 1, "owner".into(), None, "".into())
+            .unwrap_err();
 
         // we can register a new instance of this code
-        let contract_addr = cache.register_contract(code_id).unwrap();
+        let contract_addr = cache
+            .register_contract(code_id, "owner".into(), None, "label".into())
+            .unwrap();
 
         // now, we call this contract and see the error message from the contract
         let querier: MockQuerier<Empty> = MockQuerier::new(&[]);
         let info = mock_info("foobar", &[]);
         let err = cache
-            .init(contract_addr, &querier, info, b"{}".to_vec())
+            .init(contract_addr.clone(), &querier, info, b"{}".to_vec())
             .unwrap_err();
         // StdError from contract_error auto-converted to string
         assert_eq!(err, "Generic error: Init failed");
@@ -487,6 +800,21 @@ mod test {
 
         // and flush
         cache.prepare().commit(&mut router);
+
+        // the contract metadata is stored
+        assert_eq!(
+            router.contract_data(&contract_addr).unwrap(),
+            ContractData {
+                code_id: code_id as u64,
+                creator: "owner".into(),
+                admin: None,
+                label: "label".to_string(),
+            }
+        );
+        assert_eq!(
+            router.code_data(code_id).unwrap().creator,
+            HumanAddr::from("creator")
+        );
     }
 
     #[test]
@@ -504,10 +832,12 @@ mod test {
     #[test]
     fn contract_send_coins() {
         let mut router = mock_router();
-        let code_id = router.store_code(contract_payout());
+        let code_id = router.store_code("creator".into(), contract_payout());
         let mut cache = router.cache();
 
-        let contract_addr = cache.register_contract(code_id).unwrap();
+        let contract_addr = cache
+            .register_contract(code_id, "owner".into(), None, "label".into())
+            .unwrap();
 
         let querier: MockQuerier<Empty> = MockQuerier::new(&[]);
         let payout = coin(100, "TGD");
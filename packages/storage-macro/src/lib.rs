@@ -0,0 +1,128 @@
+/*!
+Derive macros for `cw-storage-plus`.
+
+`#[derive(IndexList)]` implements `IndexList<T>` for a struct whose fields all implement
+`Index<T>`. The value type `T` is given with the `#[index_list(T)]` attribute.
+
+```
+use cw_storage_plus::{IndexList as _, IndexedMap, MultiIndex, UniqueIndex, U32Key};
+use cw_storage_macro::IndexList;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Data {
+    pub name: String,
+    pub age: u32,
+}
+
+#[derive(IndexList)]
+#[index_list(Data)]
+struct DataIndexes<'a> {
+    pub name: MultiIndex<'a, Vec<u8>, Data>,
+    pub age: UniqueIndex<'a, U32Key, Data>,
+}
+
+let indexes = DataIndexes {
+    name: MultiIndex::new(|d| d.name.as_bytes().to_vec(), "data", "data__name"),
+    age: UniqueIndex::new(|d| U32Key::new(d.age), "data__age"),
+};
+assert_eq!(2, indexes.get_indexes().count());
+let map: IndexedMap<&[u8], Data, DataIndexes> = IndexedMap::new("data", indexes);
+```
+
+Fields indexing another value type do not compile:
+
+```compile_fail
+use cw_storage_plus::{UniqueIndex, U32Key};
+use cw_storage_macro::IndexList;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Data {
+    pub age: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Other {
+    pub age: u32,
+}
+
+#[derive(IndexList)]
+#[index_list(Data)]
+struct DataIndexes<'a> {
+    pub age: UniqueIndex<'a, U32Key, Other>,
+}
+```
+*/
+
+use proc_macro::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, Type};
+
+#[proc_macro_derive(IndexList, attributes(index_list))]
+pub fn derive_index_list(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_index_list(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand_index_list(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let value_type = value_type(&input)?;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    input.ident.span(),
+                    "IndexList can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                input.ident.span(),
+                "IndexList can only be derived for structs",
+            ))
+        }
+    };
+
+    // span every reference on its field, so a wrong value type is reported there
+    let refs = fields.iter().map(|f| {
+        let name = &f.ident;
+        quote_spanned! {f.ty.span()=>
+            &self.#name as &dyn cw_storage_plus::Index<#value_type>
+        }
+    });
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics cw_storage_plus::IndexList<#value_type> for #ident #ty_generics #where_clause {
+            fn get_indexes(
+                &'_ self,
+            ) -> Box<dyn Iterator<Item = &'_ dyn cw_storage_plus::Index<#value_type>> + '_> {
+                let v: Vec<&dyn cw_storage_plus::Index<#value_type>> = vec![#(#refs),*];
+                Box::new(v.into_iter())
+            }
+        }
+    })
+}
+
+/// Reads `T` from the `#[index_list(T)]` attribute
+fn value_type(input: &DeriveInput) -> syn::Result<Type> {
+    let attr = input
+        .attrs
+        .iter()
+        .find(|a| a.path.is_ident("index_list"))
+        .ok_or_else(|| {
+            Error::new(
+                input.ident.span(),
+                "missing #[index_list(T)] attribute with the value type of the map",
+            )
+        })?;
+    attr.parse_args()
+}
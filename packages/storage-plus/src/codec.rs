@@ -0,0 +1,296 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+#[cfg(feature = "bincode")]
+use std::any::type_name;
+
+#[cfg(feature = "bincode")]
+use cosmwasm_std::StdError;
+use cosmwasm_std::{from_slice, to_vec, StdResult};
+
+/// Codec defines how values are turned into bytes before they are written to storage
+/// and how they are parsed again when read. All storage types, including `IndexedMap` and
+/// its indexes, take it as an optional type parameter, which defaults to `JsonCodec`.
+///
+/// Note that the codec is part of the storage layout: changing it for existing data
+/// requires a migration.
+pub trait Codec {
+    fn encode<T: Serialize + ?Sized>(data: &T) -> StdResult<Vec<u8>>;
+    fn decode<T: DeserializeOwned>(value: &[u8]) -> StdResult<T>;
+}
+
+/// JsonCodec stores values as JSON, just like `cosmwasm_std::to_vec`/`from_slice`.
+/// This is the default for all storage types.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    #[inline]
+    fn encode<T: Serialize + ?Sized>(data: &T) -> StdResult<Vec<u8>> {
+        to_vec(data)
+    }
+
+    #[inline]
+    fn decode<T: DeserializeOwned>(value: &[u8]) -> StdResult<T> {
+        from_slice(value)
+    }
+}
+
+/// BincodeCodec stores values in the compact binary format of `bincode`, with fixed-size
+/// integers. This is much smaller and cheaper to parse than JSON, but the data is no
+/// longer readable by clients doing raw queries, unless they decode bincode too.
+///
+/// bincode is not self-describing, so types relying on `#[serde(untagged)]`, `flatten` or
+/// `deserialize_any` cannot be used with it.
+#[cfg(feature = "bincode")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BincodeCodec;
+
+#[cfg(feature = "bincode")]
+impl Codec for BincodeCodec {
+    fn encode<T: Serialize + ?Sized>(data: &T) -> StdResult<Vec<u8>> {
+        bincode::serialize(data).map_err(|e| StdError::serialize_err(type_name::<T>(), e))
+    }
+
+    fn decode<T: DeserializeOwned>(value: &[u8]) -> StdResult<T> {
+        bincode::deserialize(value).map_err(|e| StdError::parse_err(type_name::<T>(), e))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Item, Map};
+    use cosmwasm_std::testing::MockStorage;
+    use cosmwasm_std::{StdError, Storage};
+    use serde::Deserialize;
+
+    /// Tags the json with a version byte, just to show the codec is applied everywhere
+    struct Tagged;
+
+    impl Codec for Tagged {
+        fn encode<T: Serialize + ?Sized>(data: &T) -> StdResult<Vec<u8>> {
+            let mut out = vec![b'!'];
+            out.extend(to_vec(data)?);
+            Ok(out)
+        }
+
+        fn decode<T: DeserializeOwned>(value: &[u8]) -> StdResult<T> {
+            match value.split_first() {
+                Some((b'!', rest)) => from_slice(rest),
+                _ => Err(StdError::parse_err("Tagged", "missing tag")),
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+    struct Config {
+        pub owner: String,
+        pub max_tokens: i32,
+    }
+
+    const CONFIG: Item<Config, Tagged> = Item::new("config");
+    const BALANCES: Map<&[u8], u64, Tagged> = Map::new("balances");
+    const JSON_BALANCES: Map<&[u8], u64> = Map::new("balances");
+
+    #[test]
+    fn item_uses_codec() {
+        let mut store = MockStorage::new();
+        let cfg = Config {
+            owner: "admin".to_string(),
+            max_tokens: 1234,
+        };
+        CONFIG.save(&mut store, &cfg).unwrap();
+        assert_eq!(cfg, CONFIG.load(&store).unwrap());
+
+        let raw = store.get(b"config").unwrap();
+        assert_eq!(b'!', raw[0]);
+        assert_eq!(cfg, from_slice::<Config>(&raw[1..]).unwrap());
+
+        // the default codec cannot read it
+        let json: Item<Config> = Item::new("config");
+        json.load(&store).unwrap_err();
+    }
+
+    #[test]
+    fn map_uses_codec() {
+        let mut store = MockStorage::new();
+        BALANCES.save(&mut store, b"john", &1234).unwrap();
+        BALANCES
+            .update(&mut store, b"jim", |v| -> StdResult<_> {
+                Ok(v.unwrap_or_default() + 5)
+            })
+            .unwrap();
+        assert_eq!(1234, BALANCES.load(&store, b"john").unwrap());
+        assert_eq!(Some(5), BALANCES.key(b"jim").may_load(&store).unwrap());
+
+        let raw = store.get(&BALANCES.key(b"john")).unwrap();
+        assert_eq!(b"!1234".to_vec(), raw);
+        JSON_BALANCES.load(&store, b"john").unwrap_err();
+    }
+
+    #[test]
+    #[cfg(feature = "iterator")]
+    fn range_uses_codec() {
+        use crate::{SnapshotMap, Strategy};
+        use cosmwasm_std::Order;
+
+        let mut store = MockStorage::new();
+        BALANCES.save(&mut store, b"john", &1234).unwrap();
+        BALANCES.save(&mut store, b"jim", &5).unwrap();
+        let all: StdResult<Vec<_>> = BALANCES
+            .range(&store, None, None, Order::Ascending)
+            .collect();
+        assert_eq!(
+            all.unwrap(),
+            vec![(b"jim".to_vec(), 5), (b"john".to_vec(), 1234)]
+        );
+        let all: StdResult<Vec<_>> = BALANCES
+            .range_de(&store, None, None, Order::Descending)
+            .collect();
+        assert_eq!(
+            all.unwrap(),
+            vec![(b"john".to_vec(), 1234), (b"jim".to_vec(), 5)]
+        );
+
+        // snapshots encode the changelog with the same codec
+        let snap: SnapshotMap<&[u8], u64, Tagged> =
+            SnapshotMap::new("snap", "snap__check", "snap__change", Strategy::EveryBlock);
+        snap.save(&mut store, b"A", &5, 1).unwrap();
+        snap.save(&mut store, b"A", &8, 3).unwrap();
+        assert_eq!(Some(8), snap.may_load(&store, b"A").unwrap());
+        assert_eq!(Some(5), snap.may_load_at_height(&store, b"A", 2).unwrap());
+        assert_eq!(b"!8".to_vec(), store.get(&snap.key(b"A")).unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "iterator")]
+    fn indexes_use_codec() {
+        use crate::{Index, IndexList, IndexedMap, MultiIndex, UniqueIndex};
+        use cosmwasm_std::Order;
+
+        struct ConfigIndexes<'a> {
+            pub owner: MultiIndex<'a, Vec<u8>, Config, Tagged>,
+            pub tokens: UniqueIndex<'a, Vec<u8>, Config, Tagged>,
+        }
+
+        impl<'a> IndexList<Config> for ConfigIndexes<'a> {
+            fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Config>> + '_> {
+                let v: Vec<&dyn Index<Config>> = vec![&self.owner, &self.tokens];
+                Box::new(v.into_iter())
+            }
+        }
+
+        let map: IndexedMap<&[u8], Config, ConfigIndexes, Tagged> = IndexedMap::new(
+            "configs",
+            ConfigIndexes {
+                owner: MultiIndex::new(
+                    |c| c.owner.as_bytes().to_vec(),
+                    "configs",
+                    "configs__owner",
+                ),
+                tokens: UniqueIndex::new(
+                    |c| c.max_tokens.to_be_bytes().to_vec(),
+                    "configs__tokens",
+                ),
+            },
+        );
+
+        let mut store = MockStorage::new();
+        let cfg = Config {
+            owner: "admin".to_string(),
+            max_tokens: 1234,
+        };
+        map.save(&mut store, b"one", &cfg).unwrap();
+        let json: Map<&[u8], Config> = Map::new("configs");
+        json.load(&store, b"one").unwrap_err();
+
+        let all: StdResult<Vec<_>> = map.range(&store, None, None, Order::Ascending).collect();
+        assert_eq!(all.unwrap(), vec![(b"one".to_vec(), cfg.clone())]);
+        let by_owner: StdResult<Vec<_>> = map
+            .idx
+            .owner
+            .prefix(b"admin".to_vec())
+            .range(&store, None, None, Order::Ascending)
+            .collect();
+        assert_eq!(by_owner.unwrap(), vec![(b"one".to_vec(), cfg.clone())]);
+        let by_tokens = map
+            .idx
+            .tokens
+            .item(&store, 1234i32.to_be_bytes().to_vec())
+            .unwrap();
+        assert_eq!(by_tokens, Some((b"one".to_vec(), cfg)));
+    }
+
+    #[test]
+    #[cfg(feature = "bincode")]
+    fn bincode_is_compact() {
+        use cosmwasm_std::{HumanAddr, Uint128};
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+        struct Balance {
+            pub owner: HumanAddr,
+            pub amount: Uint128,
+            pub height: u64,
+        }
+
+        const BIN: Map<&[u8], Balance, BincodeCodec> = Map::new("bin");
+        const JSON: Map<&[u8], Balance> = Map::new("json");
+
+        let mut store = MockStorage::new();
+        let balance = Balance {
+            owner: HumanAddr::from("john"),
+            amount: Uint128(1234567),
+            height: 1000,
+        };
+        BIN.save(&mut store, b"john", &balance).unwrap();
+        JSON.save(&mut store, b"john", &balance).unwrap();
+        assert_eq!(balance, BIN.load(&store, b"john").unwrap());
+
+        let bin = store.get(&BIN.key(b"john")).unwrap();
+        let json = store.get(&JSON.key(b"john")).unwrap();
+        assert!(bin.len() < json.len());
+        // the json codec cannot read it
+        let wrong: Map<&[u8], Balance> = Map::new("bin");
+        wrong.load(&store, b"john").unwrap_err();
+    }
+
+    #[test]
+    #[cfg(all(feature = "bincode", feature = "iterator"))]
+    fn unique_index_pk_is_raw_in_bincode() {
+        use crate::{Index, UniqueIndex};
+
+        let bin: UniqueIndex<Vec<u8>, u64, BincodeCodec> =
+            UniqueIndex::new(|v| v.to_be_bytes().to_vec(), "bin");
+        let json: UniqueIndex<Vec<u8>, u64> =
+            UniqueIndex::new(|v| v.to_be_bytes().to_vec(), "json");
+
+        let mut store = MockStorage::new();
+        bin.save(&mut store, b"john", &7).unwrap();
+        json.save(&mut store, b"john", &7).unwrap();
+
+        // length, pk bytes and the value
+        let key = 7u64.to_be_bytes();
+        let raw = store
+            .get(&[b"\x00\x03bin".as_ref(), &key].concat())
+            .unwrap();
+        let mut expected = 4u64.to_le_bytes().to_vec();
+        expected.extend_from_slice(b"john");
+        expected.extend_from_slice(&7u64.to_le_bytes());
+        assert_eq!(raw, expected);
+        assert_eq!(
+            bin.item(&store, key.to_vec()).unwrap(),
+            Some((b"john".to_vec(), 7))
+        );
+
+        // json keeps the base64 string of Binary
+        let raw = store
+            .get(&[b"\x00\x04json".as_ref(), &key].concat())
+            .unwrap();
+        assert_eq!(raw, br#"{"pk":"am9obg==","value":7}"#.to_vec());
+        assert_eq!(
+            json.item(&store, key.to_vec()).unwrap(),
+            Some((b"john".to_vec(), 7))
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use cosmwasm_std::{StdError, StdResult};
+
+use crate::endian::SignedEndian;
+use crate::helpers::decode_length;
+use crate::keys::{IntKey, PkOwned, SignedIntKey};
+use crate::Endian;
+
+/// KeyDeserialize turns the raw bytes of a key (as returned by `range`) back into
+/// the owned form of the type it was built from. `&[u8]` becomes `Vec<u8>`, `&str`
+/// becomes `String`, the `IntKey` family becomes the int, and tuples decode each element.
+pub trait KeyDeserialize {
+    type Output: Sized;
+
+    /// How many elements this key spans in a joined key, more than 1 for (nested) tuples
+    const KEY_ELEMS: u16 = 1;
+
+    fn from_vec(value: Vec<u8>) -> StdResult<Self::Output>;
+
+    fn from_slice(value: &[u8]) -> StdResult<Self::Output> {
+        Self::from_vec(value.to_vec())
+    }
+}
+
+impl KeyDeserialize for () {
+    type Output = ();
+
+    #[inline(always)]
+    fn from_vec(_value: Vec<u8>) -> StdResult<Self::Output> {
+        Ok(())
+    }
+}
+
+impl KeyDeserialize for &[u8] {
+    type Output = Vec<u8>;
+
+    #[inline(always)]
+    fn from_vec(value: Vec<u8>) -> StdResult<Self::Output> {
+        Ok(value)
+    }
+}
+
+impl KeyDeserialize for Vec<u8> {
+    type Output = Vec<u8>;
+
+    #[inline(always)]
+    fn from_vec(value: Vec<u8>) -> StdResult<Self::Output> {
+        Ok(value)
+    }
+}
+
+impl KeyDeserialize for &str {
+    type Output = String;
+
+    #[inline(always)]
+    fn from_vec(value: Vec<u8>) -> StdResult<Self::Output> {
+        String::from_utf8(value).map_err(StdError::invalid_utf8)
+    }
+}
+
+impl KeyDeserialize for PkOwned {
+    type Output = PkOwned;
+
+    #[inline(always)]
+    fn from_vec(value: Vec<u8>) -> StdResult<Self::Output> {
+        Ok(PkOwned(value))
+    }
+}
+
+impl<T: Endian> KeyDeserialize for IntKey<T> {
+    type Output = T;
+
+    fn from_vec(value: Vec<u8>) -> StdResult<Self::Output> {
+        let mut buf = T::Buf::default();
+        let expected = buf.as_ref().len();
+        if value.len() != expected {
+            return Err(StdError::invalid_data_size(expected, value.len()));
+        }
+        buf.as_mut().copy_from_slice(&value);
+        Ok(T::from_be_bytes(buf))
+    }
+}
+
+impl<T: SignedEndian> KeyDeserialize for SignedIntKey<T> {
+    type Output = T;
+
+    fn from_vec(mut value: Vec<u8>) -> StdResult<Self::Output> {
+        // flip the sign bit back, then it is a normal int
+        if let Some(first) = value.first_mut() {
+            *first ^= 0x80;
+        }
+        IntKey::<T>::from_vec(value)
+    }
+}
+
+impl<T: KeyDeserialize, U: KeyDeserialize> KeyDeserialize for (T, U) {
+    type Output = (T::Output, U::Output);
+
+    const KEY_ELEMS: u16 = T::KEY_ELEMS + U::KEY_ELEMS;
+
+    fn from_vec(value: Vec<u8>) -> StdResult<Self::Output> {
+        let (t, u) = split_first_key(value, T::KEY_ELEMS)?;
+        Ok((T::from_vec(t)?, U::from_vec(u)?))
+    }
+}
+
+impl<T: KeyDeserialize, U: KeyDeserialize, V: KeyDeserialize> KeyDeserialize for (T, U, V) {
+    type Output = (T::Output, U::Output, V::Output);
+
+    const KEY_ELEMS: u16 = T::KEY_ELEMS + U::KEY_ELEMS + V::KEY_ELEMS;
+
+    fn from_vec(value: Vec<u8>) -> StdResult<Self::Output> {
+        let (t, rest) = split_first_key(value, T::KEY_ELEMS)?;
+        let (u, v) = split_first_key(rest, U::KEY_ELEMS)?;
+        Ok((T::from_vec(t)?, U::from_vec(u)?, V::from_vec(v)?))
+    }
+}
+
+impl<T: KeyDeserialize, U: KeyDeserialize, V: KeyDeserialize, W: KeyDeserialize> KeyDeserialize
+    for (T, U, V, W)
+{
+    type Output = (T::Output, U::Output, V::Output, W::Output);
+
+    const KEY_ELEMS: u16 = T::KEY_ELEMS + U::KEY_ELEMS + V::KEY_ELEMS + W::KEY_ELEMS;
+
+    fn from_vec(value: Vec<u8>) -> StdResult<Self::Output> {
+        let (t, rest) = split_first_key(value, T::KEY_ELEMS)?;
+        let (u, rest) = split_first_key(rest, U::KEY_ELEMS)?;
+        let (v, w) = split_first_key(rest, V::KEY_ELEMS)?;
+        Ok((
+            T::from_vec(t)?,
+            U::from_vec(u)?,
+            V::from_vec(v)?,
+            W::from_vec(w)?,
+        ))
+    }
+}
+
+/// Splits the first `elems` elements off a joined key, which are all length-prefixed there.
+/// The first part is returned as a standalone key (without the length prefix of its last
+/// element), followed by the rest.
+pub(crate) fn split_first_key(mut value: Vec<u8>, elems: u16) -> StdResult<(Vec<u8>, Vec<u8>)> {
+    let mut pos = 0;
+    let mut last = 0;
+    for _ in 0..elems {
+        if value.len() < pos + 2 {
+            return Err(StdError::generic_err(
+                "Composite key too short to contain a length prefix",
+            ));
+        }
+        let l = decode_length(&value[pos..pos + 2]);
+        if value.len() < pos + 2 + l {
+            return Err(StdError::generic_err(
+                "Composite key shorter than its length prefix",
+            ));
+        }
+        last = pos;
+        pos += 2 + l;
+    }
+    let rest = value.split_off(pos);
+    value.drain(last..last + 2);
+    Ok((value, rest))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{PrimaryKey, SignedI64Key, U32Key, U64Key, U8Key};
+
+    #[test]
+    fn deserialize_simple_keys() {
+        let k: &[u8] = b"foo";
+        assert_eq!(<&[u8]>::from_vec(k.joined_key()).unwrap(), b"foo".to_vec());
+
+        let k: &str = "bar";
+        assert_eq!(<&str>::from_vec(k.joined_key()).unwrap(), "bar".to_string());
+
+        let k = PkOwned(b"zoom".to_vec());
+        assert_eq!(PkOwned::from_slice(&k.joined_key()).unwrap(), k);
+    }
+
+    #[test]
+    fn deserialize_int_keys() {
+        let k: U8Key = 123u8.into();
+        assert_eq!(U8Key::from_vec(k.joined_key()).unwrap(), 123u8);
+
+        let k: U64Key = 1234567890u64.into();
+        assert_eq!(U64Key::from_vec(k.joined_key()).unwrap(), 1234567890u64);
+
+        let k = SignedI64Key::new(-42);
+        assert_eq!(SignedI64Key::from_vec(k.joined_key()).unwrap(), -42i64);
+        let k = SignedI64Key::new(i64::MAX);
+        assert_eq!(SignedI64Key::from_vec(k.joined_key()).unwrap(), i64::MAX);
+
+        // wrong length is an error, not a panic
+        let err = U32Key::from_vec(vec![1, 2, 3]).unwrap_err();
+        match err {
+            StdError::InvalidDataSize {
+                expected, actual, ..
+            } => {
+                assert_eq!(expected, 4);
+                assert_eq!(actual, 3);
+            }
+            e => panic!("Unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn deserialize_invalid_utf8() {
+        let err = <&str>::from_vec(vec![0xc3, 0x28]).unwrap_err();
+        assert!(matches!(err, StdError::InvalidUtf8 { .. }));
+    }
+
+    #[test]
+    fn deserialize_tuple_keys() {
+        type K<'a> = (&'a str, U32Key);
+        let k: K = ("owner", 15.into());
+        assert_eq!(
+            K::from_vec(k.joined_key()).unwrap(),
+            ("owner".to_string(), 15u32)
+        );
+
+        type T<'a> = (&'a [u8], U64Key, &'a str);
+        let k: T = (b"four", 555.into(), "cinco");
+        assert_eq!(
+            T::from_vec(k.joined_key()).unwrap(),
+            (b"four".to_vec(), 555u64, "cinco".to_string())
+        );
+
+        type F<'a> = (&'a str, &'a [u8], U8Key, U64Key);
+        let k: F = ("owner", b"asset", 7.into(), 123.into());
+        assert_eq!(
+            F::from_vec(k.joined_key()).unwrap(),
+            ("owner".to_string(), b"asset".to_vec(), 7u8, 123u64)
+        );
+
+        // too short to hold the length prefix
+        <(&[u8], &[u8])>::from_vec(vec![0]).unwrap_err();
+        // length prefix longer than remaining data
+        <(&[u8], &[u8])>::from_vec(vec![0, 5, 1, 2]).unwrap_err();
+    }
+
+    #[test]
+    fn deserialize_nested_tuple_keys() {
+        // nested keys are stored just like the flat tuple
+        type N<'a> = ((&'a str, &'a [u8]), (U32Key, &'a str));
+        let k: N = (("owner", b"asset"), (15.into(), "id"));
+        let flat: (&str, &[u8], U32Key, &str) = ("owner", b"asset", 15.into(), "id");
+        assert_eq!(k.joined_key(), flat.joined_key());
+        assert_eq!(4, N::KEY_ELEMS);
+
+        assert_eq!(
+            N::from_vec(k.joined_key()).unwrap(),
+            (
+                ("owner".to_string(), b"asset".to_vec()),
+                (15u32, "id".to_string())
+            )
+        );
+
+        type M<'a> = (&'a str, ((&'a str, U8Key), &'a str));
+        let k: M = ("a", (("b", 2.into()), "c"));
+        assert_eq!(
+            M::from_vec(k.joined_key()).unwrap(),
+            ("a".to_string(), (("b".to_string(), 2u8), "c".to_string()))
+        );
+    }
+}
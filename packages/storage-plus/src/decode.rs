@@ -0,0 +1,217 @@
+use std::fmt;
+
+use cosmwasm_std::{StdError, StdResult};
+
+use crate::helpers::decode_length;
+
+/// How one element of a key was encoded, so it can be shown in a readable form
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyKind {
+    /// Raw bytes, such as `&[u8]`, `Vec<u8>` or `PkOwned`. Shown as hex.
+    Bytes,
+    /// A string key. Shown quoted.
+    Str,
+    /// A big endian unsigned int key, such as `U64Key`
+    Uint,
+    /// A `SignedIntKey`, stored big endian with the sign bit flipped
+    SignedInt,
+}
+
+/// One decoded element of a key
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeyPart {
+    Bytes(Vec<u8>),
+    Str(String),
+    Uint(u128),
+    SignedInt(i128),
+}
+
+impl fmt::Display for KeyPart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyPart::Bytes(bytes) => {
+                write!(f, "0x")?;
+                bytes.iter().try_for_each(|b| write!(f, "{:02x}", b))
+            }
+            KeyPart::Str(s) => write!(f, "{:?}", s),
+            KeyPart::Uint(n) => write!(f, "{}", n),
+            KeyPart::SignedInt(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+/// A raw storage key split into its namespace and key elements.
+/// It displays as `namespace/part/part`, eg. `tokens/"owner1"/42`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodedKey {
+    pub namespace: String,
+    pub parts: Vec<KeyPart>,
+}
+
+impl fmt::Display for DecodedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.namespace)?;
+        self.parts.iter().try_for_each(|p| write!(f, "/{}", p))
+    }
+}
+
+/// KeyLayout describes the keys of one `Map` (or index): its namespace, and the kind of
+/// every element of its key type, in order. A nested tuple key in the last position is just
+/// flattened, so `(A, (B, C))` is described like `(A, B, C)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyLayout<'a> {
+    namespace: &'a str,
+    kinds: &'a [KeyKind],
+}
+
+impl<'a> KeyLayout<'a> {
+    pub const fn new(namespace: &'a str, kinds: &'a [KeyKind]) -> Self {
+        KeyLayout { namespace, kinds }
+    }
+
+    /// Splits a complete storage key (as returned by `Prefix::keys_raw` or dumped from state)
+    /// into its parts. Returns an error if it is not under this namespace or does not fit
+    /// the key kinds.
+    pub fn decode(&self, raw: &[u8]) -> StdResult<DecodedKey> {
+        let (namespace, mut rest) = split_length_prefixed(raw)?;
+        if namespace != self.namespace.as_bytes() {
+            return Err(StdError::generic_err(format!(
+                "Key is not in namespace {}",
+                self.namespace
+            )));
+        }
+
+        let mut parts = Vec::with_capacity(self.kinds.len());
+        for (i, kind) in self.kinds.iter().enumerate() {
+            // all but the last element are length-prefixed
+            let elem = if i + 1 < self.kinds.len() {
+                let (elem, tail) = split_length_prefixed(rest)?;
+                rest = tail;
+                elem
+            } else {
+                std::mem::take(&mut rest)
+            };
+            parts.push(decode_part(*kind, elem)?);
+        }
+        if !rest.is_empty() {
+            return Err(StdError::generic_err(
+                "Key has more elements than its layout",
+            ));
+        }
+
+        Ok(DecodedKey {
+            namespace: self.namespace.to_string(),
+            parts,
+        })
+    }
+}
+
+fn split_length_prefixed(raw: &[u8]) -> StdResult<(&[u8], &[u8])> {
+    if raw.len() < 2 {
+        return Err(StdError::generic_err(
+            "Composite key too short to contain a length prefix",
+        ));
+    }
+    let l = decode_length(&raw[..2]);
+    if raw.len() < 2 + l {
+        return Err(StdError::generic_err(
+            "Composite key shorter than its length prefix",
+        ));
+    }
+    Ok((&raw[2..2 + l], &raw[2 + l..]))
+}
+
+fn decode_part(kind: KeyKind, elem: &[u8]) -> StdResult<KeyPart> {
+    match kind {
+        KeyKind::Bytes => Ok(KeyPart::Bytes(elem.to_vec())),
+        KeyKind::Str => String::from_utf8(elem.to_vec())
+            .map(KeyPart::Str)
+            .map_err(StdError::invalid_utf8),
+        KeyKind::Uint => Ok(KeyPart::Uint(decode_uint(elem)?)),
+        KeyKind::SignedInt => {
+            let bits = elem.len() as u32 * 8;
+            let n = decode_uint(elem)? ^ (1 << (bits - 1));
+            // sign-extend from the size of the stored int
+            let shift = 128 - bits;
+            Ok(KeyPart::SignedInt(((n << shift) as i128) >> shift))
+        }
+    }
+}
+
+fn decode_uint(elem: &[u8]) -> StdResult<u128> {
+    match elem.len() {
+        1 | 2 | 4 | 8 | 16 => Ok(elem.iter().fold(0, |n, b| (n << 8) | *b as u128)),
+        l => Err(StdError::generic_err(format!(
+            "Invalid int key length: {}",
+            l
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::keys::PrimaryKey;
+    use crate::{Map, PkOwned, SignedI32Key, U8Key};
+
+    #[test]
+    fn decode_simple_keys() {
+        const PEOPLE: Map<&[u8], u64> = Map::new("people");
+        let layout = KeyLayout::new("people", &[KeyKind::Bytes]);
+        let decoded = layout.decode(&PEOPLE.key(b"\x01\xab")).unwrap();
+        assert_eq!(decoded.parts, vec![KeyPart::Bytes(vec![1, 0xab])]);
+        assert_eq!(decoded.to_string(), "people/0x01ab");
+
+        // wrong namespace
+        let other = KeyLayout::new("peoples", &[KeyKind::Bytes]);
+        other.decode(&PEOPLE.key(b"john")).unwrap_err();
+    }
+
+    #[test]
+    fn decode_composite_keys() {
+        const SCORES: Map<(&str, U8Key, SignedI32Key), u64> = Map::new("scores");
+        let layout = KeyLayout::new("scores", &[KeyKind::Str, KeyKind::Uint, KeyKind::SignedInt]);
+
+        let raw = SCORES.key(("alice", 7.into(), (-42).into()));
+        let decoded = layout.decode(&raw).unwrap();
+        assert_eq!(
+            decoded.parts,
+            vec![
+                KeyPart::Str("alice".to_string()),
+                KeyPart::Uint(7),
+                KeyPart::SignedInt(-42),
+            ]
+        );
+        assert_eq!(decoded.to_string(), "scores/\"alice\"/7/-42");
+
+        let raw = SCORES.key(("bob", 255.into(), i32::MAX.into()));
+        assert_eq!(
+            layout.decode(&raw).unwrap().to_string(),
+            format!("scores/\"bob\"/255/{}", i32::MAX)
+        );
+
+        // too few and too many kinds do not fit
+        let short = KeyLayout::new("scores", &[KeyKind::Str, KeyKind::Uint]);
+        short.decode(&raw).unwrap_err();
+        let long = KeyLayout::new(
+            "scores",
+            &[KeyKind::Str, KeyKind::Uint, KeyKind::Uint, KeyKind::Uint],
+        );
+        long.decode(&raw).unwrap_err();
+    }
+
+    #[test]
+    fn decode_nested_keys() {
+        let key = (
+            PkOwned(b"a".to_vec()),
+            (PkOwned(b"bc".to_vec()), PkOwned(b"d".to_vec())),
+        );
+        let raw = crate::helpers::namespaces_with_key(&[b"nested"], &key.joined_key());
+        let layout = KeyLayout::new("nested", &[KeyKind::Str, KeyKind::Str, KeyKind::Str]);
+        assert_eq!(
+            layout.decode(&raw).unwrap().to_string(),
+            "nested/\"a\"/\"bc\"/\"d\""
+        );
+    }
+}
@@ -0,0 +1,400 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::convert::TryInto;
+use std::marker::PhantomData;
+
+use cosmwasm_std::{StdError, StdResult, Storage};
+
+use crate::codec::{Codec, JsonCodec};
+use crate::helpers::{may_deserialize, namespaces_with_key};
+
+// metadata keys need to have different length than the position type (4 bytes) to prevent collisions
+const HEAD_KEY: &[u8] = b"h";
+const TAIL_KEY: &[u8] = b"t";
+
+/// Deque is a double-ended queue of typed values stored under one namespace.
+/// Only the head and tail positions are stored as metadata, so pushing and popping
+/// on either end touches only one value, no matter how long the queue is.
+///
+/// Positions are u32 that wrap around, so the deque can hold up to u32::MAX - 1 values.
+pub struct Deque<'a, T, C = JsonCodec> {
+    // prefix of the deque items
+    namespace: &'a [u8],
+    // see https://doc.rust-lang.org/std/marker/struct.PhantomData.html#unused-type-parameters for why this is needed
+    data_type: PhantomData<T>,
+    codec: PhantomData<C>,
+}
+
+impl<'a, T, C> Deque<'a, T, C> {
+    pub const fn new(namespace: &'a str) -> Self {
+        Deque {
+            namespace: namespace.as_bytes(),
+            data_type: PhantomData,
+            codec: PhantomData,
+        }
+    }
+}
+
+impl<'a, T, C> Deque<'a, T, C>
+where
+    T: Serialize + DeserializeOwned,
+    C: Codec,
+{
+    /// Adds the given value to the end of the deque
+    pub fn push_back(&self, storage: &mut dyn Storage, value: &T) -> StdResult<()> {
+        let tail = self.tail(storage)?;
+        self.assert_not_full(storage, tail)?;
+        self.set_unchecked(storage, tail, value)?;
+        self.set_tail(storage, tail.wrapping_add(1));
+        Ok(())
+    }
+
+    /// Adds the given value to the front of the deque
+    pub fn push_front(&self, storage: &mut dyn Storage, value: &T) -> StdResult<()> {
+        let tail = self.tail(storage)?;
+        self.assert_not_full(storage, tail)?;
+        let head = self.head(storage)?.wrapping_sub(1);
+        self.set_unchecked(storage, head, value)?;
+        self.set_head(storage, head);
+        Ok(())
+    }
+
+    /// Removes the last value of the deque and returns it, None if the deque is empty
+    pub fn pop_back(&self, storage: &mut dyn Storage) -> StdResult<Option<T>> {
+        let head = self.head(storage)?;
+        let tail = self.tail(storage)?;
+        if head == tail {
+            return Ok(None);
+        }
+        let pos = tail.wrapping_sub(1);
+        let value = self.remove_unchecked(storage, pos)?;
+        self.set_tail(storage, pos);
+        Ok(value)
+    }
+
+    /// Removes the first value of the deque and returns it, None if the deque is empty
+    pub fn pop_front(&self, storage: &mut dyn Storage) -> StdResult<Option<T>> {
+        let head = self.head(storage)?;
+        let tail = self.tail(storage)?;
+        if head == tail {
+            return Ok(None);
+        }
+        let value = self.remove_unchecked(storage, head)?;
+        self.set_head(storage, head.wrapping_add(1));
+        Ok(value)
+    }
+
+    /// Returns the first value of the deque without removing it
+    pub fn front(&self, storage: &dyn Storage) -> StdResult<Option<T>> {
+        if self.is_empty(storage)? {
+            return Ok(None);
+        }
+        self.get_unchecked(storage, self.head(storage)?)
+    }
+
+    /// Returns the last value of the deque without removing it
+    pub fn back(&self, storage: &dyn Storage) -> StdResult<Option<T>> {
+        if self.is_empty(storage)? {
+            return Ok(None);
+        }
+        self.get_unchecked(storage, self.tail(storage)?.wrapping_sub(1))
+    }
+
+    /// Returns the number of values in the deque
+    pub fn len(&self, storage: &dyn Storage) -> StdResult<u32> {
+        Ok(self.tail(storage)?.wrapping_sub(self.head(storage)?))
+    }
+
+    pub fn is_empty(&self, storage: &dyn Storage) -> StdResult<bool> {
+        Ok(self.len(storage)? == 0)
+    }
+
+    /// Returns the value at the given index (0 is the front), None if the index is out of bounds
+    pub fn get(&self, storage: &dyn Storage, index: u32) -> StdResult<Option<T>> {
+        if index >= self.len(storage)? {
+            return Ok(None);
+        }
+        let pos = self.head(storage)?.wrapping_add(index);
+        self.get_unchecked(storage, pos)
+            .and_then(|v| v.ok_or_else(|| StdError::not_found(format!("deque position {}", pos))))
+            .map(Some)
+    }
+
+    /// Returns an iterator over all values, front to back. Use `.rev()` to go back to front.
+    pub fn iter<'b>(&'b self, storage: &'b dyn Storage) -> StdResult<DequeIter<'a, 'b, T, C>> {
+        Ok(DequeIter {
+            deque: self,
+            storage,
+            start: self.head(storage)?,
+            end: self.tail(storage)?,
+        })
+    }
+
+    fn head(&self, storage: &dyn Storage) -> StdResult<u32> {
+        self.read_meta_key(storage, HEAD_KEY)
+    }
+
+    fn tail(&self, storage: &dyn Storage) -> StdResult<u32> {
+        self.read_meta_key(storage, TAIL_KEY)
+    }
+
+    fn set_head(&self, storage: &mut dyn Storage, value: u32) {
+        self.set_meta_key(storage, HEAD_KEY, value);
+    }
+
+    fn set_tail(&self, storage: &mut dyn Storage, value: u32) {
+        self.set_meta_key(storage, TAIL_KEY, value);
+    }
+
+    fn read_meta_key(&self, storage: &dyn Storage, key: &[u8]) -> StdResult<u32> {
+        let full_key = namespaces_with_key(&[self.namespace], key);
+        match storage.get(&full_key) {
+            Some(vec) => {
+                let bytes: [u8; 4] = vec
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| StdError::invalid_data_size(4, vec.len()))?;
+                Ok(u32::from_be_bytes(bytes))
+            }
+            None => Ok(0),
+        }
+    }
+
+    fn set_meta_key(&self, storage: &mut dyn Storage, key: &[u8], value: u32) {
+        let full_key = namespaces_with_key(&[self.namespace], key);
+        storage.set(&full_key, &value.to_be_bytes());
+    }
+
+    fn assert_not_full(&self, storage: &dyn Storage, tail: u32) -> StdResult<()> {
+        // one position must stay free, otherwise a full deque looks just like an empty one
+        if tail.wrapping_sub(self.head(storage)?) == u32::MAX {
+            return Err(StdError::generic_err("Deque is full"));
+        }
+        Ok(())
+    }
+
+    fn key(&self, pos: u32) -> Vec<u8> {
+        namespaces_with_key(&[self.namespace], &pos.to_be_bytes())
+    }
+
+    fn get_unchecked(&self, storage: &dyn Storage, pos: u32) -> StdResult<Option<T>> {
+        may_deserialize::<T, C>(&storage.get(&self.key(pos)))
+    }
+
+    fn set_unchecked(&self, storage: &mut dyn Storage, pos: u32, value: &T) -> StdResult<()> {
+        storage.set(&self.key(pos), &C::encode(value)?);
+        Ok(())
+    }
+
+    fn remove_unchecked(&self, storage: &mut dyn Storage, pos: u32) -> StdResult<Option<T>> {
+        let key = self.key(pos);
+        let value = may_deserialize::<T, C>(&storage.get(&key))?;
+        storage.remove(&key);
+        Ok(value)
+    }
+}
+
+pub struct DequeIter<'a, 'b, T, C>
+where
+    T: Serialize + DeserializeOwned,
+    C: Codec,
+{
+    deque: &'b Deque<'a, T, C>,
+    storage: &'b dyn Storage,
+    start: u32,
+    end: u32,
+}
+
+impl<'a, 'b, T, C> DequeIter<'a, 'b, T, C>
+where
+    T: Serialize + DeserializeOwned,
+    C: Codec,
+{
+    fn load(&self, pos: u32) -> StdResult<T> {
+        self.deque
+            .get_unchecked(self.storage, pos)?
+            .ok_or_else(|| StdError::not_found(format!("deque position {}", pos)))
+    }
+}
+
+impl<'a, 'b, T, C> Iterator for DequeIter<'a, 'b, T, C>
+where
+    T: Serialize + DeserializeOwned,
+    C: Codec,
+{
+    type Item = StdResult<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.start == self.end {
+            return None;
+        }
+        let item = self.load(self.start);
+        self.start = self.start.wrapping_add(1);
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.end.wrapping_sub(self.start) as usize;
+        (len, Some(len))
+    }
+}
+
+impl<'a, 'b, T, C> ExactSizeIterator for DequeIter<'a, 'b, T, C>
+where
+    T: Serialize + DeserializeOwned,
+    C: Codec,
+{
+}
+
+impl<'a, 'b, T, C> DoubleEndedIterator for DequeIter<'a, 'b, T, C>
+where
+    T: Serialize + DeserializeOwned,
+    C: Codec,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.start == self.end {
+            return None;
+        }
+        self.end = self.end.wrapping_sub(1);
+        Some(self.load(self.end))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+    struct Claim {
+        pub amount: u64,
+        pub release_at: u64,
+    }
+
+    const CLAIMS: Deque<Claim> = Deque::new("claims");
+    const NUMBERS: Deque<u32> = Deque::new("numbers");
+
+    fn claim(amount: u64) -> Claim {
+        Claim {
+            amount,
+            release_at: amount * 10,
+        }
+    }
+
+    #[test]
+    fn push_and_pop() {
+        let mut store = MockStorage::new();
+
+        assert_eq!(0, CLAIMS.len(&store).unwrap());
+        assert!(CLAIMS.is_empty(&store).unwrap());
+        assert_eq!(None, CLAIMS.pop_front(&mut store).unwrap());
+        assert_eq!(None, CLAIMS.pop_back(&mut store).unwrap());
+
+        // 0, 1, 2
+        CLAIMS.push_back(&mut store, &claim(1)).unwrap();
+        CLAIMS.push_back(&mut store, &claim(2)).unwrap();
+        CLAIMS.push_front(&mut store, &claim(0)).unwrap();
+        assert_eq!(3, CLAIMS.len(&store).unwrap());
+        assert_eq!(Some(claim(0)), CLAIMS.front(&store).unwrap());
+        assert_eq!(Some(claim(2)), CLAIMS.back(&store).unwrap());
+
+        assert_eq!(Some(claim(0)), CLAIMS.pop_front(&mut store).unwrap());
+        assert_eq!(Some(claim(2)), CLAIMS.pop_back(&mut store).unwrap());
+        assert_eq!(1, CLAIMS.len(&store).unwrap());
+        assert_eq!(Some(claim(1)), CLAIMS.pop_back(&mut store).unwrap());
+        assert!(CLAIMS.is_empty(&store).unwrap());
+        assert_eq!(None, CLAIMS.front(&store).unwrap());
+        assert_eq!(None, CLAIMS.back(&store).unwrap());
+
+        // nothing is left behind but the metadata
+        assert_eq!(None, store.get(&CLAIMS.key(0)));
+        assert_eq!(None, store.get(&CLAIMS.key(u32::MAX)));
+    }
+
+    #[test]
+    fn get_by_index() {
+        let mut store = MockStorage::new();
+
+        NUMBERS.push_back(&mut store, &20).unwrap();
+        NUMBERS.push_back(&mut store, &30).unwrap();
+        NUMBERS.push_front(&mut store, &10).unwrap();
+
+        assert_eq!(Some(10), NUMBERS.get(&store, 0).unwrap());
+        assert_eq!(Some(20), NUMBERS.get(&store, 1).unwrap());
+        assert_eq!(Some(30), NUMBERS.get(&store, 2).unwrap());
+        assert_eq!(None, NUMBERS.get(&store, 3).unwrap());
+
+        NUMBERS.pop_front(&mut store).unwrap();
+        assert_eq!(Some(20), NUMBERS.get(&store, 0).unwrap());
+        assert_eq!(None, NUMBERS.get(&store, 2).unwrap());
+    }
+
+    #[test]
+    fn iteration() {
+        let mut store = MockStorage::new();
+
+        for i in 0..5 {
+            NUMBERS.push_back(&mut store, &i).unwrap();
+        }
+        // wrap around the start of the position space
+        NUMBERS.push_front(&mut store, &100).unwrap();
+
+        let all: StdResult<Vec<_>> = NUMBERS.iter(&store).unwrap().collect();
+        assert_eq!(all.unwrap(), vec![100, 0, 1, 2, 3, 4]);
+
+        let all: StdResult<Vec<_>> = NUMBERS.iter(&store).unwrap().rev().collect();
+        assert_eq!(all.unwrap(), vec![4, 3, 2, 1, 0, 100]);
+
+        let mut iter = NUMBERS.iter(&store).unwrap();
+        assert_eq!(6, iter.len());
+        assert_eq!(Some(100), iter.next().transpose().unwrap());
+        assert_eq!(Some(4), iter.next_back().transpose().unwrap());
+        let rest: StdResult<Vec<_>> = iter.collect();
+        assert_eq!(rest.unwrap(), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn wrapping_positions() {
+        let mut store = MockStorage::new();
+
+        // start right before the wrap point
+        NUMBERS.set_head(&mut store, u32::MAX - 1);
+        NUMBERS.set_tail(&mut store, u32::MAX - 1);
+
+        for i in 0..4 {
+            NUMBERS.push_back(&mut store, &i).unwrap();
+        }
+        assert_eq!(4, NUMBERS.len(&store).unwrap());
+        assert_eq!(Some(3), NUMBERS.get(&store, 3).unwrap());
+        let all: StdResult<Vec<_>> = NUMBERS.iter(&store).unwrap().collect();
+        assert_eq!(all.unwrap(), vec![0, 1, 2, 3]);
+
+        assert_eq!(Some(0), NUMBERS.pop_front(&mut store).unwrap());
+        assert_eq!(Some(3), NUMBERS.pop_back(&mut store).unwrap());
+        assert_eq!(2, NUMBERS.len(&store).unwrap());
+    }
+
+    #[test]
+    fn full_deque_errors() {
+        let mut store = MockStorage::new();
+
+        // fake a full deque
+        NUMBERS.set_head(&mut store, 0);
+        NUMBERS.set_tail(&mut store, u32::MAX);
+        NUMBERS.push_back(&mut store, &1).unwrap_err();
+        NUMBERS.push_front(&mut store, &1).unwrap_err();
+    }
+
+    #[test]
+    fn isolated_namespaces() {
+        let mut store = MockStorage::new();
+
+        NUMBERS.push_back(&mut store, &1).unwrap();
+        let other: Deque<u32> = Deque::new("numbers2");
+        assert!(other.is_empty(&store).unwrap());
+        other.push_back(&mut store, &2).unwrap();
+        assert_eq!(Some(1), NUMBERS.pop_front(&mut store).unwrap());
+        assert_eq!(Some(2), other.pop_front(&mut store).unwrap());
+    }
+}
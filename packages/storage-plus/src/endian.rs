@@ -56,3 +56,13 @@ impl_primitives![
     i8, i16, i32, i64, i128,
     u8, u16, u32, u64, u128,
 ];
+
+/// SignedEndian marks the signed primitives. Their big-endian two's complement bytes sort
+/// negative values after positive ones, so keys flip the sign bit (see `SignedIntKey`).
+pub trait SignedEndian: Endian {}
+
+impl SignedEndian for i8 {}
+impl SignedEndian for i16 {}
+impl SignedEndian for i32 {}
+impl SignedEndian for i64 {}
+impl SignedEndian for i128 {}
@@ -7,25 +7,29 @@
 use serde::de::DeserializeOwned;
 use std::any::type_name;
 
-use cosmwasm_std::{from_slice, StdError, StdResult};
+use cosmwasm_std::{StdError, StdResult};
+
+use crate::codec::Codec;
 
 /// may_deserialize parses json bytes from storage (Option), returning Ok(None) if no data present
 ///
 /// value is an odd type, but this is meant to be easy to use with output from storage.get (Option<Vec<u8>>)
 /// and value.map(|s| s.as_slice()) seems trickier than &value
-pub(crate) fn may_deserialize<T: DeserializeOwned>(
+pub(crate) fn may_deserialize<T: DeserializeOwned, C: Codec>(
     value: &Option<Vec<u8>>,
 ) -> StdResult<Option<T>> {
     match value {
-        Some(vec) => Ok(Some(from_slice(&vec)?)),
+        Some(vec) => Ok(Some(C::decode(&vec)?)),
         None => Ok(None),
     }
 }
 
 /// must_deserialize parses json bytes from storage (Option), returning NotFound error if no data present
-pub(crate) fn must_deserialize<T: DeserializeOwned>(value: &Option<Vec<u8>>) -> StdResult<T> {
+pub(crate) fn must_deserialize<T: DeserializeOwned, C: Codec>(
+    value: &Option<Vec<u8>>,
+) -> StdResult<T> {
     match value {
-        Some(vec) => from_slice(&vec),
+        Some(vec) => C::decode(&vec),
         None => Err(StdError::not_found(type_name::<T>())),
     }
 }
@@ -90,9 +94,38 @@ pub(crate) fn decode_length(prefix: &[u8]) -> usize {
     (prefix[0] as usize) * 256 + (prefix[1] as usize)
 }
 
+/// Answers raw queries for one contract from a MockStorage, to test the query helpers
+#[cfg(test)]
+pub(crate) struct MockRawQuerier {
+    pub contract: cosmwasm_std::HumanAddr,
+    pub storage: cosmwasm_std::testing::MockStorage,
+}
+
+#[cfg(test)]
+impl cosmwasm_std::Querier for MockRawQuerier {
+    fn raw_query(&self, bin_request: &[u8]) -> cosmwasm_std::QuerierResult {
+        use cosmwasm_std::{
+            from_slice, ContractResult, Empty, QueryRequest, Storage, SystemError, SystemResult,
+            WasmQuery,
+        };
+        match from_slice::<QueryRequest<Empty>>(bin_request) {
+            Ok(QueryRequest::Wasm(WasmQuery::Raw { contract_addr, key }))
+                if contract_addr == self.contract =>
+            {
+                let value = self.storage.get(&key).unwrap_or_default();
+                SystemResult::Ok(ContractResult::Ok(value.into()))
+            }
+            _ => SystemResult::Err(SystemError::UnsupportedRequest {
+                kind: "only raw queries to the mock contract".to_string(),
+            }),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::codec::JsonCodec;
     use cosmwasm_std::{to_vec, StdError};
     use serde::{Deserialize, Serialize};
 
@@ -128,13 +161,13 @@ mod test {
         };
         let value = to_vec(&person).unwrap();
 
-        let may_parse: Option<Person> = may_deserialize(&Some(value)).unwrap();
+        let may_parse: Option<Person> = may_deserialize::<_, JsonCodec>(&Some(value)).unwrap();
         assert_eq!(may_parse, Some(person));
     }
 
     #[test]
     fn may_deserialize_handles_none() {
-        let may_parse = may_deserialize::<Person>(&None).unwrap();
+        let may_parse = may_deserialize::<Person, JsonCodec>(&None).unwrap();
         assert_eq!(may_parse, None);
     }
 
@@ -147,13 +180,13 @@ mod test {
         let value = to_vec(&person).unwrap();
         let loaded = Some(value);
 
-        let parsed: Person = must_deserialize(&loaded).unwrap();
+        let parsed: Person = must_deserialize::<_, JsonCodec>(&loaded).unwrap();
         assert_eq!(parsed, person);
     }
 
     #[test]
     fn must_deserialize_handles_none() {
-        let parsed = must_deserialize::<Person>(&None);
+        let parsed = must_deserialize::<Person, JsonCodec>(&None);
         match parsed.unwrap_err() {
             StdError::NotFound { kind, .. } => {
                 assert_eq!(kind, "cw_storage_plus::helpers::test::Person")
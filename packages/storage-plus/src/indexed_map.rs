@@ -1,13 +1,16 @@
 // this module requires iterator to be useful at all
 #![cfg(feature = "iterator")]
 
-use cosmwasm_std::{StdError, StdResult, Storage};
+use cosmwasm_std::{Order, StdError, StdResult, Storage};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
+use crate::codec::{Codec, JsonCodec};
+use crate::de::KeyDeserialize;
 use crate::indexes::Index;
-use crate::keys::{EmptyPrefix, Prefixer, PrimaryKey};
+use crate::keys::PrimaryKey;
 use crate::map::Map;
+use crate::path::Path;
 use crate::prefix::{Bound, Prefix};
 
 pub trait IndexList<T> {
@@ -16,24 +19,26 @@ pub trait IndexList<T> {
 
 /// IndexedBucket works like a bucket but has a secondary index
 /// TODO: remove traits here and make this const fn new
-pub struct IndexedMap<'a, K, T, I>
+pub struct IndexedMap<'a, K, T, I, C = JsonCodec>
 where
     K: PrimaryKey<'a>,
     T: Serialize + DeserializeOwned + Clone,
     I: IndexList<T>,
+    C: Codec,
 {
     pk_namespace: &'a [u8],
-    primary: Map<'a, K, T>,
+    primary: Map<'a, K, T, C>,
     /// This is meant to be read directly to get the proper types, like:
-    /// map.idx.owner.items(...)
+    /// map.idx.owner.prefix(...).range(...)
     pub idx: I,
 }
 
-impl<'a, K, T, I> IndexedMap<'a, K, T, I>
+impl<'a, K, T, I, C> IndexedMap<'a, K, T, I, C>
 where
     K: PrimaryKey<'a>,
     T: Serialize + DeserializeOwned + Clone,
     I: IndexList<T>,
+    C: Codec,
 {
     /// TODO: remove traits here and make this const fn new
     pub fn new(pk_namespace: &'a str, indexes: I) -> Self {
@@ -45,11 +50,12 @@ where
     }
 }
 
-impl<'a, K, T, I> IndexedMap<'a, K, T, I>
+impl<'a, K, T, I, C> IndexedMap<'a, K, T, I, C>
 where
     K: PrimaryKey<'a>,
     T: Serialize + DeserializeOwned + Clone,
     I: IndexList<T>,
+    C: Codec,
 {
     /// save will serialize the model and store, returns an error on serialization issues.
     /// this must load the old value to update the indexes properly
@@ -92,6 +98,41 @@ where
         Ok(())
     }
 
+    /// Applies many writes in one pass: `Some(data)` saves the value, `None` removes the key.
+    /// Every old value is loaded only once and used to update the indexes. Returns the
+    /// previous value of every item, in the same order.
+    pub fn update_many<It>(&self, store: &mut dyn Storage, items: It) -> StdResult<Vec<Option<T>>>
+    where
+        It: IntoIterator<Item = (K, Option<T>)>,
+    {
+        let mut olds = vec![];
+        for (key, data) in items {
+            let old = self.may_load(store, key.clone())?;
+            if data.is_some() || old.is_some() {
+                self.replace(store, key, data.as_ref(), old.as_ref())?;
+            }
+            olds.push(old);
+        }
+        Ok(olds)
+    }
+
+    /// Removes all entries from the map, along with their entries in all indexes.
+    /// Every value is loaded to find its index entries, so this is not cheap for large maps.
+    pub fn clear(&self, store: &mut dyn Storage) -> StdResult<()> {
+        // we cannot remove while iterating, so collect the data first
+        let all: Vec<_> = self
+            .no_prefix()
+            .range(store, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()?;
+        for (pk, old) in all.iter() {
+            for index in self.idx.get_indexes() {
+                index.remove(store, pk, old)?;
+            }
+            store.remove(&Path::<T>::new(self.pk_namespace, &[pk]));
+        }
+        Ok(())
+    }
+
     /// Loads the data, perform the specified action, and store the result
     /// in the database. This is shorthand for some common sequences, which may be useful.
     ///
@@ -122,38 +163,114 @@ where
         self.primary.may_load(store, key)
     }
 
+    /// has returns true if the key is set, without parsing the value
+    pub fn has(&self, store: &dyn Storage, key: K) -> bool {
+        self.primary.has(store, key)
+    }
+
+    /// Returns only the primary keys (as raw bytes, like `range`), without parsing any of the values
+    pub fn keys<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: cosmwasm_std::Order,
+    ) -> Box<dyn Iterator<Item = Vec<u8>> + 'c> {
+        self.primary.keys(store, min, max, order)
+    }
+
+    /// Returns the complete storage keys of the primary map, without parsing any of the values
+    pub fn keys_raw<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: cosmwasm_std::Order,
+    ) -> Box<dyn Iterator<Item = Vec<u8>> + 'c> {
+        self.primary.keys_raw(store, min, max, order)
+    }
+
+    /// Counts the entries between min and max, without parsing any of the values
+    pub fn count(&self, store: &dyn Storage, min: Option<Bound>, max: Option<Bound>) -> usize {
+        self.primary.count(store, min, max)
+    }
+
+    fn no_prefix(&self) -> Prefix<T> {
+        self.primary.no_prefix()
+    }
+
+    pub fn range<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: cosmwasm_std::Order,
+    ) -> Box<dyn Iterator<Item = StdResult<cosmwasm_std::KV<T>>> + 'c>
+    where
+        T: 'c,
+    {
+        self.no_prefix().range(store, min, max, order)
+    }
+
     // use prefix to scan -> range
     pub fn prefix(&self, p: K::Prefix) -> Prefix<T> {
-        Prefix::new(self.pk_namespace, &p.prefix())
+        self.primary.prefix(p)
     }
 
     // use sub_prefix to scan -> range
     pub fn sub_prefix(&self, p: K::SubPrefix) -> Prefix<T> {
-        Prefix::new(self.pk_namespace, &p.prefix())
+        self.primary.sub_prefix(p)
+    }
+
+    // use prefix_de to scan -> range_de
+    pub fn prefix_de(&self, p: K::Prefix) -> Prefix<T, K::Suffix> {
+        self.primary.prefix_de(p)
+    }
+
+    // use sub_prefix_de to scan -> range_de
+    pub fn sub_prefix_de(&self, p: K::SubPrefix) -> Prefix<T, K::SuperSuffix> {
+        self.primary.sub_prefix_de(p)
     }
 }
 
-// short-cut for simple keys, rather than .prefix(()).range(...)
-impl<'a, K, T, I> IndexedMap<'a, K, T, I>
+// typed iteration over the full primary key, this works for simple and composite keys alike
+impl<'a, K, T, I, C> IndexedMap<'a, K, T, I, C>
 where
-    K: PrimaryKey<'a>,
+    K: PrimaryKey<'a> + KeyDeserialize,
     T: Serialize + DeserializeOwned + Clone,
     I: IndexList<T>,
-    K::Prefix: EmptyPrefix,
+    C: Codec,
 {
-    // I would prefer not to copy code from Prefix, but no other way
-    // with lifetimes (create Prefix inside function and return ref = no no)
-    pub fn range<'c>(
+    fn no_prefix_de(&self) -> Prefix<T, K> {
+        self.primary.no_prefix_de()
+    }
+
+    pub fn range_de<'c>(
         &self,
         store: &'c dyn Storage,
         min: Option<Bound>,
         max: Option<Bound>,
         order: cosmwasm_std::Order,
-    ) -> Box<dyn Iterator<Item = StdResult<cosmwasm_std::KV<T>>> + 'c>
+    ) -> Box<dyn Iterator<Item = StdResult<(K::Output, T)>> + 'c>
+    where
+        T: 'c,
+        K::Output: 'c,
+    {
+        self.no_prefix_de().range_de(store, min, max, order)
+    }
+
+    pub fn keys_de<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: cosmwasm_std::Order,
+    ) -> Box<dyn Iterator<Item = StdResult<K::Output>> + 'c>
     where
         T: 'c,
+        K::Output: 'c,
     {
-        self.prefix(K::Prefix::new()).range(store, min, max, order)
+        self.no_prefix_de().keys_de(store, min, max, order)
     }
 }
 
@@ -175,7 +292,7 @@ mod test {
     }
 
     struct DataIndexes<'a> {
-        pub name: MultiIndex<'a, Data>,
+        pub name: MultiIndex<'a, Vec<u8>, Data>,
         pub age: UniqueIndex<'a, U32Key, Data>,
         pub name_lastname: UniqueIndex<'a, (PkOwned, PkOwned), Data>,
     }
@@ -201,9 +318,9 @@ mod test {
         IndexedMap::new("data", indexes)
     }
 
-    fn save_data<'a>(
+    fn save_data<'a, I: IndexList<Data>>(
         store: &mut MockStorage,
-        map: &IndexedMap<'a, &'a [u8], Data, DataIndexes<'a>>,
+        map: &IndexedMap<'a, &'a [u8], Data, I>,
     ) -> (Vec<&'a [u8]>, Vec<Data>) {
         let mut pks = vec![];
         let mut datas = vec![];
@@ -269,7 +386,7 @@ mod test {
         let count = map
             .idx
             .name
-            .all_items(&store, &index_string("Maria"))
+            .all_items(&store, index_string("Maria"))
             .unwrap()
             .len();
         assert_eq!(2, count);
@@ -281,7 +398,7 @@ mod test {
         let marias = map
             .idx
             .name
-            .all_items(&store, &index_string("Maria"))
+            .all_items(&store, index_string("Maria"))
             .unwrap();
         assert_eq!(2, marias.len());
         let (k, v) = &marias[0];
@@ -292,7 +409,7 @@ mod test {
         let count = map
             .idx
             .name
-            .all_items(&store, &index_string("Marib"))
+            .all_items(&store, index_string("Marib"))
             .unwrap()
             .len();
         assert_eq!(0, count);
@@ -301,7 +418,7 @@ mod test {
         let count = map
             .idx
             .name
-            .all_items(&store, &index_string("Mari`"))
+            .all_items(&store, index_string("Mari`"))
             .unwrap()
             .len();
         assert_eq!(0, count);
@@ -310,7 +427,7 @@ mod test {
         let count = map
             .idx
             .name
-            .all_items(&store, &index_string("Maria5"))
+            .all_items(&store, index_string("Maria5"))
             .unwrap()
             .len();
         assert_eq!(0, count);
@@ -401,7 +518,8 @@ mod test {
          -> usize {
             map.idx
                 .name
-                .pks(store, &index_string(name), None, None, Order::Ascending)
+                .prefix(index_string(name))
+                .keys(store, None, None, Order::Ascending)
                 .count()
         };
 
@@ -415,7 +533,9 @@ mod test {
         assert_eq!(name_count(&map, &store, "Mary"), 0);
 
         // remove maria 2
+        assert!(map.has(&store, pks[1]));
         map.remove(&mut store, pks[1]).unwrap();
+        assert!(!map.has(&store, pks[1]));
 
         // change john to mary
         map.update(&mut store, pks[2], |d| -> StdResult<_> {
@@ -433,6 +553,42 @@ mod test {
         assert_eq!(name_count(&map, &store, "Mary"), 1);
     }
 
+    #[test]
+    fn update_many_reflected_on_indexes() {
+        let mut store = MockStorage::new();
+        let map = build_map();
+        let (pks, datas) = save_data(&mut store, &map);
+
+        let mut mary = datas[2].clone();
+        mary.name = "Mary".to_string();
+        let olds = map
+            .update_many(
+                &mut store,
+                vec![(pks[1], None), (pks[2], Some(mary.clone())), (b"5", None)],
+            )
+            .unwrap();
+        assert_eq!(
+            olds,
+            vec![Some(datas[1].clone()), Some(datas[2].clone()), None]
+        );
+
+        let names = |name: &str| {
+            map.idx
+                .name
+                .prefix(index_string(name))
+                .keys(&store, None, None, Order::Ascending)
+                .count()
+        };
+        assert_eq!(names("Maria"), 1);
+        assert_eq!(names("John"), 0);
+        assert_eq!(names("Mary"), 1);
+
+        // the unique index of the removed item is free again
+        let age = U32Key::new(datas[1].age);
+        assert_eq!(None, map.idx.age.item(&store, age).unwrap());
+        assert_eq!(mary, map.load(&store, pks[2]).unwrap());
+    }
+
     #[test]
     fn unique_index_simple_key_range() {
         let mut store = MockStorage::new();
@@ -462,6 +618,39 @@ mod test {
         assert_eq!(datas[1], ages[1].1);
         assert_eq!(datas[2], ages[2].1);
         assert_eq!(datas[0], ages[3].1);
+
+        // the pks alone, in the same order
+        let only_pks: Vec<_> = map
+            .idx
+            .age
+            .pks(&store, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        let expected: Vec<_> = ages
+            .iter()
+            .map(|(pk, data)| (U32Key::new(data.age).joined_key(), pk.clone()))
+            .collect();
+        assert_eq!(expected, only_pks);
+
+        // the index key of the last one continues the iteration
+        let (last, _) = only_pks[1].clone();
+        let rest: Vec<_> = map
+            .idx
+            .age
+            .pks(&store, Some(Bound::Exclusive(last)), None, Order::Ascending)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert_eq!(&expected[2..], rest.as_slice());
+
+        // and a single lookup by index key gives the pk as well
+        let (pk, data) = map
+            .idx
+            .age
+            .item(&store, U32Key::new(datas[2].age))
+            .unwrap()
+            .unwrap();
+        assert_eq!(pks[2].to_vec(), pk);
+        assert_eq!(datas[2], data);
     }
 
     #[test]
@@ -492,4 +681,292 @@ mod test {
         assert_eq!(datas[0], marias[0].1);
         assert_eq!(datas[1], marias[1].1);
     }
+
+    #[test]
+    fn range_de_simple_key() {
+        let mut store = MockStorage::new();
+        let map = build_map();
+
+        // save data
+        let (pks, datas) = save_data(&mut store, &map);
+
+        let res: StdResult<Vec<_>> = map.range_de(&store, None, None, Order::Ascending).collect();
+        let all = res.unwrap();
+        assert_eq!(4, all.len());
+        for (i, (k, v)) in all.into_iter().enumerate() {
+            assert_eq!(pks[i].to_vec(), k);
+            assert_eq!(datas[i], v);
+        }
+
+        let res: StdResult<Vec<_>> = map
+            .keys_de(
+                &store,
+                None,
+                Some(Bound::exclusive(b"3".to_vec())),
+                Order::Descending,
+            )
+            .collect();
+        assert_eq!(res.unwrap(), vec![b"2".to_vec(), b"1".to_vec()]);
+    }
+
+    #[test]
+    fn keys_and_count() {
+        let mut store = MockStorage::new();
+        let map = build_map();
+
+        // save data
+        let (pks, _) = save_data(&mut store, &map);
+
+        let keys: Vec<_> = map.keys(&store, None, None, Order::Ascending).collect();
+        let expected: Vec<_> = pks.iter().map(|pk| pk.to_vec()).collect();
+        assert_eq!(keys, expected);
+
+        // index entries are not counted
+        assert_eq!(4, map.count(&store, None, None));
+        map.remove(&mut store, pks[1]).unwrap();
+        assert_eq!(3, map.count(&store, None, None));
+        assert_eq!(
+            2,
+            map.count(&store, Some(Bound::inclusive(pks[2].to_vec())), None)
+        );
+    }
+
+    #[test]
+    fn clear_removes_index_entries() {
+        let mut store = MockStorage::new();
+        let map = build_map();
+
+        // save data
+        let (pks, datas) = save_data(&mut store, &map);
+
+        map.clear(&mut store).unwrap();
+        assert_eq!(0, map.count(&store, None, None));
+        assert_eq!(None, map.may_load(&store, pks[0]).unwrap());
+
+        // all index entries are gone
+        assert_eq!(0, map.idx.name.count(&store, index_string("Maria")));
+        let ages: Vec<_> = map
+            .idx
+            .age
+            .range(&store, None, None, Order::Ascending)
+            .collect();
+        assert_eq!(0, ages.len());
+        // and only empty storage is left
+        assert_eq!(0, store.range(None, None, Order::Ascending).count());
+
+        // we can save the same data again, unique indexes don't complain
+        map.save(&mut store, pks[0], &datas[0]).unwrap();
+        assert_eq!(1, map.count(&store, None, None));
+    }
+
+    #[test]
+    fn multi_index_reads_old_layout() {
+        // entries written by MultiIndex before the index value was typed
+        const OLD_DATA: Map<&[u8], Data> = Map::new("data");
+        const OLD_NAME_IDX: Map<(&[u8], &[u8]), u32> = Map::new("data__name");
+
+        let mut store = MockStorage::new();
+        let data = Data {
+            name: "Maria".to_string(),
+            last_name: "Doe".to_string(),
+            age: 42,
+        };
+        OLD_DATA.save(&mut store, b"1", &data).unwrap();
+        OLD_NAME_IDX.save(&mut store, (b"Maria", b"1"), &1).unwrap();
+
+        let map = build_map();
+        let items = map
+            .idx
+            .name
+            .all_items(&store, index_string("Maria"))
+            .unwrap();
+        assert_eq!(items, vec![(b"1".to_vec(), data.clone())]);
+
+        // the old raw API still works
+        #[allow(deprecated)]
+        let pks: Vec<_> = map
+            .idx
+            .name
+            .pks(&store, b"Maria", None, None, Order::Ascending)
+            .collect();
+        assert_eq!(pks, vec![b"1".to_vec()]);
+        #[allow(deprecated)]
+        let items: Vec<_> = map
+            .idx
+            .name
+            .items(&store, b"Maria", None, None, Order::Descending)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert_eq!(items, vec![(b"1".to_vec(), data)]);
+
+        // and the new index removes the old entry
+        map.remove(&mut store, b"1").unwrap();
+        assert!(!OLD_NAME_IDX.has(&store, (b"Maria", b"1")));
+    }
+
+    #[test]
+    fn composite_multi_index() {
+        struct NameAgeIndexes<'a> {
+            pub name_age: MultiIndex<'a, (Vec<u8>, U32Key), Data>,
+        }
+
+        impl<'a> IndexList<Data> for NameAgeIndexes<'a> {
+            fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Data>> + '_> {
+                let v: Vec<&dyn Index<Data>> = vec![&self.name_age];
+                Box::new(v.into_iter())
+            }
+        }
+
+        let mut store = MockStorage::new();
+        let indexes = NameAgeIndexes {
+            name_age: MultiIndex::new(
+                |d| (index_string(&d.name), U32Key::new(d.age)),
+                "data",
+                "data__name_age",
+            ),
+        };
+        let map: IndexedMap<&[u8], Data, NameAgeIndexes> = IndexedMap::new("data", indexes);
+        let (pks, datas) = save_data(&mut store, &map);
+
+        // exact match on both columns
+        let found: Vec<_> = map
+            .idx
+            .name_age
+            .prefix((index_string("Maria"), U32Key::new(42)))
+            .range(&store, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert_eq!(found, vec![(pks[0].to_vec(), datas[0].clone())]);
+
+        // only the name, sorted by age
+        let found: Vec<_> = map
+            .idx
+            .name_age
+            .sub_prefix(index_string("Maria"))
+            .range(&store, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert_eq!(
+            found,
+            vec![
+                (pks[1].to_vec(), datas[1].clone()),
+                (pks[0].to_vec(), datas[0].clone()),
+            ]
+        );
+
+        // range over the age column of one name
+        let young: Vec<_> = map
+            .idx
+            .name_age
+            .sub_prefix(index_string("Maria"))
+            .range(
+                &store,
+                None,
+                Some(Bound::exclusive_prefix(U32Key::new(30))),
+                Order::Ascending,
+            )
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert_eq!(young, vec![(pks[1].to_vec(), datas[1].clone())]);
+        let old = map
+            .idx
+            .name_age
+            .sub_prefix(index_string("Maria"))
+            .keys(
+                &store,
+                Some(Bound::inclusive_prefix(U32Key::new(42))),
+                None,
+                Order::Ascending,
+            )
+            .count();
+        assert_eq!(1, old);
+
+        // the whole index, in index order (note the length prefix sorts shorter names first)
+        let all: Vec<_> = map
+            .idx
+            .name_age
+            .range(&store, None, None, Order::Descending)
+            .map(|r| r.map(|(pk, _)| pk))
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert_eq!(
+            all,
+            vec![
+                pks[3].to_vec(),
+                pks[0].to_vec(),
+                pks[1].to_vec(),
+                pks[2].to_vec()
+            ]
+        );
+    }
+
+    #[test]
+    fn paginate_indexes() {
+        let mut store = MockStorage::new();
+        let map = build_map();
+        let (pks, datas) = save_data(&mut store, &map);
+
+        // walk the unique age index backwards, two at a time
+        // ages: 12 (4), 23 (2), 32 (3), 42 (1)
+        let page = map
+            .idx
+            .age
+            .page(&store, None, 2, Order::Descending)
+            .unwrap();
+        assert_eq!(
+            page.items,
+            vec![
+                (pks[0].to_vec(), datas[0].clone()),
+                (pks[2].to_vec(), datas[2].clone()),
+            ]
+        );
+        let page = map
+            .idx
+            .age
+            .page(&store, page.next_key, 2, Order::Descending)
+            .unwrap();
+        assert_eq!(
+            page.items,
+            vec![
+                (pks[1].to_vec(), datas[1].clone()),
+                (pks[3].to_vec(), datas[3].clone()),
+            ]
+        );
+        // exactly at the end, so no cursor
+        assert_eq!(page.next_key, None);
+
+        // the same forward
+        let page = map.idx.age.page(&store, None, 3, Order::Ascending).unwrap();
+        assert_eq!(3, page.items.len());
+        assert_eq!(page.items[0].0, pks[3].to_vec());
+        let page = map
+            .idx
+            .age
+            .page(&store, page.next_key, 3, Order::Ascending)
+            .unwrap();
+        assert_eq!(page.items, vec![(pks[0].to_vec(), datas[0].clone())]);
+        assert_eq!(page.next_key, None);
+
+        // multi index, the cursor is the pk under the prefix
+        let page = map
+            .idx
+            .name
+            .page(&store, index_string("Maria"), None, 1, Order::Descending)
+            .unwrap();
+        assert_eq!(page.items, vec![(pks[1].to_vec(), datas[1].clone())]);
+        assert_eq!(page.next_key, Some(pks[1].to_vec()));
+        let page = map
+            .idx
+            .name
+            .page(
+                &store,
+                index_string("Maria"),
+                page.next_key,
+                1,
+                Order::Descending,
+            )
+            .unwrap();
+        assert_eq!(page.items, vec![(pks[0].to_vec(), datas[0].clone())]);
+        assert_eq!(page.next_key, None);
+    }
 }
@@ -0,0 +1,671 @@
+// this module requires iterator to be useful at all
+#![cfg(feature = "iterator")]
+
+use cosmwasm_std::{Order, StdError, StdResult, Storage};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::codec::{Codec, JsonCodec};
+use crate::de::KeyDeserialize;
+use crate::indexed_map::IndexList;
+use crate::iter_helpers::deserialize_kv_with;
+use crate::keys::{Prefixer, PrimaryKey};
+use crate::migrate::Progress;
+use crate::path::Path;
+use crate::prefix::{Bound, Prefix};
+use crate::snapshot::{SnapshotMap, Strategy};
+
+/// IndexedSnapshotMap works like an IndexedMap, but the primary map is a SnapshotMap,
+/// so values can also be read as they were at a past height.
+///
+/// Every index is written with the height too. A `SnapshotMultiIndex` keeps the history of
+/// its entries, so it can list them as they were at a past height, while `MultiIndex` and
+/// `UniqueIndex` only reflect the current state.
+pub struct IndexedSnapshotMap<'a, K, T, I, C = JsonCodec>
+where
+    K: PrimaryKey<'a>,
+    T: Serialize + DeserializeOwned + Clone,
+    I: IndexList<T>,
+    C: Codec,
+{
+    pk_namespace: &'a [u8],
+    primary: SnapshotMap<'a, K, T, C>,
+    /// This is meant to be read directly to get the proper types, like:
+    /// map.idx.owner.prefix(...).range(...)
+    pub idx: I,
+}
+
+impl<'a, K, T, I, C> IndexedSnapshotMap<'a, K, T, I, C>
+where
+    K: PrimaryKey<'a>,
+    T: Serialize + DeserializeOwned + Clone,
+    I: IndexList<T>,
+    C: Codec,
+{
+    /// Usage: IndexedSnapshotMap::new("data", "data__check", "data__change", Strategy::EveryBlock, indexes)
+    pub fn new(
+        pk_namespace: &'a str,
+        checkpoints: &'a str,
+        changelog: &'a str,
+        strategy: Strategy,
+        indexes: I,
+    ) -> Self {
+        IndexedSnapshotMap {
+            pk_namespace: pk_namespace.as_bytes(),
+            primary: SnapshotMap::new(pk_namespace, checkpoints, changelog, strategy),
+            idx: indexes,
+        }
+    }
+
+    /// Keeps only the last `blocks` blocks of history, see `SnapshotMap::with_retention`.
+    /// Every `SnapshotMultiIndex` should be given the same retention.
+    pub fn with_retention(self, blocks: u64) -> Self {
+        IndexedSnapshotMap {
+            primary: self.primary.with_retention(blocks),
+            ..self
+        }
+    }
+
+    pub fn add_checkpoint(&self, store: &mut dyn Storage, height: u64) -> StdResult<()> {
+        self.primary.add_checkpoint(store, height)
+    }
+
+    pub fn remove_checkpoint(&self, store: &mut dyn Storage, height: u64) -> StdResult<()> {
+        self.primary.remove_checkpoint(store, height)
+    }
+}
+
+impl<'a, K, T, I, C> IndexedSnapshotMap<'a, K, T, I, C>
+where
+    K: PrimaryKey<'a> + Prefixer<'a> + KeyDeserialize,
+    T: Serialize + DeserializeOwned + Clone,
+    I: IndexList<T>,
+    C: Codec,
+{
+    pub fn key(&self, k: K) -> Path<T, C> {
+        self.primary.key(k)
+    }
+
+    /// save will serialize the model and store, returns an error on serialization issues.
+    /// this must load the old value to update the indexes properly
+    /// if you loaded the old value earlier in the same function, use replace to avoid needless db reads
+    pub fn save(&self, store: &mut dyn Storage, key: K, data: &T, height: u64) -> StdResult<()> {
+        let old_data = self.may_load(store, key.clone())?;
+        self.replace(store, key, Some(data), old_data.as_ref(), height)
+    }
+
+    pub fn remove(&self, store: &mut dyn Storage, key: K, height: u64) -> StdResult<()> {
+        let old_data = self.may_load(store, key.clone())?;
+        self.replace(store, key, None, old_data.as_ref(), height)
+    }
+
+    /// replace writes data to key. old_data must be the current stored value (from a previous load)
+    /// and is used to properly update the index. This is used by save, replace, and update
+    /// and can be called directly if you want to optimize
+    pub fn replace(
+        &self,
+        store: &mut dyn Storage,
+        key: K,
+        data: Option<&T>,
+        old_data: Option<&T>,
+        height: u64,
+    ) -> StdResult<()> {
+        // this is the key *relative* to the primary map namespace
+        let pk = key.joined_key();
+        if let Some(old) = old_data {
+            for index in self.idx.get_indexes() {
+                index.remove_at_height(store, &pk, old, height)?;
+            }
+        }
+        if let Some(updated) = data {
+            for index in self.idx.get_indexes() {
+                index.save_at_height(store, &pk, updated, height)?;
+            }
+        }
+        // the old data goes to the changelog, so the primary map does not read it again
+        self.primary.replace(store, key, data, old_data, height)
+    }
+
+    /// Applies many writes at the given height in one pass: `Some(data)` saves the value,
+    /// `None` removes the key. Every old value is loaded only once and used to update the
+    /// indexes. Returns the previous value of every item, in the same order.
+    pub fn update_many<It>(
+        &self,
+        store: &mut dyn Storage,
+        items: It,
+        height: u64,
+    ) -> StdResult<Vec<Option<T>>>
+    where
+        It: IntoIterator<Item = (K, Option<T>)>,
+    {
+        let mut olds = vec![];
+        for (key, data) in items {
+            let old = self.may_load(store, key.clone())?;
+            if data.is_some() || old.is_some() {
+                self.replace(store, key, data.as_ref(), old.as_ref(), height)?;
+            }
+            olds.push(old);
+        }
+        Ok(olds)
+    }
+
+    /// Loads the data, perform the specified action, and store the result
+    /// in the database. This is shorthand for some common sequences, which may be useful.
+    ///
+    /// If the data exists, `action(Some(value))` is called. Otherwise `action(None)` is called.
+    pub fn update<A, E>(
+        &self,
+        store: &mut dyn Storage,
+        key: K,
+        height: u64,
+        action: A,
+    ) -> Result<T, E>
+    where
+        A: FnOnce(Option<T>) -> Result<T, E>,
+        E: From<StdError>,
+    {
+        let input = self.may_load(store, key.clone())?;
+        let old_val = input.clone();
+        let output = action(input)?;
+        self.replace(store, key, Some(&output), old_val.as_ref(), height)?;
+        Ok(output)
+    }
+
+    // Everything else, that doesn't touch indexers, is just pass-through from self.primary,
+    // thus can be used from while iterating over indexes
+
+    /// load will return an error if no data is set at the given key, or on parse error
+    pub fn load(&self, store: &dyn Storage, key: K) -> StdResult<T> {
+        self.primary.load(store, key)
+    }
+
+    /// may_load will parse the data stored at the key if present, returns Ok(None) if no data there.
+    /// returns an error on issues parsing
+    pub fn may_load(&self, store: &dyn Storage, key: K) -> StdResult<Option<T>> {
+        self.primary.may_load(store, key)
+    }
+
+    /// has returns true if the key is set, without parsing the value
+    pub fn has(&self, store: &dyn Storage, key: K) -> bool {
+        self.primary.has(store, key)
+    }
+
+    /// may_load_at_height reads the value as it was at the beginning of the given height.
+    /// Returns StdError::NotFound if that height was not checkpointed.
+    pub fn may_load_at_height(
+        &self,
+        store: &dyn Storage,
+        key: K,
+        height: u64,
+    ) -> StdResult<Option<T>> {
+        self.primary.may_load_at_height(store, key, height)
+    }
+
+    // If there is no checkpoint for that height, then we return StdError::NotFound
+    pub fn assert_checkpointed(&self, store: &dyn Storage, height: u64) -> StdResult<()> {
+        self.primary.assert_checkpointed(store, height)
+    }
+
+    /// Removes the history written below `before_height`, visiting at most `limit` keys,
+    /// see `SnapshotMap::prune`. This only prunes the primary map, every `SnapshotMultiIndex`
+    /// is pruned with its own `prune`.
+    pub fn prune(
+        &self,
+        store: &mut dyn Storage,
+        before_height: u64,
+        start_after: Option<Vec<u8>>,
+        limit: usize,
+    ) -> StdResult<Progress> {
+        self.primary.prune(store, before_height, start_after, limit)
+    }
+
+    /// Returns only the primary keys (as raw bytes, like `range`), without parsing any of the values
+    pub fn keys<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = Vec<u8>> + 'c> {
+        self.primary.keys(store, min, max, order)
+    }
+
+    /// Returns the complete storage keys of the primary map, without parsing any of the values
+    pub fn keys_raw<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = Vec<u8>> + 'c> {
+        self.primary.keys_raw(store, min, max, order)
+    }
+
+    /// Counts the current entries between min and max, without parsing any of the values
+    pub fn count(&self, store: &dyn Storage, min: Option<Bound>, max: Option<Bound>) -> usize {
+        self.primary.count(store, min, max)
+    }
+
+    fn no_prefix(&self) -> Prefix<T> {
+        Prefix::new_de_fn(
+            self.pk_namespace,
+            &[],
+            self.pk_namespace,
+            deserialize_kv_with::<T, C>,
+        )
+    }
+
+    fn no_prefix_de(&self) -> Prefix<T, K> {
+        Prefix::new_de_fn(
+            self.pk_namespace,
+            &[],
+            self.pk_namespace,
+            deserialize_kv_with::<T, C>,
+        )
+    }
+
+    pub fn range<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = StdResult<cosmwasm_std::KV<T>>> + 'c>
+    where
+        T: 'c,
+    {
+        self.no_prefix().range(store, min, max, order)
+    }
+
+    // use prefix to scan -> range
+    pub fn prefix(&self, p: K::Prefix) -> Prefix<T> {
+        Prefix::new_de_fn(
+            self.pk_namespace,
+            &p.prefix(),
+            self.pk_namespace,
+            deserialize_kv_with::<T, C>,
+        )
+    }
+
+    // use sub_prefix to scan -> range
+    pub fn sub_prefix(&self, p: K::SubPrefix) -> Prefix<T> {
+        Prefix::new_de_fn(
+            self.pk_namespace,
+            &p.prefix(),
+            self.pk_namespace,
+            deserialize_kv_with::<T, C>,
+        )
+    }
+
+    // use prefix_de to scan -> range_de
+    pub fn prefix_de(&self, p: K::Prefix) -> Prefix<T, K::Suffix> {
+        Prefix::new_de_fn(
+            self.pk_namespace,
+            &p.prefix(),
+            self.pk_namespace,
+            deserialize_kv_with::<T, C>,
+        )
+    }
+
+    // use sub_prefix_de to scan -> range_de
+    pub fn sub_prefix_de(&self, p: K::SubPrefix) -> Prefix<T, K::SuperSuffix> {
+        Prefix::new_de_fn(
+            self.pk_namespace,
+            &p.prefix(),
+            self.pk_namespace,
+            deserialize_kv_with::<T, C>,
+        )
+    }
+
+    pub fn range_de<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = StdResult<(K::Output, T)>> + 'c>
+    where
+        T: 'c,
+        K::Output: 'c,
+    {
+        self.no_prefix_de().range_de(store, min, max, order)
+    }
+
+    pub fn keys_de<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = StdResult<K::Output>> + 'c>
+    where
+        T: 'c,
+        K::Output: 'c,
+    {
+        self.no_prefix_de().keys_de(store, min, max, order)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::indexes::{index_string, Index, MultiIndex, SnapshotMultiIndex, UniqueIndex};
+    use crate::{IndexedMap, PkOwned, U32Key};
+    use cosmwasm_std::testing::MockStorage;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+    struct Data {
+        pub name: String,
+        pub age: u32,
+    }
+
+    struct DataIndexes<'a> {
+        pub name: MultiIndex<'a, Vec<u8>, Data>,
+        pub age: UniqueIndex<'a, U32Key, Data>,
+    }
+
+    impl<'a> IndexList<Data> for DataIndexes<'a> {
+        fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Data>> + '_> {
+            let v: Vec<&dyn Index<Data>> = vec![&self.name, &self.age];
+            Box::new(v.into_iter())
+        }
+    }
+
+    fn build_map<'a>() -> IndexedSnapshotMap<'a, &'a [u8], Data, DataIndexes<'a>> {
+        let indexes = DataIndexes {
+            name: MultiIndex::new(|d| index_string(&d.name), "data", "data__name"),
+            age: UniqueIndex::new(|d| U32Key::new(d.age), "data__age"),
+        };
+        IndexedSnapshotMap::new(
+            "data",
+            "data__check",
+            "data__change",
+            Strategy::EveryBlock,
+            indexes,
+        )
+    }
+
+    struct HistoryIndexes<'a> {
+        pub age: SnapshotMultiIndex<'a, U32Key, Data>,
+    }
+
+    impl<'a> IndexList<Data> for HistoryIndexes<'a> {
+        fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Data>> + '_> {
+            let v: Vec<&dyn Index<Data>> = vec![&self.age];
+            Box::new(v.into_iter())
+        }
+    }
+
+    fn build_history_map<'a>(
+        strategy: Strategy,
+    ) -> IndexedSnapshotMap<'a, &'a [u8], Data, HistoryIndexes<'a>> {
+        let indexes = HistoryIndexes {
+            age: SnapshotMultiIndex::new(
+                |d| U32Key::new(d.age),
+                "people",
+                "people__age",
+                "people__check",
+                "people__age__change",
+                strategy,
+            ),
+        };
+        IndexedSnapshotMap::new(
+            "people",
+            "people__check",
+            "people__change",
+            strategy,
+            indexes,
+        )
+    }
+
+    fn data(name: &str, age: u32) -> Data {
+        Data {
+            name: name.to_string(),
+            age,
+        }
+    }
+
+    #[test]
+    fn indexes_follow_current_state() {
+        let mut store = MockStorage::new();
+        let map = build_map();
+
+        map.save(&mut store, b"1", &data("Maria", 42), 1).unwrap();
+        map.save(&mut store, b"2", &data("Maria", 23), 1).unwrap();
+        map.save(&mut store, b"3", &data("John", 32), 2).unwrap();
+
+        // unique constraint is enforced
+        map.save(&mut store, b"4", &data("Jim", 42), 2).unwrap_err();
+
+        assert_eq!(
+            2,
+            map.idx
+                .name
+                .all_items(&store, index_string("Maria"))
+                .unwrap()
+                .len()
+        );
+
+        // change the name, index is updated
+        map.update(&mut store, b"2", 3, |d| -> StdResult<_> {
+            let mut d = d.unwrap();
+            d.name = "John".to_string();
+            Ok(d)
+        })
+        .unwrap();
+        assert_eq!(1, map.idx.name.count(&store, index_string("Maria")));
+        assert_eq!(2, map.idx.name.count(&store, index_string("John")));
+
+        // remove clears the indexes, the age can be reused
+        map.remove(&mut store, b"1", 4).unwrap();
+        assert_eq!(0, map.idx.name.count(&store, index_string("Maria")));
+        assert_eq!(None, map.idx.age.item(&store, U32Key::new(42)).unwrap());
+        map.save(&mut store, b"4", &data("Jim", 42), 4).unwrap();
+
+        let keys: Vec<_> = map.keys_de(&store, None, None, Order::Ascending).collect();
+        assert_eq!(
+            keys,
+            vec![Ok(b"2".to_vec()), Ok(b"3".to_vec()), Ok(b"4".to_vec())]
+        );
+        assert_eq!(3, map.count(&store, None, None));
+    }
+
+    #[test]
+    fn load_at_height() {
+        let mut store = MockStorage::new();
+        let map = build_map();
+
+        map.save(&mut store, b"1", &data("Maria", 42), 1).unwrap();
+        map.update(&mut store, b"1", 3, |d| -> StdResult<_> {
+            let mut d = d.unwrap();
+            d.age = 43;
+            Ok(d)
+        })
+        .unwrap();
+        map.remove(&mut store, b"1", 5).unwrap();
+
+        assert_eq!(None, map.may_load_at_height(&store, b"1", 1).unwrap());
+        assert_eq!(
+            Some(data("Maria", 42)),
+            map.may_load_at_height(&store, b"1", 3).unwrap()
+        );
+        assert_eq!(
+            Some(data("Maria", 43)),
+            map.may_load_at_height(&store, b"1", 5).unwrap()
+        );
+        assert_eq!(None, map.may_load_at_height(&store, b"1", 6).unwrap());
+        assert_eq!(None, map.may_load(&store, b"1").unwrap());
+        assert_eq!(None, map.idx.age.item(&store, U32Key::new(43)).unwrap());
+    }
+
+    #[test]
+    fn update_many_keeps_history_and_indexes() {
+        let mut store = MockStorage::new();
+        let map = build_map();
+
+        map.save(&mut store, b"1", &data("Maria", 42), 1).unwrap();
+        let olds = map
+            .update_many(
+                &mut store,
+                vec![
+                    (b"1".as_ref(), None),
+                    (b"2", Some(data("John", 42))),
+                    (b"3", None),
+                ],
+                3,
+            )
+            .unwrap();
+        assert_eq!(olds, vec![Some(data("Maria", 42)), None, None]);
+
+        assert_eq!(0, map.idx.name.count(&store, index_string("Maria")));
+        assert_eq!(1, map.idx.name.count(&store, index_string("John")));
+        assert_eq!(
+            Some(data("Maria", 42)),
+            map.may_load_at_height(&store, b"1", 3).unwrap()
+        );
+        assert_eq!(None, map.may_load_at_height(&store, b"2", 3).unwrap());
+        assert_eq!(
+            Some(data("John", 42)),
+            map.may_load_at_height(&store, b"2", 4).unwrap()
+        );
+    }
+
+    #[test]
+    fn index_at_height() {
+        let mut store = MockStorage::new();
+        let map = build_history_map(Strategy::EveryBlock);
+
+        map.save(&mut store, b"1", &data("Maria", 42), 1).unwrap();
+        map.save(&mut store, b"22", &data("John", 42), 1).unwrap();
+        map.save(&mut store, b"3", &data("Jim", 20), 2).unwrap();
+        // John gets older, Maria leaves, Jim changes only his name
+        map.update(&mut store, b"22", 3, |d| -> StdResult<_> {
+            let mut d = d.unwrap();
+            d.age = 43;
+            Ok(d)
+        })
+        .unwrap();
+        map.remove(&mut store, b"1", 4).unwrap();
+        map.save(&mut store, b"3", &data("Jimmy", 20), 4).unwrap();
+
+        let age = |height| {
+            map.idx
+                .age
+                .prefix_at_height(&store, U32Key::new(42), Order::Ascending, height)
+                .unwrap()
+        };
+        assert_eq!(age(1), Vec::<Vec<u8>>::new());
+        assert_eq!(age(2), vec![b"1".to_vec(), b"22".to_vec()]);
+        assert_eq!(age(4), vec![b"1".to_vec()]);
+        assert_eq!(age(5), Vec::<Vec<u8>>::new());
+
+        // everyone by age, oldest first
+        let by_age = |height| {
+            map.idx
+                .age
+                .range_at_height(&store, None, None, Order::Descending, height)
+                .unwrap()
+        };
+        assert_eq!(
+            by_age(3),
+            vec![
+                (42, b"22".to_vec()),
+                (42, b"1".to_vec()),
+                (20, b"3".to_vec())
+            ]
+        );
+        assert_eq!(
+            by_age(4),
+            vec![
+                (43, b"22".to_vec()),
+                (42, b"1".to_vec()),
+                (20, b"3".to_vec())
+            ]
+        );
+        assert_eq!(by_age(5), vec![(43, b"22".to_vec()), (20, b"3".to_vec())]);
+        // the values at that height come from the map
+        assert_eq!(
+            Some(data("Jim", 20)),
+            map.may_load_at_height(&store, b"3", 4).unwrap()
+        );
+
+        // bounds apply to the raw index keys, which end with the pk
+        let min = Bound::Inclusive((U32Key::new(42), PkOwned(vec![])).joined_key());
+        let older = map
+            .idx
+            .age
+            .range_at_height(&store, Some(min), None, Order::Ascending, 4)
+            .unwrap();
+        assert_eq!(older, vec![(42, b"1".to_vec()), (43, b"22".to_vec())]);
+
+        // the current entries are the same as in a MultiIndex
+        let current: Vec<_> = map
+            .idx
+            .age
+            .prefix(U32Key::new(43))
+            .keys(&store, None, None, Order::Ascending)
+            .collect();
+        assert_eq!(current, vec![b"22".to_vec()]);
+
+        // history of the index is pruned separately
+        map.idx.age.prune(&mut store, 4, None, 10).unwrap();
+        map.idx
+            .age
+            .prefix_at_height(&store, U32Key::new(42), Order::Ascending, 3)
+            .unwrap_err();
+        let at_4 = map
+            .idx
+            .age
+            .prefix_at_height(&store, U32Key::new(42), Order::Ascending, 4)
+            .unwrap();
+        assert_eq!(at_4, vec![b"1".to_vec()]);
+        assert_eq!(
+            Some(data("John", 42)),
+            map.may_load_at_height(&store, b"22", 3).unwrap()
+        );
+    }
+
+    #[test]
+    fn index_at_selected_height() {
+        let mut store = MockStorage::new();
+        let map = build_history_map(Strategy::Selected);
+
+        map.save(&mut store, b"1", &data("Maria", 42), 1).unwrap();
+        map.add_checkpoint(&mut store, 2).unwrap();
+        map.save(&mut store, b"2", &data("John", 42), 2).unwrap();
+        map.remove(&mut store, b"1", 3).unwrap();
+
+        // the index shares the checkpoints of the map
+        let at_2 = map
+            .idx
+            .age
+            .prefix_at_height(&store, U32Key::new(42), Order::Ascending, 2)
+            .unwrap();
+        assert_eq!(at_2, vec![b"1".to_vec()]);
+        map.idx
+            .age
+            .prefix_at_height(&store, U32Key::new(42), Order::Ascending, 3)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn history_index_needs_height() {
+        let mut store = MockStorage::new();
+        let map: IndexedMap<&[u8], Data, HistoryIndexes> = IndexedMap::new(
+            "people",
+            HistoryIndexes {
+                age: SnapshotMultiIndex::new(
+                    |d| U32Key::new(d.age),
+                    "people",
+                    "people__age",
+                    "people__check",
+                    "people__age__change",
+                    Strategy::EveryBlock,
+                ),
+            },
+        );
+        map.save(&mut store, b"1", &data("Maria", 42)).unwrap_err();
+    }
+}
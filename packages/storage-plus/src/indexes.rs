@@ -4,12 +4,20 @@
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{from_slice, Binary, Order, StdError, StdResult, Storage, KV};
+use cosmwasm_std::{Order, StdError, StdResult, Storage, KV};
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
 
-use crate::keys::EmptyPrefix;
+use crate::codec::{Codec, JsonCodec};
+use crate::de::{split_first_key, KeyDeserialize};
+use crate::helpers::namespaces_with_key;
+use crate::keys::U64Key;
 use crate::map::Map;
+use crate::migrate::Progress;
 use crate::prefix::range_with_prefix;
-use crate::{Bound, PkOwned, Prefix, Prefixer, PrimaryKey};
+use crate::snapshot::{ChangeSet, SnapshotMap, Strategy};
+use crate::{Bound, Page, PkOwned, Prefix, Prefixer, PrimaryKey};
 
 /// MARKER is stored in the multi-index as value, but we only look at the key (which is pk)
 const MARKER: u32 = 1;
@@ -36,46 +44,207 @@ where
 {
     fn save(&self, store: &mut dyn Storage, pk: &[u8], data: &T) -> StdResult<()>;
     fn remove(&self, store: &mut dyn Storage, pk: &[u8], old_data: &T) -> StdResult<()>;
+
+    /// Called by `IndexedSnapshotMap` instead of `save`, with the height of the write.
+    /// Indexes without history, like `MultiIndex` and `UniqueIndex`, just update the current state.
+    fn save_at_height(
+        &self,
+        store: &mut dyn Storage,
+        pk: &[u8],
+        data: &T,
+        _height: u64,
+    ) -> StdResult<()> {
+        self.save(store, pk, data)
+    }
+
+    /// Called by `IndexedSnapshotMap` instead of `remove`, with the height of the write
+    fn remove_at_height(
+        &self,
+        store: &mut dyn Storage,
+        pk: &[u8],
+        old_data: &T,
+        _height: u64,
+    ) -> StdResult<()> {
+        self.remove(store, pk, old_data)
+    }
 }
 
-pub struct MultiIndex<'a, T> {
-    index: fn(&T) -> Vec<u8>,
-    idx_map: Map<'a, (&'a [u8], &'a [u8]), u32>,
+/// MultiIndex stores (namespace, index_name, idx_value, pk) -> MARKER, which allows many
+/// entries with the same index value. The index value `IK` is any `PrimaryKey`, like `Vec<u8>`
+/// or `(Vec<u8>, U64Key)`. The pk is always appended to it by the index itself, so all entries
+/// for one index value can be found with `prefix`, and the leading columns of a composite
+/// index value with `sub_prefix`.
+///
+/// `C` must be the codec of the map this indexes, as it is used to load the data.
+pub struct MultiIndex<'a, IK, T, C = JsonCodec> {
+    index: fn(&T) -> IK,
+    idx_namespace: &'a [u8],
+    idx_map: Map<'a, (IK, PkOwned), u32>,
     // note, we collapse the pk - combining everything under the namespace - even if it is composite
-    pk_map: Map<'a, &'a [u8], T>,
+    pk_namespace: &'a [u8],
+    codec: PhantomData<C>,
 }
 
-impl<'a, T> MultiIndex<'a, T> {
+impl<'a, IK, T, C> MultiIndex<'a, IK, T, C> {
     // TODO: make this a const fn
-    pub fn new(idx_fn: fn(&T) -> Vec<u8>, pk_namespace: &'a str, idx_namespace: &'a str) -> Self {
+    /// Usage:
+    /// MultiIndex::new(|d: &Data| (d.owner.to_vec(), U64Key::new(d.expires)), "data", "data__owner_expires")
+    pub fn new(idx_fn: fn(&T) -> IK, pk_namespace: &'a str, idx_namespace: &'a str) -> Self {
         MultiIndex {
             index: idx_fn,
-            pk_map: Map::new(pk_namespace),
+            idx_namespace: idx_namespace.as_bytes(),
             idx_map: Map::new(idx_namespace),
+            pk_namespace: pk_namespace.as_bytes(),
+            codec: PhantomData,
         }
     }
 }
 
-impl<'a, T> Index<T> for MultiIndex<'a, T>
+impl<'a, IK, T, C> Index<T> for MultiIndex<'a, IK, T, C>
 where
     T: Serialize + DeserializeOwned + Clone,
+    IK: PrimaryKey<'a> + Prefixer<'a> + KeyDeserialize,
+    C: Codec,
 {
     fn save(&self, store: &mut dyn Storage, pk: &[u8], data: &T) -> StdResult<()> {
         let idx = (self.index)(data);
-        self.idx_map.save(store, (&idx, &pk), &MARKER)
+        self.idx_map
+            .save(store, (idx, PkOwned(pk.to_vec())), &MARKER)
     }
 
     fn remove(&self, store: &mut dyn Storage, pk: &[u8], old_data: &T) -> StdResult<()> {
         let idx = (self.index)(old_data);
-        self.idx_map.remove(store, (&idx, &pk));
+        self.idx_map.remove(store, (idx, PkOwned(pk.to_vec())));
         Ok(())
     }
 }
 
-impl<'a, T> MultiIndex<'a, T>
+/// Loads the data for an index entry, whose remaining key is only the pk
+fn deserialize_multi_kv<T: DeserializeOwned, C: Codec>(
+    store: &dyn Storage,
+    pk_namespace: &[u8],
+    kv: KV,
+) -> StdResult<KV<T>> {
+    let (pk, _) = kv;
+    let full_key = namespaces_with_key(&[pk_namespace], &pk);
+    let v = store
+        .get(&full_key)
+        .ok_or_else(|| StdError::generic_err("pk not found"))?;
+    let v = C::decode::<T>(&v)?;
+    Ok((pk, v))
+}
+
+/// Like `deserialize_multi_kv`, but the remaining key still starts with the index columns `S`
+fn deserialize_multi_kv_after<T: DeserializeOwned, C: Codec, S: KeyDeserialize>(
+    store: &dyn Storage,
+    pk_namespace: &[u8],
+    kv: KV,
+) -> StdResult<KV<T>> {
+    let (key, v) = kv;
+    let (_, pk) = split_first_key(key, S::KEY_ELEMS)?;
+    deserialize_multi_kv::<T, C>(store, pk_namespace, (pk, v))
+}
+
+impl<'a, IK, T, C> MultiIndex<'a, IK, T, C>
 where
     T: Serialize + DeserializeOwned + Clone,
+    IK: PrimaryKey<'a> + Prefixer<'a> + KeyDeserialize,
+    C: Codec,
 {
+    fn no_prefix(&self) -> Prefix<T> {
+        Prefix::new_de_fn(
+            self.idx_namespace,
+            &[],
+            self.pk_namespace,
+            deserialize_multi_kv_after::<T, C, IK>,
+        )
+    }
+
+    /// All entries with the given index value, `range` returns (pk, data) sorted by pk
+    pub fn prefix(&self, p: IK) -> Prefix<T> {
+        Prefix::new_de_fn(
+            self.idx_namespace,
+            &p.prefix(),
+            self.pk_namespace,
+            deserialize_multi_kv::<T, C>,
+        )
+    }
+
+    /// All entries with the given value of the leading index columns.
+    /// `range` returns (pk, data) sorted by the last index column, then pk.
+    pub fn sub_prefix(&self, p: IK::Prefix) -> Prefix<T> {
+        Prefix::new_de_fn(
+            self.idx_namespace,
+            &p.prefix(),
+            self.pk_namespace,
+            deserialize_multi_kv_after::<T, C, IK::Suffix>,
+        )
+    }
+
+    /// Iterates over all entries in index order, returning (pk, data)
+    pub fn range<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = StdResult<KV<T>>> + 'c>
+    where
+        T: 'c,
+    {
+        self.no_prefix().range(store, min, max, order)
+    }
+
+    /// Loads one page of the entries with the given index value, in either order.
+    /// Pass the returned `next_key` as `start_after` to get the next page.
+    pub fn page(
+        &self,
+        store: &dyn Storage,
+        p: IK,
+        start_after: Option<Vec<u8>>,
+        limit: usize,
+        order: Order,
+    ) -> StdResult<Page<T>> {
+        self.prefix(p).page(store, start_after, limit, order)
+    }
+
+    #[cfg(test)]
+    pub fn count(&self, store: &dyn Storage, p: IK) -> usize {
+        self.prefix(p).count(store, None, None)
+    }
+
+    #[cfg(test)]
+    pub fn all_pks(&self, store: &dyn Storage, p: IK) -> Vec<Vec<u8>> {
+        self.prefix(p)
+            .keys(store, None, None, Order::Ascending)
+            .collect()
+    }
+
+    #[cfg(test)]
+    pub fn all_items(&self, store: &dyn Storage, p: IK) -> StdResult<Vec<KV<T>>> {
+        self.prefix(p)
+            .range(store, None, None, Order::Ascending)
+            .collect()
+    }
+}
+
+// the raw-bytes API from before typed index values, for single column indexes
+impl<'a, IK, T, C> MultiIndex<'a, IK, T, C>
+where
+    T: Serialize + DeserializeOwned + Clone,
+    IK: PrimaryKey<'a, Prefix = ()>,
+    C: Codec,
+{
+    fn raw_prefix(&self, idx: &[u8]) -> Prefix<T> {
+        Prefix::new_de_fn(
+            self.idx_namespace,
+            &[idx],
+            self.pk_namespace,
+            deserialize_multi_kv::<T, C>,
+        )
+    }
+
+    #[deprecated(note = "use prefix(idx).keys(..) instead")]
     pub fn pks<'c>(
         &self,
         store: &'c dyn Storage,
@@ -84,58 +253,306 @@ where
         max: Option<Bound>,
         order: Order,
     ) -> Box<dyn Iterator<Item = Vec<u8>> + 'c> {
-        let prefix = self.idx_map.prefix(idx);
-        let mapped = range_with_prefix(store, &prefix, min, max, order).map(|(k, _)| k);
-        Box::new(mapped)
+        self.raw_prefix(idx).keys(store, min, max, order)
     }
 
-    /// returns all items that match this secondary index, always by pk Ascending
+    /// returns all items that match this secondary index, sorted by pk in the given order
+    #[deprecated(note = "use prefix(idx).range(..) instead")]
     pub fn items<'c>(
-        &'c self,
+        &self,
         store: &'c dyn Storage,
         idx: &[u8],
         min: Option<Bound>,
         max: Option<Bound>,
         order: Order,
-    ) -> Box<dyn Iterator<Item = StdResult<KV<T>>> + 'c> {
-        let mapped = self.pks(store, idx, min, max, order).map(move |pk| {
-            let v = self.pk_map.load(store, &pk)?;
-            Ok((pk, v))
-        });
-        Box::new(mapped)
+    ) -> Box<dyn Iterator<Item = StdResult<KV<T>>> + 'c>
+    where
+        T: 'c,
+    {
+        self.raw_prefix(idx).range(store, min, max, order)
     }
+}
 
-    #[cfg(test)]
-    pub fn count<'c>(&self, store: &'c dyn Storage, idx: &[u8]) -> usize {
-        self.pks(store, idx, None, None, Order::Ascending).count()
+/// SnapshotMultiIndex is a `MultiIndex` that also keeps the history of its entries, for use in
+/// an `IndexedSnapshotMap`. The current entries are stored just like in a `MultiIndex`, so the
+/// same queries work on them, and every change goes to a changelog with the height of the write.
+/// `prefix_at_height` and `range_at_height` then list the entries as they were at a past height.
+///
+/// It must be given the checkpoints namespace and the strategy of the map it indexes (and the same
+/// retention, if any), but its own changelog. That changelog is pruned with `prune` on the index.
+///
+/// It only works inside an `IndexedSnapshotMap`, as every write needs a height.
+pub struct SnapshotMultiIndex<'a, IK, T, C = JsonCodec> {
+    current: MultiIndex<'a, IK, T, C>,
+    snapshots: SnapshotMap<'a, (IK, PkOwned), u32>,
+    changelog_namespace: &'a [u8],
+}
+
+impl<'a, IK, T, C> SnapshotMultiIndex<'a, IK, T, C> {
+    /// Usage:
+    /// SnapshotMultiIndex::new(|d: &Data| U64Key::new(d.weight), "data", "data__weight", "data__check", "data__weight__change", Strategy::EveryBlock)
+    pub fn new(
+        idx_fn: fn(&T) -> IK,
+        pk_namespace: &'a str,
+        idx_namespace: &'a str,
+        checkpoints: &'a str,
+        changelog: &'a str,
+        strategy: Strategy,
+    ) -> Self {
+        SnapshotMultiIndex {
+            current: MultiIndex::new(idx_fn, pk_namespace, idx_namespace),
+            snapshots: SnapshotMap::new(idx_namespace, checkpoints, changelog, strategy),
+            changelog_namespace: changelog.as_bytes(),
+        }
     }
 
-    #[cfg(test)]
-    pub fn all_pks<'c>(&self, store: &'c dyn Storage, idx: &[u8]) -> Vec<Vec<u8>> {
-        self.pks(store, idx, None, None, Order::Ascending).collect()
+    /// Keeps only the last `blocks` blocks of history, see `SnapshotMap::with_retention`
+    pub fn with_retention(self, blocks: u64) -> Self {
+        SnapshotMultiIndex {
+            snapshots: self.snapshots.with_retention(blocks),
+            ..self
+        }
     }
+}
 
-    #[cfg(test)]
-    pub fn all_items<'c>(&self, store: &'c dyn Storage, idx: &[u8]) -> StdResult<Vec<KV<T>>> {
-        self.items(store, idx, None, None, Order::Ascending)
+fn missing_height() -> StdError {
+    StdError::generic_err(
+        "SnapshotMultiIndex must be written with a height, use an IndexedSnapshotMap",
+    )
+}
+
+impl<'a, IK, T, C> Index<T> for SnapshotMultiIndex<'a, IK, T, C>
+where
+    T: Serialize + DeserializeOwned + Clone,
+    IK: PrimaryKey<'a> + Prefixer<'a> + KeyDeserialize,
+    C: Codec,
+{
+    fn save(&self, _store: &mut dyn Storage, _pk: &[u8], _data: &T) -> StdResult<()> {
+        Err(missing_height())
+    }
+
+    fn remove(&self, _store: &mut dyn Storage, _pk: &[u8], _old_data: &T) -> StdResult<()> {
+        Err(missing_height())
+    }
+
+    fn save_at_height(
+        &self,
+        store: &mut dyn Storage,
+        pk: &[u8],
+        data: &T,
+        height: u64,
+    ) -> StdResult<()> {
+        let idx = (self.current.index)(data);
+        self.snapshots
+            .save(store, (idx, PkOwned(pk.to_vec())), &MARKER, height)
+    }
+
+    fn remove_at_height(
+        &self,
+        store: &mut dyn Storage,
+        pk: &[u8],
+        old_data: &T,
+        height: u64,
+    ) -> StdResult<()> {
+        let idx = (self.current.index)(old_data);
+        self.snapshots
+            .remove(store, (idx, PkOwned(pk.to_vec())), height)
+    }
+}
+
+fn in_bounds(key: &[u8], min: &Option<Bound>, max: &Option<Bound>) -> bool {
+    let above = match min {
+        Some(Bound::Inclusive(m)) => key >= m.as_slice(),
+        Some(Bound::Exclusive(m)) => key > m.as_slice(),
+        None => true,
+    };
+    let below = match max {
+        Some(Bound::Inclusive(m)) => key <= m.as_slice(),
+        Some(Bound::Exclusive(m)) => key < m.as_slice(),
+        None => true,
+    };
+    above && below
+}
+
+impl<'a, IK, T, C> SnapshotMultiIndex<'a, IK, T, C>
+where
+    T: Serialize + DeserializeOwned + Clone,
+    IK: PrimaryKey<'a> + Prefixer<'a> + KeyDeserialize,
+    C: Codec,
+{
+    /// All current entries with the given index value, see `MultiIndex::prefix`
+    pub fn prefix(&self, p: IK) -> Prefix<T> {
+        self.current.prefix(p)
+    }
+
+    /// All current entries with the given value of the leading index columns,
+    /// see `MultiIndex::sub_prefix`
+    pub fn sub_prefix(&self, p: IK::Prefix) -> Prefix<T> {
+        self.current.sub_prefix(p)
+    }
+
+    /// Iterates over all current entries in index order, returning (pk, data)
+    pub fn range<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = StdResult<KV<T>>> + 'c>
+    where
+        T: 'c,
+    {
+        self.current.range(store, min, max, order)
+    }
+
+    /// Loads one page of the current entries with the given index value, see `MultiIndex::page`
+    pub fn page(
+        &self,
+        store: &dyn Storage,
+        p: IK,
+        start_after: Option<Vec<u8>>,
+        limit: usize,
+        order: Order,
+    ) -> StdResult<Page<T>> {
+        self.current.page(store, p, start_after, limit, order)
+    }
+
+    /// Returns the pks of all entries with the given index value at the beginning of the given
+    /// height, sorted by pk. Their values at that height can be read with `may_load_at_height`
+    /// on the map. Returns StdError::NotFound if that height was not checkpointed.
+    pub fn prefix_at_height(
+        &self,
+        store: &dyn Storage,
+        p: IK,
+        order: Order,
+        height: u64,
+    ) -> StdResult<Vec<Vec<u8>>> {
+        self.keys_at_height(store, &p.prefix(), 0, None, None, order, height)
+    }
+
+    /// Returns (index value, pk) of all entries at the beginning of the given height, in index
+    /// order. min and max are raw keys, like in `range`.
+    /// Returns StdError::NotFound if that height was not checkpointed.
+    pub fn range_at_height(
+        &self,
+        store: &dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+        height: u64,
+    ) -> StdResult<Vec<(IK::Output, Vec<u8>)>> {
+        self.keys_at_height(store, &[], IK::KEY_ELEMS, min, max, order, height)?
+            .into_iter()
+            .map(|key| <(IK, PkOwned)>::from_vec(key).map(|(ik, pk)| (ik, pk.0)))
             .collect()
     }
+
+    /// Removes the history written below `before_height`, visiting at most `limit` keys,
+    /// see `SnapshotMap::prune`
+    pub fn prune(
+        &self,
+        store: &mut dyn Storage,
+        before_height: u64,
+        start_after: Option<Vec<u8>>,
+        limit: usize,
+    ) -> StdResult<Progress> {
+        self.snapshots
+            .prune(store, before_height, start_after, limit)
+    }
+
+    /// Returns the keys of all entries under the prefix at the beginning of the given height,
+    /// relative to the prefix like in `range`. `remaining` is the number of index columns that
+    /// are not part of the prefix.
+    ///
+    /// This reads all history under the prefix, so it should be kept short with retention or prune.
+    #[allow(clippy::too_many_arguments)]
+    fn keys_at_height(
+        &self,
+        store: &dyn Storage,
+        prefix: &[&[u8]],
+        remaining: u16,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+        height: u64,
+    ) -> StdResult<Vec<Vec<u8>>> {
+        self.snapshots.assert_checkpointed(store, height)?;
+
+        // the changelog is sorted by entry, then height. The first change of an entry at or
+        // after the given height holds its state at that height.
+        let mut exists = BTreeMap::new();
+        let changelog = namespaces_with_key(&[&[self.changelog_namespace], prefix].concat(), b"");
+        for (key, value) in range_with_prefix(store, &changelog, None, None, Order::Ascending) {
+            // the changelog key is the entry with a length-prefixed pk, followed by the height
+            let (entry, changed) = key.split_at(key.len() - 8);
+            if U64Key::from_slice(changed)? < height {
+                continue;
+            }
+            let (entry, _) = split_first_key(entry.to_vec(), remaining + 1)?;
+            if let Entry::Vacant(e) = exists.entry(entry) {
+                let change = JsonCodec::decode::<ChangeSet<u32>>(&value)?;
+                e.insert(change.old.is_some());
+            }
+        }
+
+        // entries not changed since then are still there
+        let current = namespaces_with_key(&[&[self.current.idx_namespace], prefix].concat(), b"");
+        for (entry, _) in range_with_prefix(store, &current, min.clone(), max.clone(), order) {
+            exists.entry(entry).or_insert(true);
+        }
+
+        let keys = exists
+            .into_iter()
+            .filter(|(key, existed)| *existed && in_bounds(key, &min, &max))
+            .map(|(key, _)| key);
+        Ok(match order {
+            Order::Ascending => keys.collect(),
+            Order::Descending => keys.rev().collect(),
+        })
+    }
 }
 
 #[derive(Deserialize, Serialize)]
 pub(crate) struct UniqueRef<T> {
     // note, we collapse the pk - combining everything under the namespace - even if it is composite
-    pk: Binary,
+    #[serde(with = "pk_bytes")]
+    pk: Vec<u8>,
     value: T,
 }
 
-pub struct UniqueIndex<'a, K, T> {
+/// Serializes the pk of a unique index entry as a base64 string for human readable formats
+/// (like `JsonCodec`, this is the layout of `Binary`), and as plain bytes for binary formats
+/// (like `BincodeCodec`), so it does not get bigger than the pk itself there.
+mod pk_bytes {
+    use cosmwasm_std::Binary;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(pk: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            Binary::from(pk).serialize(serializer)
+        } else {
+            serializer.serialize_bytes(pk)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        if deserializer.is_human_readable() {
+            Binary::deserialize(deserializer).map(Binary::into)
+        } else {
+            Vec::<u8>::deserialize(deserializer)
+        }
+    }
+}
+
+/// UniqueIndex stores (namespace, index_name, idx_value) -> {pk, value}, which allows one entry
+/// per index value. The copy of the value is encoded with `C`, like the map this indexes.
+pub struct UniqueIndex<'a, K, T, C = JsonCodec> {
     index: fn(&T) -> K,
-    idx_map: Map<'a, K, UniqueRef<T>>,
+    idx_map: Map<'a, K, UniqueRef<T>, C>,
     idx_namespace: &'a [u8],
 }
 
-impl<'a, K, T> UniqueIndex<'a, K, T> {
+impl<'a, K, T, C> UniqueIndex<'a, K, T, C> {
     // TODO: make this a const fn
     pub fn new(idx_fn: fn(&T) -> K, idx_namespace: &'a str) -> Self {
         UniqueIndex {
@@ -146,10 +563,11 @@ impl<'a, K, T> UniqueIndex<'a, K, T> {
     }
 }
 
-impl<'a, K, T> Index<T> for UniqueIndex<'a, K, T>
+impl<'a, K, T, C> Index<T> for UniqueIndex<'a, K, T, C>
 where
     T: Serialize + DeserializeOwned + Clone,
     K: PrimaryKey<'a>,
+    C: Codec,
 {
     fn save(&self, store: &mut dyn Storage, pk: &[u8], data: &T) -> StdResult<()> {
         let idx = (self.index)(data);
@@ -159,7 +577,7 @@ where
                 match existing {
                     Some(_) => Err(StdError::generic_err("Violates unique constraint on index")),
                     None => Ok(UniqueRef::<T> {
-                        pk: pk.into(),
+                        pk: pk.to_vec(),
                         value: data.clone(),
                     }),
                 }
@@ -174,54 +592,109 @@ where
     }
 }
 
-fn deserialize_unique_kv<T: DeserializeOwned>(kv: KV) -> StdResult<KV<T>> {
+// only the pk of a UniqueRef, the value is still parsed but not kept
+#[derive(Deserialize)]
+struct UniquePk {
+    #[serde(with = "pk_bytes")]
+    pk: Vec<u8>,
+}
+
+fn deserialize_unique_kv<T: DeserializeOwned, C: Codec>(
+    _store: &dyn Storage,
+    _pk_namespace: &[u8],
+    kv: KV,
+) -> StdResult<KV<T>> {
     let (_, v) = kv;
-    let t = from_slice::<UniqueRef<T>>(&v)?;
-    Ok((t.pk.into(), t.value))
+    let t = C::decode::<UniqueRef<T>>(&v)?;
+    Ok((t.pk, t.value))
 }
 
-impl<'a, K, T> UniqueIndex<'a, K, T>
+impl<'a, K, T, C> UniqueIndex<'a, K, T, C>
 where
     T: Serialize + DeserializeOwned + Clone,
     K: PrimaryKey<'a>,
+    C: Codec,
 {
     pub fn prefix(&self, p: K::Prefix) -> Prefix<T> {
-        Prefix::new_de_fn(self.idx_namespace, &p.prefix(), deserialize_unique_kv)
+        Prefix::new_de_fn(
+            self.idx_namespace,
+            &p.prefix(),
+            self.idx_namespace,
+            deserialize_unique_kv::<T, C>,
+        )
     }
 
     pub fn sub_prefix(&self, p: K::SubPrefix) -> Prefix<T> {
-        Prefix::new_de_fn(self.idx_namespace, &p.prefix(), deserialize_unique_kv)
+        Prefix::new_de_fn(
+            self.idx_namespace,
+            &p.prefix(),
+            self.idx_namespace,
+            deserialize_unique_kv::<T, C>,
+        )
     }
 
-    /// returns all items that match this secondary index, always by pk Ascending
+    /// returns the pk and data of the item with this index key, if any
     pub fn item(&self, store: &dyn Storage, idx: K) -> StdResult<Option<KV<T>>> {
-        let data = self
-            .idx_map
-            .may_load(store, idx)?
-            .map(|i| (i.pk.into(), i.value));
+        let data = self.idx_map.may_load(store, idx)?.map(|i| (i.pk, i.value));
         Ok(data)
     }
 }
 
-// short-cut for simple keys, rather than .prefix(()).range(...)
-impl<'a, K, T> UniqueIndex<'a, K, T>
+impl<'a, K, T, C> UniqueIndex<'a, K, T, C>
 where
     T: Serialize + DeserializeOwned + Clone,
     K: PrimaryKey<'a>,
-    K::Prefix: EmptyPrefix,
+    C: Codec,
 {
-    // I would prefer not to copy code from Prefix, but no other way
-    // with lifetimes (create Prefix inside function and return ref = no no)
+    fn no_prefix(&self) -> Prefix<T> {
+        Prefix::new_de_fn(
+            self.idx_namespace,
+            &[],
+            self.idx_namespace,
+            deserialize_unique_kv::<T, C>,
+        )
+    }
+
+    /// Iterates over all entries in index order, returning (pk, data)
     pub fn range<'c>(
         &self,
         store: &'c dyn Storage,
         min: Option<Bound>,
         max: Option<Bound>,
-        order: cosmwasm_std::Order,
+        order: Order,
     ) -> Box<dyn Iterator<Item = StdResult<KV<T>>> + 'c>
     where
         T: 'c,
     {
-        self.prefix(K::Prefix::new()).range(store, min, max, order)
+        self.no_prefix().range(store, min, max, order)
+    }
+
+    /// Iterates over all entries in index order, returning (index key, pk). The index key is
+    /// raw, like the keys of `range`, so the last one can be passed as an exclusive `min` to
+    /// continue from there. The stored copies of the data are still parsed to read the pk,
+    /// but they are not returned.
+    pub fn pks<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = StdResult<KV<Vec<u8>>>> + 'c> {
+        let prefix = namespaces_with_key(&[self.idx_namespace], b"");
+        let mapped = range_with_prefix(store, &prefix, min, max, order)
+            .map(|(k, v)| C::decode::<UniquePk>(&v).map(|r| (k, r.pk)));
+        Box::new(mapped)
+    }
+
+    /// Loads one page of all entries in index order, in either direction.
+    /// Pass the returned `next_key` as `start_after` to get the next page.
+    pub fn page(
+        &self,
+        store: &dyn Storage,
+        start_after: Option<Vec<u8>>,
+        limit: usize,
+        order: Order,
+    ) -> StdResult<Page<T>> {
+        self.no_prefix().page(store, start_after, limit, order)
     }
 }
@@ -2,32 +2,37 @@ use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::marker::PhantomData;
 
-use cosmwasm_std::{to_vec, StdError, StdResult, Storage};
+use cosmwasm_std::{HumanAddr, QuerierWrapper, StdError, StdResult, Storage};
 
+use crate::codec::{Codec, JsonCodec};
 use crate::helpers::{may_deserialize, must_deserialize};
 
 /// Item stores one typed item at the given key.
 /// This is an analog of Singleton.
 /// It functions just as Path but doesn't ue a Vec and thus has a const fn constructor.
-pub struct Item<'a, T> {
+/// The value is encoded with `C`, which is JSON unless specified otherwise.
+pub struct Item<'a, T, C = JsonCodec> {
     // this is full key - no need to length-prefix it, we only store one item
     storage_key: &'a [u8],
     // see https://doc.rust-lang.org/std/marker/struct.PhantomData.html#unused-type-parameters for why this is needed
     data_type: PhantomData<T>,
+    codec: PhantomData<C>,
 }
 
-impl<'a, T> Item<'a, T> {
+impl<'a, T, C> Item<'a, T, C> {
     pub const fn new(storage_key: &'a str) -> Self {
         Item {
             storage_key: storage_key.as_bytes(),
             data_type: PhantomData,
+            codec: PhantomData,
         }
     }
 }
 
-impl<'a, T> Item<'a, T>
+impl<'a, T, C> Item<'a, T, C>
 where
     T: Serialize + DeserializeOwned,
+    C: Codec,
 {
     // this gets the path of the data to use elsewhere
     pub fn as_slice(&self) -> &[u8] {
@@ -36,7 +41,7 @@ where
 
     /// save will serialize the model and store, returns an error on serialization issues
     pub fn save(&self, store: &mut dyn Storage, data: &T) -> StdResult<()> {
-        store.set(self.storage_key, &to_vec(data)?);
+        store.set(self.storage_key, &C::encode(data)?);
         Ok(())
     }
 
@@ -47,14 +52,14 @@ where
     /// load will return an error if no data is set at the given key, or on parse error
     pub fn load(&self, store: &dyn Storage) -> StdResult<T> {
         let value = store.get(self.storage_key);
-        must_deserialize(&value)
+        must_deserialize::<T, C>(&value)
     }
 
     /// may_load will parse the data stored at the key if present, returns Ok(None) if no data there.
     /// returns an error on issues parsing
     pub fn may_load(&self, store: &dyn Storage) -> StdResult<Option<T>> {
         let value = store.get(self.storage_key);
-        may_deserialize(&value)
+        may_deserialize::<T, C>(&value)
     }
 
     /// Loads the data, perform the specified action, and store the result
@@ -71,15 +76,34 @@ where
         self.save(store, &output)?;
         Ok(output)
     }
+
+    /// Reads the item from the storage of another contract with a raw query, which is much
+    /// cheaper than a smart query. This relies on the other contract storing it under the same
+    /// key and codec. Like `may_load`, it returns Ok(None) if no data is set there.
+    pub fn query(
+        &self,
+        querier: &QuerierWrapper,
+        remote_contract: HumanAddr,
+    ) -> StdResult<Option<T>> {
+        let value = querier.query_wasm_raw(remote_contract, self.storage_key)?;
+        may_deserialize::<T, C>(&value)
+    }
+
+    /// Like `query`, but returns an error if no data is set there, like `load`
+    pub fn query_must(&self, querier: &QuerierWrapper, remote_contract: HumanAddr) -> StdResult<T> {
+        let value = querier.query_wasm_raw(remote_contract, self.storage_key)?;
+        must_deserialize::<T, C>(&value)
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::helpers::MockRawQuerier;
     use cosmwasm_std::testing::MockStorage;
     use serde::{Deserialize, Serialize};
 
-    use cosmwasm_std::StdError;
+    use cosmwasm_std::{to_vec, StdError};
 
     #[derive(Serialize, Deserialize, PartialEq, Debug)]
     struct Config {
@@ -291,4 +315,41 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn query_remote_item() {
+        let mut querier = MockRawQuerier {
+            contract: HumanAddr::from("remote"),
+            storage: MockStorage::new(),
+        };
+
+        // nothing there yet
+        let empty = CONFIG
+            .query(&QuerierWrapper::new(&querier), "remote".into())
+            .unwrap();
+        assert_eq!(None, empty);
+        let err = CONFIG
+            .query_must(&QuerierWrapper::new(&querier), "remote".into())
+            .unwrap_err();
+        assert!(matches!(err, StdError::NotFound { .. }));
+
+        let cfg = Config {
+            owner: "admin".to_string(),
+            max_tokens: 1234,
+        };
+        CONFIG.save(&mut querier.storage, &cfg).unwrap();
+        let loaded = CONFIG
+            .query_must(&QuerierWrapper::new(&querier), "remote".into())
+            .unwrap();
+        assert_eq!(cfg, loaded);
+        let loaded = CONFIG
+            .query(&QuerierWrapper::new(&querier), "remote".into())
+            .unwrap();
+        assert_eq!(Some(cfg), loaded);
+
+        // other contracts are not answered
+        CONFIG
+            .query(&QuerierWrapper::new(&querier), "other".into())
+            .unwrap_err();
+    }
 }
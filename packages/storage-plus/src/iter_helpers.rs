@@ -3,13 +3,26 @@
 use serde::de::DeserializeOwned;
 
 use cosmwasm_std::KV;
-use cosmwasm_std::{from_slice, StdResult};
+use cosmwasm_std::{StdResult, Storage};
 
+use crate::codec::{Codec, JsonCodec};
 use crate::helpers::encode_length;
 
-pub(crate) fn deserialize_kv<T: DeserializeOwned>(kv: KV) -> StdResult<KV<T>> {
+pub(crate) fn deserialize_kv<T: DeserializeOwned>(
+    store: &dyn Storage,
+    pk_namespace: &[u8],
+    kv: KV,
+) -> StdResult<KV<T>> {
+    deserialize_kv_with::<T, JsonCodec>(store, pk_namespace, kv)
+}
+
+pub(crate) fn deserialize_kv_with<T: DeserializeOwned, C: Codec>(
+    _store: &dyn Storage,
+    _pk_namespace: &[u8],
+    kv: KV,
+) -> StdResult<KV<T>> {
     let (k, v) = kv;
-    let t = from_slice::<T>(&v)?;
+    let t = C::decode::<T>(&v)?;
     Ok((k, t))
 }
 
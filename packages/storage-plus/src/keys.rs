@@ -1,6 +1,10 @@
 use std::marker::PhantomData;
 use std::str::from_utf8;
 
+use cosmwasm_std::{StdError, StdResult};
+
+use crate::de::KeyDeserialize;
+use crate::endian::SignedEndian;
 use crate::helpers::{decode_length, namespaces_with_key};
 use crate::Endian;
 
@@ -9,6 +13,11 @@ pub trait PrimaryKey<'a>: Clone {
     type Prefix: Prefixer<'a>;
     type SubPrefix: Prefixer<'a>;
 
+    /// The remaining part of the key once `Prefix` is applied, used to type the keys in `prefix_de`
+    type Suffix: KeyDeserialize;
+    /// The remaining part of the key once `SubPrefix` is applied, used to type the keys in `sub_prefix_de`
+    type SuperSuffix: KeyDeserialize;
+
     /// returns a slice of key steps, which can be optionally combined
     fn key(&self) -> Vec<&[u8]>;
 
@@ -20,20 +29,22 @@ pub trait PrimaryKey<'a>: Clone {
 
     /// extracts a single or composite key from a joined key,
     /// only lives as long as the original bytes
-    fn parse_key(serialized: &'a [u8]) -> Self;
+    fn parse_key(serialized: &'a [u8]) -> StdResult<Self>;
 }
 
 impl<'a> PrimaryKey<'a> for &'a [u8] {
     type Prefix = ();
     type SubPrefix = ();
+    type Suffix = Self;
+    type SuperSuffix = Self;
 
     fn key(&self) -> Vec<&[u8]> {
         // this is simple, we don't add more prefixes
         vec![self]
     }
 
-    fn parse_key(serialized: &'a [u8]) -> Self {
-        serialized
+    fn parse_key(serialized: &'a [u8]) -> StdResult<Self> {
+        Ok(serialized)
     }
 }
 
@@ -41,21 +52,43 @@ impl<'a> PrimaryKey<'a> for &'a [u8] {
 impl<'a> PrimaryKey<'a> for &'a str {
     type Prefix = ();
     type SubPrefix = ();
+    type Suffix = Self;
+    type SuperSuffix = Self;
 
     fn key(&self) -> Vec<&[u8]> {
         // this is simple, we don't add more prefixes
         vec![self.as_bytes()]
     }
 
-    fn parse_key(serialized: &'a [u8]) -> Self {
-        from_utf8(serialized).unwrap()
+    fn parse_key(serialized: &'a [u8]) -> StdResult<Self> {
+        from_utf8(serialized).map_err(StdError::invalid_utf8)
+    }
+}
+
+// Owned bytes, useful when the key is computed (like in index functions)
+impl<'a> PrimaryKey<'a> for Vec<u8> {
+    type Prefix = ();
+    type SubPrefix = ();
+    type Suffix = Self;
+    type SuperSuffix = Self;
+
+    fn key(&self) -> Vec<&[u8]> {
+        vec![&self]
+    }
+
+    fn parse_key(serialized: &'a [u8]) -> StdResult<Self> {
+        Ok(serialized.to_vec())
     }
 }
 
 // use generics for combining there - so we can use &[u8], PkOwned, or IntKey
-impl<'a, T: PrimaryKey<'a> + Prefixer<'a>, U: PrimaryKey<'a>> PrimaryKey<'a> for (T, U) {
+impl<'a, T: PrimaryKey<'a> + Prefixer<'a> + KeyDeserialize, U: PrimaryKey<'a> + KeyDeserialize>
+    PrimaryKey<'a> for (T, U)
+{
     type Prefix = T;
     type SubPrefix = ();
+    type Suffix = U;
+    type SuperSuffix = Self;
 
     fn key(&self) -> Vec<&[u8]> {
         let mut keys = self.0.key();
@@ -63,20 +96,24 @@ impl<'a, T: PrimaryKey<'a> + Prefixer<'a>, U: PrimaryKey<'a>> PrimaryKey<'a> for
         keys
     }
 
-    fn parse_key(serialized: &'a [u8]) -> Self {
-        let l = decode_length(&serialized[0..2]);
-        let first = &serialized[2..l + 2];
-        let second = &serialized[l + 2..];
-        (T::parse_key(first), U::parse_key(second))
+    fn parse_key(serialized: &'a [u8]) -> StdResult<Self> {
+        let (first, second) = split_single_elem::<T>(serialized)?;
+        Ok((T::parse_key(first)?, U::parse_key(second)?))
     }
 }
 
 // use generics for combining there - so we can use &[u8], PkOwned, or IntKey
-impl<'a, T: PrimaryKey<'a> + Prefixer<'a>, U: PrimaryKey<'a> + Prefixer<'a>, V: PrimaryKey<'a>>
-    PrimaryKey<'a> for (T, U, V)
+impl<
+        'a,
+        T: PrimaryKey<'a> + Prefixer<'a> + KeyDeserialize,
+        U: PrimaryKey<'a> + Prefixer<'a> + KeyDeserialize,
+        V: PrimaryKey<'a> + KeyDeserialize,
+    > PrimaryKey<'a> for (T, U, V)
 {
     type Prefix = (T, U);
     type SubPrefix = T;
+    type Suffix = V;
+    type SuperSuffix = (U, V);
 
     fn key(&self) -> Vec<&[u8]> {
         let mut keys = self.0.key();
@@ -85,20 +122,78 @@ impl<'a, T: PrimaryKey<'a> + Prefixer<'a>, U: PrimaryKey<'a> + Prefixer<'a>, V:
         keys
     }
 
-    fn parse_key(serialized: &'a [u8]) -> Self {
-        let l1 = decode_length(&serialized[0..2]);
-        let first = &serialized[2..2 + l1];
-        let l2 = decode_length(&serialized[2 + l1..2 + l1 + 2]);
-        let second = &serialized[2 + l1 + 2..2 + l1 + 2 + l2];
-        let third = &serialized[2 + l1 + 2 + l2..];
-        (
-            T::parse_key(first),
-            U::parse_key(second),
-            V::parse_key(third),
-        )
+    fn parse_key(serialized: &'a [u8]) -> StdResult<Self> {
+        let (first, rest) = split_single_elem::<T>(serialized)?;
+        let (second, third) = split_single_elem::<U>(rest)?;
+        Ok((
+            T::parse_key(first)?,
+            U::parse_key(second)?,
+            V::parse_key(third)?,
+        ))
     }
 }
 
+// use generics for combining there - so we can use &[u8], PkOwned, or IntKey
+impl<
+        'a,
+        T: PrimaryKey<'a> + Prefixer<'a> + KeyDeserialize,
+        U: PrimaryKey<'a> + Prefixer<'a> + KeyDeserialize,
+        V: PrimaryKey<'a> + Prefixer<'a> + KeyDeserialize,
+        W: PrimaryKey<'a> + KeyDeserialize,
+    > PrimaryKey<'a> for (T, U, V, W)
+{
+    type Prefix = (T, U, V);
+    type SubPrefix = (T, U);
+    type Suffix = W;
+    type SuperSuffix = (V, W);
+
+    fn key(&self) -> Vec<&[u8]> {
+        let mut keys = self.0.key();
+        keys.extend(&self.1.key());
+        keys.extend(&self.2.key());
+        keys.extend(&self.3.key());
+        keys
+    }
+
+    fn parse_key(serialized: &'a [u8]) -> StdResult<Self> {
+        let (first, rest) = split_single_elem::<T>(serialized)?;
+        let (second, rest) = split_single_elem::<U>(rest)?;
+        let (third, fourth) = split_single_elem::<V>(rest)?;
+        Ok((
+            T::parse_key(first)?,
+            U::parse_key(second)?,
+            V::parse_key(third)?,
+            W::parse_key(fourth)?,
+        ))
+    }
+}
+
+/// Splits the first (length-prefixed) element off a joined key, for `parse_key` of tuples.
+///
+/// Tuple keys can be nested, eg. `((A, B), C)` is stored just like `(A, B, C)`.
+/// A nested key that is not the last element cannot be borrowed from the joined bytes
+/// though, as its last element has an extra length prefix there, so this returns an error.
+/// Use `KeyDeserialize` (like `range_de`) to read those keys.
+fn split_single_elem<T: KeyDeserialize>(serialized: &[u8]) -> StdResult<(&[u8], &[u8])> {
+    if T::KEY_ELEMS != 1 {
+        return Err(StdError::generic_err(
+            "parse_key only supports nested keys in the last position",
+        ));
+    }
+    if serialized.len() < 2 {
+        return Err(StdError::generic_err(
+            "Composite key too short to contain a length prefix",
+        ));
+    }
+    let l = decode_length(&serialized[0..2]);
+    if serialized.len() < 2 + l {
+        return Err(StdError::generic_err(
+            "Composite key shorter than its length prefix",
+        ));
+    }
+    Ok((&serialized[2..2 + l], &serialized[2 + l..]))
+}
+
 // pub trait Prefixer<'a>: Copy {
 pub trait Prefixer<'a> {
     /// returns 0 or more namespaces that should length-prefixed and concatenated for range searches
@@ -134,20 +229,29 @@ impl<'a, T: Prefixer<'a>, U: Prefixer<'a>, V: Prefixer<'a>> Prefixer<'a> for (T,
     }
 }
 
-// Provide a string version of this to raw encode strings
-impl<'a> Prefixer<'a> for &'a str {
+impl<'a, T: Prefixer<'a>, U: Prefixer<'a>, V: Prefixer<'a>, W: Prefixer<'a>> Prefixer<'a>
+    for (T, U, V, W)
+{
     fn prefix(&self) -> Vec<&[u8]> {
-        vec![self.as_bytes()]
+        let mut res = self.0.prefix();
+        res.extend(self.1.prefix());
+        res.extend(self.2.prefix());
+        res.extend(self.3.prefix());
+        res
     }
 }
 
-// this is a marker for the Map.range() helper, so we can detect () in Generic bounds
-pub trait EmptyPrefix {
-    fn new() -> Self;
+impl<'a> Prefixer<'a> for Vec<u8> {
+    fn prefix(&self) -> Vec<&[u8]> {
+        vec![&self]
+    }
 }
 
-impl EmptyPrefix for () {
-    fn new() {}
+// Provide a string version of this to raw encode strings
+impl<'a> Prefixer<'a> for &'a str {
+    fn prefix(&self) -> Vec<&[u8]> {
+        vec![self.as_bytes()]
+    }
 }
 
 // Add support for an dynamic keys - constructor functions below
@@ -157,13 +261,15 @@ pub struct PkOwned(pub Vec<u8>);
 impl<'a> PrimaryKey<'a> for PkOwned {
     type Prefix = ();
     type SubPrefix = ();
+    type Suffix = Self;
+    type SuperSuffix = Self;
 
     fn key(&self) -> Vec<&[u8]> {
         vec![&self.0]
     }
 
-    fn parse_key(serialized: &'a [u8]) -> Self {
-        PkOwned(serialized.to_vec())
+    fn parse_key(serialized: &'a [u8]) -> StdResult<Self> {
+        Ok(PkOwned(serialized.to_vec()))
     }
 }
 
@@ -174,16 +280,18 @@ impl<'a> Prefixer<'a> for PkOwned {
 }
 
 // this auto-implements PrimaryKey for all the IntKey types (and more!)
-impl<'a, T: AsRef<PkOwned> + From<PkOwned> + Clone> PrimaryKey<'a> for T {
+impl<'a, T: AsRef<PkOwned> + From<PkOwned> + Clone + KeyDeserialize> PrimaryKey<'a> for T {
     type Prefix = ();
     type SubPrefix = ();
+    type Suffix = Self;
+    type SuperSuffix = Self;
 
     fn key(&self) -> Vec<&[u8]> {
         self.as_ref().key()
     }
 
-    fn parse_key(serialized: &'a [u8]) -> Self {
-        PkOwned::parse_key(serialized).into()
+    fn parse_key(serialized: &'a [u8]) -> StdResult<Self> {
+        PkOwned::parse_key(serialized).map(T::from)
     }
 }
 
@@ -200,6 +308,8 @@ pub type U32Key = IntKey<u32>;
 pub type U64Key = IntKey<u64>;
 pub type U128Key = IntKey<u128>;
 
+// Note: these sort negative values after positive ones, as the bytes are plain two's complement.
+// They are kept for existing data, use the SignedIntKey family below for new maps.
 pub type I8Key = IntKey<i8>;
 pub type I16Key = IntKey<i16>;
 pub type I32Key = IntKey<i32>;
@@ -261,6 +371,78 @@ impl<T: Endian> AsRef<PkOwned> for IntKey<T> {
     }
 }
 
+pub type SignedI8Key = SignedIntKey<i8>;
+pub type SignedI16Key = SignedIntKey<i16>;
+pub type SignedI32Key = SignedIntKey<i32>;
+pub type SignedI64Key = SignedIntKey<i64>;
+pub type SignedI128Key = SignedIntKey<i128>;
+
+/// SignedIntKey works like IntKey for the signed int types, but stores the big-endian bytes
+/// with the sign bit flipped, so the keys sort in numeric order: -2 < -1 < 0 < 1.
+///
+/// This is a different encoding than `I32Key` and friends, so it cannot read their data.
+/// Existing entries can be moved over by converting each key with `From<IntKey<T>>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignedIntKey<T: SignedEndian> {
+    pub wrapped: PkOwned,
+    pub data: PhantomData<T>,
+}
+
+impl<T: SignedEndian> SignedIntKey<T> {
+    pub fn new(val: T) -> Self {
+        let mut bytes = val.to_be_bytes();
+        bytes.as_mut()[0] ^= 0x80;
+        SignedIntKey {
+            wrapped: PkOwned(bytes.into()),
+            data: PhantomData,
+        }
+    }
+}
+
+impl<T: SignedEndian> From<T> for SignedIntKey<T> {
+    fn from(val: T) -> Self {
+        SignedIntKey::new(val)
+    }
+}
+
+impl<T: SignedEndian> From<PkOwned> for SignedIntKey<T> {
+    fn from(wrap: PkOwned) -> Self {
+        SignedIntKey {
+            wrapped: wrap,
+            data: PhantomData,
+        }
+    }
+}
+
+impl<T: SignedEndian> From<Vec<u8>> for SignedIntKey<T> {
+    fn from(wrap: Vec<u8>) -> Self {
+        PkOwned(wrap).into()
+    }
+}
+
+/// Re-encodes a key from the old two's complement encoding
+impl<T: SignedEndian> From<IntKey<T>> for SignedIntKey<T> {
+    fn from(old: IntKey<T>) -> Self {
+        let mut bytes = old.wrapped.0;
+        if let Some(first) = bytes.first_mut() {
+            *first ^= 0x80;
+        }
+        PkOwned(bytes).into()
+    }
+}
+
+impl<T: SignedEndian> From<SignedIntKey<T>> for Vec<u8> {
+    fn from(k: SignedIntKey<T>) -> Vec<u8> {
+        k.wrapped.0
+    }
+}
+
+impl<T: SignedEndian> AsRef<PkOwned> for SignedIntKey<T> {
+    fn as_ref(&self) -> &PkOwned {
+        &self.wrapped
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -291,7 +473,7 @@ mod test {
         assert_eq!("hello".as_bytes(), path[0]);
 
         let joined = k.joined_key();
-        let parsed = K::parse_key(&joined);
+        let parsed = K::parse_key(&joined).unwrap();
         assert_eq!(parsed, "hello");
     }
 
@@ -350,7 +532,7 @@ mod test {
         let key: K = b"four";
         let joined = key.joined_key();
         assert_eq!(key, joined.as_slice());
-        let parsed = K::parse_key(&joined);
+        let parsed = K::parse_key(&joined).unwrap();
         assert_eq!(key, parsed);
     }
 
@@ -361,7 +543,7 @@ mod test {
         let key: K = (b"four", b"square");
         let joined = key.joined_key();
         assert_eq!(4 + 6 + 2, joined.len());
-        let parsed = K::parse_key(&joined);
+        let parsed = K::parse_key(&joined).unwrap();
         assert_eq!(key, parsed);
     }
 
@@ -372,7 +554,7 @@ mod test {
         let key: K = ("four", 15.into(), b"cinco");
         let joined = key.joined_key();
         assert_eq!(4 + 4 + 5 + 2 * 2, joined.len());
-        let parsed = K::parse_key(&joined);
+        let parsed = K::parse_key(&joined).unwrap();
         assert_eq!(key, parsed);
     }
 
@@ -383,8 +565,41 @@ mod test {
         let key: K = ("one", 222.into(), "three");
         let joined = key.joined_key();
         assert_eq!(3 + 8 + 5 + 2 * 2, joined.len());
-        let parsed = K::parse_key(&joined);
+        let parsed = K::parse_key(&joined).unwrap();
+        assert_eq!(key, parsed);
+    }
+
+    #[test]
+    fn parse_joined_keys_pk4() {
+        type K<'a> = (&'a str, U32Key, &'a [u8], U64Key);
+
+        let key: K = ("four", 15.into(), b"cinco", 6.into());
+        let joined = key.joined_key();
+        assert_eq!(4 + 4 + 5 + 8 + 3 * 2, joined.len());
+        let parsed = K::parse_key(&joined).unwrap();
+        assert_eq!(key, parsed);
+    }
+
+    #[test]
+    fn parse_nested_keys() {
+        // nested in the last position can be borrowed
+        type Last<'a> = (&'a [u8], (&'a [u8], &'a [u8]));
+        let key: Last = (b"foo", (b"bar", b"zoom"));
+        let joined = key.joined_key();
+        let parsed = Last::parse_key(&joined).unwrap();
         assert_eq!(key, parsed);
+
+        // anywhere else it is an error, not a panic
+        type First<'a> = ((&'a [u8], &'a [u8]), &'a [u8]);
+        let key: First = ((b"foo", b"bar"), b"zoom");
+        First::parse_key(&key.joined_key()).unwrap_err();
+        type Middle<'a> = (&'a [u8], (&'a [u8], &'a [u8]), &'a [u8]);
+        let key: Middle = (b"foo", (b"bar", b"baz"), b"zoom");
+        Middle::parse_key(&key.joined_key()).unwrap_err();
+
+        // as well as bytes that are too short
+        <(&[u8], &[u8])>::parse_key(b"\x00").unwrap_err();
+        <(&[u8], &[u8])>::parse_key(b"\x00\x05foo").unwrap_err();
     }
 
     #[test]
@@ -392,7 +607,7 @@ mod test {
         let key: U64Key = 12345678.into();
         let joined = key.joined_key();
         assert_eq!(8, joined.len());
-        let parsed = U64Key::parse_key(&joined);
+        let parsed = U64Key::parse_key(&joined).unwrap();
         assert_eq!(key, parsed);
     }
 
@@ -403,7 +618,7 @@ mod test {
         let key: K = (54321.into(), "random");
         let joined = key.joined_key();
         assert_eq!(2 + 4 + 6, joined.len());
-        let parsed = K::parse_key(&joined);
+        let parsed = K::parse_key(&joined).unwrap();
         assert_eq!(key, parsed);
         assert_eq!("random", parsed.1);
     }
@@ -427,4 +642,65 @@ mod test {
             vec![one.as_slice(), two.as_slice(), three.as_slice()]
         );
     }
+
+    #[test]
+    fn signed_int_keys_sort_numerically() {
+        let values = [i32::MIN, -1000, -1, 0, 1, 1000, i32::MAX];
+        let keys: Vec<_> = values
+            .iter()
+            .map(|v| SignedI32Key::new(*v).joined_key())
+            .collect();
+        let mut sorted = keys.clone();
+        sorted.sort();
+        assert_eq!(keys, sorted);
+
+        // the old encoding does not
+        assert!(I32Key::new(-1).joined_key() > I32Key::new(1).joined_key());
+
+        // converted keys match the new encoding
+        for v in values.iter() {
+            let converted: SignedI32Key = I32Key::new(*v).into();
+            assert_eq!(converted, SignedI32Key::new(*v));
+        }
+        assert_eq!(
+            SignedI8Key::new(-1).joined_key(),
+            vec![0x7f],
+            "sign bit is flipped"
+        );
+    }
+
+    // a custom key as shown in the README upgrade notes
+    #[derive(Clone, Debug, PartialEq)]
+    struct Denom<'a>(&'a str);
+
+    impl<'a> PrimaryKey<'a> for Denom<'a> {
+        type Prefix = ();
+        type SubPrefix = ();
+        type Suffix = Self;
+        type SuperSuffix = Self;
+
+        fn key(&self) -> Vec<&[u8]> {
+            vec![self.0.as_bytes()]
+        }
+
+        fn parse_key(serialized: &'a [u8]) -> StdResult<Self> {
+            <&str>::parse_key(serialized).map(Denom)
+        }
+    }
+
+    impl<'a> KeyDeserialize for Denom<'a> {
+        type Output = String;
+
+        fn from_vec(value: Vec<u8>) -> StdResult<Self::Output> {
+            <&str>::from_vec(value)
+        }
+    }
+
+    #[test]
+    fn custom_key() {
+        let key = Denom("ujuno");
+        let joined = key.joined_key();
+        assert_eq!(Denom::parse_key(&joined).unwrap(), key);
+        assert_eq!(Denom::from_slice(&joined).unwrap(), "ujuno".to_string());
+    }
 }
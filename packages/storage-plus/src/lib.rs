@@ -1,26 +1,49 @@
+mod codec;
+mod de;
+mod decode;
+mod deque;
 mod endian;
 mod helpers;
 mod indexed_map;
+mod indexed_snapshot;
 mod indexes;
 mod item;
 mod iter_helpers;
 mod keys;
 mod map;
+mod migrate;
 mod path;
 mod prefix;
 mod snapshot;
 
-pub use endian::Endian;
+#[cfg(feature = "bincode")]
+pub use codec::BincodeCodec;
+pub use codec::{Codec, JsonCodec};
+#[cfg(feature = "macro")]
+pub use cw_storage_macro::IndexList;
+pub use de::KeyDeserialize;
+pub use decode::{DecodedKey, KeyKind, KeyLayout, KeyPart};
+pub use deque::{Deque, DequeIter};
+pub use endian::{Endian, SignedEndian};
 #[cfg(feature = "iterator")]
 pub use indexed_map::{IndexList, IndexedMap};
 #[cfg(feature = "iterator")]
-pub use indexes::{index_string, index_string_tuple, Index, MultiIndex, UniqueIndex};
+pub use indexed_snapshot::IndexedSnapshotMap;
+#[cfg(feature = "iterator")]
+pub use indexes::{
+    index_string, index_string_tuple, Index, MultiIndex, SnapshotMultiIndex, UniqueIndex,
+};
 pub use item::Item;
 pub use keys::{I128Key, I16Key, I32Key, I64Key, I8Key};
 pub use keys::{PkOwned, Prefixer, PrimaryKey, U128Key, U16Key, U32Key, U64Key, U8Key};
+pub use keys::{
+    SignedI128Key, SignedI16Key, SignedI32Key, SignedI64Key, SignedI8Key, SignedIntKey,
+};
 pub use map::Map;
+#[cfg(feature = "iterator")]
+pub use migrate::{migrate_bucket, migrate_map, migrate_singleton, Progress};
 pub use path::Path;
 #[cfg(feature = "iterator")]
-pub use prefix::{range_with_prefix, Bound, Prefix};
+pub use prefix::{range_with_prefix, Bound, Page, Prefix};
 #[cfg(feature = "iterator")]
-pub use snapshot::{SnapshotMap, Strategy};
+pub use snapshot::{SnapshotItem, SnapshotMap, Strategy};
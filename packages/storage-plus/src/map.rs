@@ -2,49 +2,96 @@ use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::marker::PhantomData;
 
-use crate::keys::PrimaryKey;
+use crate::codec::{Codec, JsonCodec};
+#[cfg(feature = "iterator")]
+use crate::de::KeyDeserialize;
+#[cfg(feature = "iterator")]
+use crate::iter_helpers::deserialize_kv_with;
 #[cfg(feature = "iterator")]
-use crate::keys::{EmptyPrefix, Prefixer};
+use crate::keys::Prefixer;
+use crate::keys::PrimaryKey;
 use crate::path::Path;
 #[cfg(feature = "iterator")]
 use crate::prefix::{Bound, Prefix};
-use cosmwasm_std::{StdError, StdResult, Storage};
+use cosmwasm_std::{HumanAddr, QuerierWrapper, StdError, StdResult, Storage};
 
+/// Map stores many typed values under one namespace, looked up by a simple or composite key.
+/// The values are encoded with `C`, which is JSON unless specified otherwise.
 #[derive(Debug, Clone)]
-pub struct Map<'a, K, T> {
+pub struct Map<'a, K, T, C = JsonCodec> {
     namespace: &'a [u8],
     // see https://doc.rust-lang.org/std/marker/struct.PhantomData.html#unused-type-parameters for why this is needed
     key_type: PhantomData<K>,
     data_type: PhantomData<T>,
+    codec: PhantomData<C>,
 }
 
-impl<'a, K, T> Map<'a, K, T> {
+impl<'a, K, T, C> Map<'a, K, T, C> {
     pub const fn new(namespace: &'a str) -> Self {
         Map {
             namespace: namespace.as_bytes(),
             data_type: PhantomData,
             key_type: PhantomData,
+            codec: PhantomData,
         }
     }
+    pub(crate) fn namespace(&self) -> &'a [u8] {
+        self.namespace
+    }
 }
 
-impl<'a, K, T> Map<'a, K, T>
+impl<'a, K, T, C> Map<'a, K, T, C>
 where
     T: Serialize + DeserializeOwned,
+    C: Codec,
     K: PrimaryKey<'a>,
 {
-    pub fn key(&self, k: K) -> Path<T> {
+    pub fn key(&self, k: K) -> Path<T, C> {
         Path::new(self.namespace, &k.key())
     }
 
     #[cfg(feature = "iterator")]
     pub fn prefix(&self, p: K::Prefix) -> Prefix<T> {
-        Prefix::new(self.namespace, &p.prefix())
+        Prefix::new_de_fn(
+            self.namespace,
+            &p.prefix(),
+            self.namespace,
+            deserialize_kv_with::<T, C>,
+        )
     }
 
     #[cfg(feature = "iterator")]
     pub fn sub_prefix(&self, p: K::SubPrefix) -> Prefix<T> {
-        Prefix::new(self.namespace, &p.prefix())
+        Prefix::new_de_fn(
+            self.namespace,
+            &p.prefix(),
+            self.namespace,
+            deserialize_kv_with::<T, C>,
+        )
+    }
+
+    /// Like `prefix`, but the returned `Prefix` knows the type of the remaining key,
+    /// so `range_de` can give back typed keys
+    #[cfg(feature = "iterator")]
+    pub fn prefix_de(&self, p: K::Prefix) -> Prefix<T, K::Suffix> {
+        Prefix::new_de_fn(
+            self.namespace,
+            &p.prefix(),
+            self.namespace,
+            deserialize_kv_with::<T, C>,
+        )
+    }
+
+    /// Like `sub_prefix`, but the returned `Prefix` knows the type of the remaining key,
+    /// so `range_de` can give back typed keys
+    #[cfg(feature = "iterator")]
+    pub fn sub_prefix_de(&self, p: K::SubPrefix) -> Prefix<T, K::SuperSuffix> {
+        Prefix::new_de_fn(
+            self.namespace,
+            &p.prefix(),
+            self.namespace,
+            deserialize_kv_with::<T, C>,
+        )
     }
 
     pub fn save(&self, store: &mut dyn Storage, k: K, data: &T) -> StdResult<()> {
@@ -66,6 +113,11 @@ where
         self.key(k).may_load(store)
     }
 
+    /// has returns true if the key is set, without parsing the value
+    pub fn has(&self, store: &dyn Storage, k: K) -> bool {
+        self.key(k).has(store)
+    }
+
     /// Loads the data, perform the specified action, and store the result
     /// in the database. This is shorthand for some common sequences, which may be useful.
     ///
@@ -77,18 +129,66 @@ where
     {
         self.key(k).update(store, action)
     }
+
+    /// Reads the value for `k` from the storage of another contract with a raw query, which is
+    /// much cheaper than a smart query. This relies on the other contract using the same
+    /// namespace, key type and codec. Returns Ok(None) if the key is not set there.
+    pub fn query(
+        &self,
+        querier: &QuerierWrapper,
+        remote_contract: HumanAddr,
+        k: K,
+    ) -> StdResult<Option<T>> {
+        self.key(k).query(querier, remote_contract)
+    }
+
+    /// Like `query`, but returns an error if the key is not set there, like `load`
+    pub fn query_must(
+        &self,
+        querier: &QuerierWrapper,
+        remote_contract: HumanAddr,
+        k: K,
+    ) -> StdResult<T> {
+        self.key(k).query_must(querier, remote_contract)
+    }
+
+    /// Applies many writes in one pass: `Some(data)` saves the value, `None` removes the key.
+    /// Returns the previous value of every item, in the same order, so the caller can
+    /// tell what changed. Later items see the writes of earlier ones.
+    pub fn update_many<I>(&self, store: &mut dyn Storage, items: I) -> StdResult<Vec<Option<T>>>
+    where
+        I: IntoIterator<Item = (K, Option<T>)>,
+    {
+        let mut olds = vec![];
+        for (k, data) in items {
+            let path = self.key(k);
+            olds.push(path.may_load(store)?);
+            match data {
+                Some(data) => path.save(store, &data)?,
+                None => path.remove(store),
+            }
+        }
+        Ok(olds)
+    }
 }
 
-// short-cut for simple keys, rather than .prefix(()).range(...)
+// iteration over the full key, this works for simple and composite keys alike
 #[cfg(feature = "iterator")]
-impl<'a, K, T> Map<'a, K, T>
+impl<'a, K, T, C> Map<'a, K, T, C>
 where
     T: Serialize + DeserializeOwned,
+    C: Codec,
     K: PrimaryKey<'a>,
-    K::Prefix: EmptyPrefix,
 {
-    // I would prefer not to copy code from Prefix, but no other way
-    // with lifetimes (create Prefix inside function and return ref = no no)
+    pub(crate) fn no_prefix(&self) -> Prefix<T> {
+        Prefix::new_de_fn(
+            self.namespace,
+            &[],
+            self.namespace,
+            deserialize_kv_with::<T, C>,
+        )
+    }
+
     pub fn range<'c>(
         &self,
         store: &'c dyn Storage,
@@ -99,7 +199,89 @@ where
     where
         T: 'c,
     {
-        self.prefix(K::Prefix::new()).range(store, min, max, order)
+        self.no_prefix().range(store, min, max, order)
+    }
+
+    /// Returns only the keys (as raw bytes, like `range`), without parsing any of the values
+    pub fn keys<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: cosmwasm_std::Order,
+    ) -> Box<dyn Iterator<Item = Vec<u8>> + 'c> {
+        self.no_prefix().keys(store, min, max, order)
+    }
+
+    /// Returns the complete storage keys (including the namespace), without parsing any of the values
+    pub fn keys_raw<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: cosmwasm_std::Order,
+    ) -> Box<dyn Iterator<Item = Vec<u8>> + 'c> {
+        self.no_prefix().keys_raw(store, min, max, order)
+    }
+
+    /// Counts the entries between min and max, without parsing any of the values
+    pub fn count(&self, store: &dyn Storage, min: Option<Bound>, max: Option<Bound>) -> usize {
+        self.no_prefix().count(store, min, max)
+    }
+
+    /// Removes all entries from the map. Use `prefix(p).clear(store, limit)`
+    /// to clear a part of a composite key, or to split a large map over several transactions.
+    pub fn clear(&self, store: &mut dyn Storage) {
+        self.no_prefix().clear(store, None);
+    }
+}
+
+// typed iteration over the full key, this works for simple and composite keys alike
+#[cfg(feature = "iterator")]
+impl<'a, K, T, C> Map<'a, K, T, C>
+where
+    T: Serialize + DeserializeOwned,
+    C: Codec,
+    K: PrimaryKey<'a> + KeyDeserialize,
+{
+    pub(crate) fn no_prefix_de(&self) -> Prefix<T, K> {
+        Prefix::new_de_fn(
+            self.namespace,
+            &[],
+            self.namespace,
+            deserialize_kv_with::<T, C>,
+        )
+    }
+
+    /// Like `range`, but returns the keys deserialized into `K::Output`
+    /// (eg. `Vec<u8>` for `&[u8]`, `String` for `&str`, `(u64, Vec<u8>)` for `(U64Key, &[u8])`)
+    pub fn range_de<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: cosmwasm_std::Order,
+    ) -> Box<dyn Iterator<Item = StdResult<(K::Output, T)>> + 'c>
+    where
+        T: 'c,
+        K::Output: 'c,
+    {
+        self.no_prefix_de().range_de(store, min, max, order)
+    }
+
+    /// Returns only the deserialized keys, without parsing any of the values
+    pub fn keys_de<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: cosmwasm_std::Order,
+    ) -> Box<dyn Iterator<Item = StdResult<K::Output>> + 'c>
+    where
+        T: 'c,
+        K::Output: 'c,
+    {
+        self.no_prefix_de().keys_de(store, min, max, order)
     }
 }
 
@@ -109,6 +291,7 @@ mod test {
     use serde::{Deserialize, Serialize};
     use std::ops::Deref;
 
+    use crate::helpers::MockRawQuerier;
     #[cfg(feature = "iterator")]
     use crate::iter_helpers::to_length_prefixed;
     use crate::U8Key;
@@ -186,6 +369,20 @@ mod test {
         assert_eq!(None, john.may_load(&store).unwrap());
     }
 
+    #[test]
+    fn has_does_not_parse() {
+        let mut store = MockStorage::new();
+        assert!(!PEOPLE.has(&store, b"john"));
+
+        // this is not valid Data, so only has() can see it
+        let john = PEOPLE.key(b"john");
+        store.set(&john, b"not json");
+        assert!(john.has(&store));
+        assert!(PEOPLE.has(&store, b"john"));
+        assert!(!PEOPLE.has(&store, b"jack"));
+        PEOPLE.may_load(&store, b"john").unwrap_err();
+    }
+
     #[test]
     fn composite_keys() {
         let mut store = MockStorage::new();
@@ -283,6 +480,17 @@ mod test {
             all,
             vec![(b"spender".to_vec(), 1000), (b"spender2".to_vec(), 3000)]
         );
+
+        // range over the whole map returns the joined keys
+        let all: StdResult<Vec<_>> = ALLOWANCE
+            .range(&store, None, None, Order::Descending)
+            .collect();
+        let all = all.unwrap();
+        assert_eq!(3, all.len());
+        assert_eq!(
+            all[0],
+            ((b"owner2".as_ref(), b"spender".as_ref()).joined_key(), 5000)
+        );
     }
 
     #[test]
@@ -346,6 +554,398 @@ mod test {
         );
     }
 
+    #[test]
+    #[cfg(feature = "iterator")]
+    fn range_de_simple_key() {
+        let mut store = MockStorage::new();
+
+        let data = Data {
+            name: "John".to_string(),
+            age: 32,
+        };
+        PEOPLE.save(&mut store, b"john", &data).unwrap();
+        let data2 = Data {
+            name: "Jim".to_string(),
+            age: 44,
+        };
+        PEOPLE.save(&mut store, b"jim", &data2).unwrap();
+
+        let all: StdResult<Vec<_>> = PEOPLE
+            .range_de(&store, None, None, Order::Ascending)
+            .collect();
+        assert_eq!(
+            all.unwrap(),
+            vec![(b"jim".to_vec(), data2), (b"john".to_vec(), data)]
+        );
+
+        let keys: StdResult<Vec<_>> = PEOPLE
+            .keys_de(&store, None, None, Order::Descending)
+            .collect();
+        assert_eq!(keys.unwrap(), vec![b"john".to_vec(), b"jim".to_vec()]);
+    }
+
+    #[test]
+    #[cfg(feature = "iterator")]
+    fn range_signed_int_keys() {
+        use crate::SignedI32Key;
+
+        const TICKS: Map<SignedI32Key, u64> = Map::new("ticks");
+        let mut store = MockStorage::new();
+        for tick in &[5, -3, 0, -100, 42] {
+            TICKS
+                .save(&mut store, SignedI32Key::new(*tick), &1)
+                .unwrap();
+        }
+
+        let ticks: StdResult<Vec<_>> = TICKS
+            .keys_de(&store, None, None, Order::Ascending)
+            .collect();
+        assert_eq!(ticks.unwrap(), vec![-100, -3, 0, 5, 42]);
+
+        // bounds work across zero
+        let ticks: StdResult<Vec<_>> = TICKS
+            .keys_de(
+                &store,
+                Some(Bound::inclusive_key(SignedI32Key::new(-3))),
+                Some(Bound::exclusive_key(SignedI32Key::new(42))),
+                Order::Descending,
+            )
+            .collect();
+        assert_eq!(ticks.unwrap(), vec![5, 0, -3]);
+    }
+
+    #[test]
+    #[cfg(feature = "iterator")]
+    fn range_de_composite_key() {
+        let mut store = MockStorage::new();
+
+        ALLOWANCE
+            .save(&mut store, (b"owner", b"spender"), &1000)
+            .unwrap();
+        ALLOWANCE
+            .save(&mut store, (b"owner", b"spender2"), &3000)
+            .unwrap();
+        ALLOWANCE
+            .save(&mut store, (b"owner2", b"spender"), &5000)
+            .unwrap();
+
+        // the full composite key is decoded
+        let all: StdResult<Vec<_>> = ALLOWANCE
+            .range_de(&store, None, None, Order::Ascending)
+            .collect();
+        assert_eq!(
+            all.unwrap(),
+            vec![
+                ((b"owner".to_vec(), b"spender".to_vec()), 1000),
+                ((b"owner".to_vec(), b"spender2".to_vec()), 3000),
+                ((b"owner2".to_vec(), b"spender".to_vec()), 5000),
+            ]
+        );
+
+        // or just the remaining key under a prefix
+        let all: StdResult<Vec<_>> = ALLOWANCE
+            .prefix_de(b"owner")
+            .range_de(&store, None, None, Order::Ascending)
+            .collect();
+        assert_eq!(
+            all.unwrap(),
+            vec![(b"spender".to_vec(), 1000), (b"spender2".to_vec(), 3000)]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "iterator")]
+    fn range_de_triple_key() {
+        let mut store = MockStorage::new();
+
+        TRIPLE
+            .save(&mut store, (b"owner", 9u8.into(), "recipient"), &1000)
+            .unwrap();
+        TRIPLE
+            .save(&mut store, (b"owner", 9u8.into(), "recipient2"), &3000)
+            .unwrap();
+        TRIPLE
+            .save(&mut store, (b"owner", 10u8.into(), "recipient3"), &3000)
+            .unwrap();
+        TRIPLE
+            .save(&mut store, (b"owner2", 9u8.into(), "recipient"), &5000)
+            .unwrap();
+
+        let all: StdResult<Vec<_>> = TRIPLE
+            .prefix_de((b"owner", 9u8.into()))
+            .range_de(&store, None, None, Order::Ascending)
+            .collect();
+        assert_eq!(
+            all.unwrap(),
+            vec![
+                ("recipient".to_string(), 1000),
+                ("recipient2".to_string(), 3000)
+            ]
+        );
+
+        // the sub prefix decodes the remaining (U8Key, &str) pair, no more manual parsing
+        let all: StdResult<Vec<_>> = TRIPLE
+            .sub_prefix_de(b"owner")
+            .range_de(&store, None, None, Order::Ascending)
+            .collect();
+        assert_eq!(
+            all.unwrap(),
+            vec![
+                ((9, "recipient".to_string()), 1000),
+                ((9, "recipient2".to_string()), 3000),
+                ((10, "recipient3".to_string()), 3000)
+            ]
+        );
+
+        let keys: StdResult<Vec<_>> = TRIPLE
+            .keys_de(&store, None, None, Order::Descending)
+            .collect();
+        assert_eq!(
+            keys.unwrap(),
+            vec![
+                (b"owner2".to_vec(), 9, "recipient".to_string()),
+                (b"owner".to_vec(), 10, "recipient3".to_string()),
+                (b"owner".to_vec(), 9, "recipient2".to_string()),
+                (b"owner".to_vec(), 9, "recipient".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "iterator")]
+    fn four_tuple_and_nested_keys() {
+        use crate::U64Key;
+
+        type Order4<'a> = (&'a str, &'a str, U64Key, U64Key);
+        const ORDERS: Map<Order4, u64> = Map::new("orders");
+        let mut store = MockStorage::new();
+
+        ORDERS
+            .save(&mut store, ("alice", "atom", 100.into(), 1.into()), &10)
+            .unwrap();
+        ORDERS
+            .save(&mut store, ("alice", "atom", 100.into(), 2.into()), &20)
+            .unwrap();
+        ORDERS
+            .save(&mut store, ("alice", "atom", 200.into(), 3.into()), &30)
+            .unwrap();
+        ORDERS
+            .save(&mut store, ("alice", "osmo", 100.into(), 4.into()), &40)
+            .unwrap();
+        ORDERS
+            .save(&mut store, ("bob", "atom", 100.into(), 5.into()), &50)
+            .unwrap();
+        assert_eq!(
+            20,
+            ORDERS
+                .load(&store, ("alice", "atom", 100.into(), 2.into()))
+                .unwrap()
+        );
+
+        // prefix fixes three elements
+        let ids: Vec<_> = ORDERS
+            .prefix_de(("alice", "atom", 100.into()))
+            .keys_de(&store, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert_eq!(ids, vec![1, 2]);
+
+        // sub_prefix fixes two
+        let all: Vec<_> = ORDERS
+            .sub_prefix_de(("alice", "atom"))
+            .range_de(&store, None, None, Order::Descending)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert_eq!(all, vec![((200, 3), 30), ((100, 2), 20), ((100, 1), 10)]);
+
+        // nesting picks other levels, with the same storage layout
+        type Nested<'a> = (&'a str, (&'a str, U64Key, U64Key));
+        const NESTED: Map<Nested, u64> = Map::new("orders");
+        let by_owner: Vec<_> = NESTED
+            .prefix_de("alice")
+            .keys_de(&store, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert_eq!(
+            by_owner,
+            vec![
+                ("atom".to_string(), 100, 1),
+                ("atom".to_string(), 100, 2),
+                ("atom".to_string(), 200, 3),
+                ("osmo".to_string(), 100, 4),
+            ]
+        );
+
+        type Pairs<'a> = ((&'a str, &'a str), (U64Key, U64Key));
+        const PAIRS: Map<Pairs, u64> = Map::new("orders");
+        let bob: Vec<_> = PAIRS
+            .prefix_de(("bob", "atom"))
+            .range_de(&store, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert_eq!(bob, vec![((100, 5), 50)]);
+        let all: Vec<_> = PAIRS
+            .keys_de(&store, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert_eq!(all.len(), 5);
+        // the length prefix sorts the shorter owner first
+        assert_eq!(all[0], (("bob".to_string(), "atom".to_string()), (100, 5)));
+    }
+
+    #[test]
+    #[cfg(feature = "iterator")]
+    fn range_with_typed_bounds() {
+        let mut store = MockStorage::new();
+
+        ALLOWANCE
+            .save(&mut store, (b"owner", b"spender"), &1000)
+            .unwrap();
+        ALLOWANCE
+            .save(&mut store, (b"owner", b"spender2"), &3000)
+            .unwrap();
+        ALLOWANCE
+            .save(&mut store, (b"owner2", b"spender"), &5000)
+            .unwrap();
+
+        // start after a full composite key, no manual length-prefixing
+        let start = Bound::exclusive_key((b"owner".as_ref(), b"spender".as_ref()));
+        let all: StdResult<Vec<_>> = ALLOWANCE
+            .range_de(&store, Some(start), None, Order::Ascending)
+            .collect();
+        assert_eq!(
+            all.unwrap(),
+            vec![
+                ((b"owner".to_vec(), b"spender2".to_vec()), 3000),
+                ((b"owner2".to_vec(), b"spender".to_vec()), 5000),
+            ]
+        );
+
+        TRIPLE
+            .save(&mut store, (b"owner", 9u8.into(), "recipient"), &1000)
+            .unwrap();
+        TRIPLE
+            .save(&mut store, (b"owner", 9u8.into(), "recipient2"), &3000)
+            .unwrap();
+        TRIPLE
+            .save(&mut store, (b"owner", 10u8.into(), "recipient3"), &3000)
+            .unwrap();
+
+        // bounds on the remaining composite key of a sub prefix
+        let start = Bound::exclusive_key((U8Key::from(9), "recipient"));
+        let end = Bound::inclusive_key((U8Key::from(10), "recipient3"));
+        let all: StdResult<Vec<_>> = TRIPLE
+            .sub_prefix_de(b"owner")
+            .range_de(&store, Some(start), Some(end), Order::Descending)
+            .collect();
+        assert_eq!(
+            all.unwrap(),
+            vec![
+                ((10, "recipient3".to_string()), 3000),
+                ((9, "recipient2".to_string()), 3000),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "iterator")]
+    fn keys_and_count() {
+        let mut store = MockStorage::new();
+
+        ALLOWANCE
+            .save(&mut store, (b"owner", b"spender"), &1000)
+            .unwrap();
+        ALLOWANCE
+            .save(&mut store, (b"owner", b"spender2"), &3000)
+            .unwrap();
+        ALLOWANCE
+            .save(&mut store, (b"owner2", b"spender"), &5000)
+            .unwrap();
+
+        let keys: Vec<_> = ALLOWANCE
+            .prefix(b"owner")
+            .keys(&store, None, None, Order::Descending)
+            .collect();
+        assert_eq!(keys, vec![b"spender2".to_vec(), b"spender".to_vec()]);
+
+        // the full keys are still length-prefixed
+        let keys: Vec<_> = ALLOWANCE
+            .keys(&store, None, None, Order::Ascending)
+            .collect();
+        assert_eq!(3, keys.len());
+        assert_eq!(
+            keys[2],
+            (b"owner2".as_ref(), b"spender".as_ref()).joined_key()
+        );
+
+        // raw keys point right at the data
+        let raw: Vec<_> = ALLOWANCE
+            .keys_raw(&store, None, None, Order::Ascending)
+            .collect();
+        assert_eq!(
+            raw[1],
+            ALLOWANCE.key((b"owner", b"spender2")).deref().to_vec()
+        );
+
+        assert_eq!(3, ALLOWANCE.count(&store, None, None));
+        assert_eq!(2, ALLOWANCE.prefix(b"owner").count(&store, None, None));
+        assert_eq!(0, ALLOWANCE.prefix(b"owner3").count(&store, None, None));
+        assert_eq!(
+            1,
+            ALLOWANCE.prefix(b"owner").count(
+                &store,
+                Some(Bound::exclusive(b"spender".to_vec())),
+                None
+            )
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "iterator")]
+    fn clear_map_and_prefix() {
+        let mut store = MockStorage::new();
+
+        ALLOWANCE
+            .save(&mut store, (b"owner", b"spender"), &1000)
+            .unwrap();
+        ALLOWANCE
+            .save(&mut store, (b"owner", b"spender2"), &3000)
+            .unwrap();
+        ALLOWANCE
+            .save(&mut store, (b"owner2", b"spender"), &5000)
+            .unwrap();
+        PEOPLE
+            .save(
+                &mut store,
+                b"john",
+                &Data {
+                    name: "John".to_string(),
+                    age: 32,
+                },
+            )
+            .unwrap();
+
+        // clear only one owner
+        assert!(!ALLOWANCE.prefix(b"owner").is_empty(&store));
+        let removed = ALLOWANCE.prefix(b"owner").clear(&mut store, None);
+        assert_eq!(2, removed);
+        assert!(ALLOWANCE.prefix(b"owner").is_empty(&store));
+        assert!(!ALLOWANCE.prefix(b"owner2").is_empty(&store));
+        assert_eq!(
+            None,
+            ALLOWANCE.may_load(&store, (b"owner", b"spender")).unwrap()
+        );
+        assert_eq!(
+            5000,
+            ALLOWANCE.load(&store, (b"owner2", b"spender")).unwrap()
+        );
+
+        // clear everything, other maps untouched
+        ALLOWANCE.clear(&mut store);
+        assert_eq!(0, ALLOWANCE.count(&store, None, None));
+        assert_eq!(1, PEOPLE.count(&store, None, None));
+    }
+
     #[test]
     fn basic_update() {
         let mut store = MockStorage::new();
@@ -361,6 +961,52 @@ mod test {
         assert_eq!(20, loaded);
     }
 
+    #[test]
+    fn update_many_returns_old_values() {
+        let mut store = MockStorage::new();
+        ALLOWANCE.save(&mut store, (b"owner", b"a"), &5).unwrap();
+        ALLOWANCE.save(&mut store, (b"owner", b"b"), &7).unwrap();
+
+        let olds = ALLOWANCE
+            .update_many(
+                &mut store,
+                vec![
+                    ((b"owner".as_ref(), b"a".as_ref()), Some(15)),
+                    ((b"owner".as_ref(), b"b".as_ref()), None),
+                    ((b"owner".as_ref(), b"c".as_ref()), Some(3)),
+                    // sees the write above
+                    ((b"owner".as_ref(), b"c".as_ref()), Some(4)),
+                ],
+            )
+            .unwrap();
+        assert_eq!(olds, vec![Some(5), Some(7), None, Some(3)]);
+
+        assert_eq!(15, ALLOWANCE.load(&store, (b"owner", b"a")).unwrap());
+        assert_eq!(None, ALLOWANCE.may_load(&store, (b"owner", b"b")).unwrap());
+        assert_eq!(4, ALLOWANCE.load(&store, (b"owner", b"c")).unwrap());
+    }
+
+    #[test]
+    fn query_remote_map() {
+        let mut querier = MockRawQuerier {
+            contract: HumanAddr::from("remote"),
+            storage: MockStorage::new(),
+        };
+        let key: (&[u8], &[u8]) = (b"owner", b"spender");
+        ALLOWANCE.save(&mut querier.storage, key, &1234).unwrap();
+
+        let wrapper = QuerierWrapper::new(&querier);
+        let allowance = ALLOWANCE.query(&wrapper, "remote".into(), key).unwrap();
+        assert_eq!(Some(1234), allowance);
+        let missing = ALLOWANCE
+            .query(&wrapper, "remote".into(), (b"owner", b"other"))
+            .unwrap();
+        assert_eq!(None, missing);
+        ALLOWANCE
+            .query_must(&wrapper, "remote".into(), (b"owner", b"other"))
+            .unwrap_err();
+    }
+
     #[test]
     fn readme_works() -> StdResult<()> {
         let mut store = MockStorage::new();
@@ -0,0 +1,245 @@
+#![cfg(feature = "iterator")]
+//! Helpers to move existing data into a new storage layout, such as the legacy
+//! `cosmwasm_storage` `Bucket`/`Singleton` types or a `Map` with another namespace or key type.
+//!
+//! Large maps can be moved over several transactions: every call handles at most `limit`
+//! entries and returns a cursor to continue from.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use cosmwasm_std::{from_slice, Order, StdError, StdResult, Storage};
+
+use crate::codec::Codec;
+use crate::helpers::namespaces_with_key;
+use crate::item::Item;
+use crate::iter_helpers::concat;
+use crate::keys::PrimaryKey;
+use crate::map::Map;
+use crate::prefix::Prefix;
+
+/// The result of one batch of a migration
+#[derive(Clone, Debug, PartialEq)]
+pub struct Progress {
+    /// Number of entries read (and removed) from the source in this batch
+    pub moved: usize,
+    /// Pass this as `start_after` to the next call. None means the migration is complete.
+    pub next_key: Option<Vec<u8>>,
+}
+
+/// Moves up to `limit` entries from `from` into `to`, in ascending order of the source keys.
+/// `limit` must be greater than zero.
+///
+/// `convert` gets the raw key (relative to the source namespace) and the value, and returns
+/// the new key and value, or None to drop the entry. Every entry read is removed from the
+/// source, so it is safe to rewrite the values of a map in place. When the key format changes,
+/// `to` must use another namespace, or new keys may be read again in a later batch.
+pub fn migrate_map<'a, 'b, K, T, C, NK, NT, NC, F>(
+    store: &mut dyn Storage,
+    from: &Map<'a, K, T, C>,
+    to: &Map<'b, NK, NT, NC>,
+    start_after: Option<Vec<u8>>,
+    limit: usize,
+    convert: F,
+) -> StdResult<Progress>
+where
+    K: PrimaryKey<'a>,
+    T: Serialize + DeserializeOwned,
+    C: Codec,
+    NK: PrimaryKey<'b>,
+    NT: Serialize + DeserializeOwned,
+    NC: Codec,
+    F: FnMut(Vec<u8>, T) -> StdResult<Option<(NK, NT)>>,
+{
+    migrate_prefix(store, from.no_prefix(), to, start_after, limit, convert)
+}
+
+/// Like `migrate_map`, but reads a legacy `Bucket` (JSON encoded). `namespaces` is the one
+/// namespace given to `Bucket::new`, or all the namespaces given to `Bucket::multilevel`.
+///
+/// A bucket with a single namespace has the same layout as `Map<&[u8], T>`, and a multilevel
+/// one the same as a `Map` with a tuple key. So if the format does not change, you can also
+/// just point a `Map` at the existing data.
+pub fn migrate_bucket<'b, T, NK, NT, NC, F>(
+    store: &mut dyn Storage,
+    namespaces: &[&[u8]],
+    to: &Map<'b, NK, NT, NC>,
+    start_after: Option<Vec<u8>>,
+    limit: usize,
+    convert: F,
+) -> StdResult<Progress>
+where
+    T: Serialize + DeserializeOwned,
+    NK: PrimaryKey<'b>,
+    NT: Serialize + DeserializeOwned,
+    NC: Codec,
+    F: FnMut(Vec<u8>, T) -> StdResult<Option<(NK, NT)>>,
+{
+    let (top, subs) = namespaces
+        .split_first()
+        .ok_or_else(|| StdError::generic_err("Bucket needs at least one namespace"))?;
+    let prefix = Prefix::new(top, subs);
+    migrate_prefix(store, prefix, to, start_after, limit, convert)
+}
+
+/// Moves the value of a legacy `Singleton` (JSON encoded) into `to`, using its codec.
+/// Returns the value moved, or None if there was nothing stored under `key`.
+pub fn migrate_singleton<T, C>(
+    store: &mut dyn Storage,
+    key: &[u8],
+    to: &Item<T, C>,
+) -> StdResult<Option<T>>
+where
+    T: Serialize + DeserializeOwned,
+    C: Codec,
+{
+    let legacy_key = namespaces_with_key(&[key], b"");
+    match store.get(&legacy_key) {
+        Some(value) => {
+            let data: T = from_slice(&value)?;
+            store.remove(&legacy_key);
+            to.save(store, &data)?;
+            Ok(Some(data))
+        }
+        None => Ok(None),
+    }
+}
+
+fn migrate_prefix<'b, T, NK, NT, NC, F>(
+    store: &mut dyn Storage,
+    from: Prefix<T>,
+    to: &Map<'b, NK, NT, NC>,
+    start_after: Option<Vec<u8>>,
+    limit: usize,
+    mut convert: F,
+) -> StdResult<Progress>
+where
+    T: Serialize + DeserializeOwned,
+    NK: PrimaryKey<'b>,
+    NT: Serialize + DeserializeOwned,
+    NC: Codec,
+    F: FnMut(Vec<u8>, T) -> StdResult<Option<(NK, NT)>>,
+{
+    let page = from.page(store, start_after, limit, Order::Ascending)?;
+    let moved = page.items.len();
+    for (k, v) in page.items {
+        // remove first, so rewriting the same key works
+        store.remove(&concat(&from, &k));
+        if let Some((key, data)) = convert(k, v)? {
+            to.save(store, key, &data)?;
+        }
+    }
+    Ok(Progress {
+        moved,
+        next_key: page.next_key,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{PkOwned, U64Key};
+    use cosmwasm_std::testing::MockStorage;
+    use cosmwasm_std::to_vec;
+
+    #[test]
+    fn rekey_map_in_batches() {
+        let mut store = MockStorage::new();
+        const OLD: Map<&[u8], u64> = Map::new("old");
+        const NEW: Map<U64Key, String> = Map::new("new");
+        for i in 1..=5u64 {
+            OLD.save(&mut store, i.to_string().as_bytes(), &(i * 10))
+                .unwrap();
+        }
+
+        // the key becomes an int, the value a string, and 3 is dropped
+        let convert = |k: Vec<u8>, v: u64| -> StdResult<_> {
+            let k: u64 = String::from_utf8(k)?.parse().unwrap();
+            Ok(if k == 3 {
+                None
+            } else {
+                Some((U64Key::from(k), v.to_string()))
+            })
+        };
+
+        let progress = migrate_map(&mut store, &OLD, &NEW, None, 2, convert).unwrap();
+        assert_eq!(progress.moved, 2);
+        assert_eq!(progress.next_key, Some(b"2".to_vec()));
+        assert_eq!(3, OLD.count(&store, None, None));
+        assert_eq!(2, NEW.count(&store, None, None));
+
+        let progress = migrate_map(&mut store, &OLD, &NEW, progress.next_key, 2, convert).unwrap();
+        assert_eq!(progress.moved, 2);
+        let progress = migrate_map(&mut store, &OLD, &NEW, progress.next_key, 2, convert).unwrap();
+        assert_eq!(progress.moved, 1);
+        assert_eq!(progress.next_key, None);
+
+        assert_eq!(0, OLD.count(&store, None, None));
+        let moved: Vec<_> = NEW
+            .range_de(&store, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert_eq!(
+            moved,
+            vec![
+                (1, "10".to_string()),
+                (2, "20".to_string()),
+                (4, "40".to_string()),
+                (5, "50".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rewrite_values_in_place() {
+        let mut store = MockStorage::new();
+        const OLD: Map<&[u8], u64> = Map::new("data");
+        const NEW: Map<Vec<u8>, String> = Map::new("data");
+        OLD.save(&mut store, b"a", &1).unwrap();
+        OLD.save(&mut store, b"b", &2).unwrap();
+
+        let progress = migrate_map(&mut store, &OLD, &NEW, None, 10, |k, v| {
+            Ok(Some((k, v.to_string())))
+        })
+        .unwrap();
+        assert_eq!(progress.moved, 2);
+        assert_eq!(progress.next_key, None);
+        assert_eq!("1", NEW.load(&store, b"a".to_vec()).unwrap());
+        assert_eq!("2", NEW.load(&store, b"b".to_vec()).unwrap());
+    }
+
+    #[test]
+    fn move_legacy_bucket_and_singleton() {
+        let mut store = MockStorage::new();
+        // the layout of Bucket::multilevel(&[b"allowance", b"owner"]) and singleton(b"config")
+        let bucket_key = |k: &[u8]| namespaces_with_key(&[b"allowance", b"owner"], k);
+        store.set(&bucket_key(b"alice"), &to_vec(&5u64).unwrap());
+        store.set(&bucket_key(b"bob"), &to_vec(&7u64).unwrap());
+        store.set(b"\x00\x06config", &to_vec("hello").unwrap());
+
+        const ALLOWANCES: Map<(PkOwned, PkOwned), u64> = Map::new("allowances");
+        let progress = migrate_bucket(
+            &mut store,
+            &[b"allowance", b"owner"],
+            &ALLOWANCES,
+            None,
+            10,
+            |k, v: u64| Ok(Some(((PkOwned(b"owner".to_vec()), PkOwned(k)), v))),
+        )
+        .unwrap();
+        assert_eq!(progress.moved, 2);
+        assert_eq!(None, store.get(&bucket_key(b"alice")));
+        let key = (PkOwned(b"owner".to_vec()), PkOwned(b"bob".to_vec()));
+        assert_eq!(7, ALLOWANCES.load(&store, key).unwrap());
+
+        const CONFIG: Item<String> = Item::new("config");
+        let moved = migrate_singleton(&mut store, b"config", &CONFIG).unwrap();
+        assert_eq!(moved, Some("hello".to_string()));
+        assert_eq!("hello", CONFIG.load(&store).unwrap());
+        assert_eq!(None, store.get(b"\x00\x06config"));
+        // nothing left to move
+        let moved = migrate_singleton(&mut store, b"config", &CONFIG).unwrap();
+        assert_eq!(moved, None);
+    }
+}
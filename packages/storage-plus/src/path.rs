@@ -2,12 +2,13 @@ use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::marker::PhantomData;
 
+use crate::codec::{Codec, JsonCodec};
 use crate::helpers::{may_deserialize, must_deserialize, nested_namespaces_with_key};
-use cosmwasm_std::{to_vec, StdError, StdResult, Storage};
+use cosmwasm_std::{HumanAddr, QuerierWrapper, StdError, StdResult, Storage};
 use std::ops::Deref;
 
 #[derive(Debug, Clone)]
-pub struct Path<T>
+pub struct Path<T, C = JsonCodec>
 where
     T: Serialize + DeserializeOwned,
 {
@@ -15,9 +16,10 @@ where
     storage_key: Vec<u8>,
     // see https://doc.rust-lang.org/std/marker/struct.PhantomData.html#unused-type-parameters for why this is needed
     data: PhantomData<T>,
+    codec: PhantomData<C>,
 }
 
-impl<T> Deref for Path<T>
+impl<T, C> Deref for Path<T, C>
 where
     T: Serialize + DeserializeOwned,
 {
@@ -28,9 +30,10 @@ where
     }
 }
 
-impl<T> Path<T>
+impl<T, C> Path<T, C>
 where
     T: Serialize + DeserializeOwned,
+    C: Codec,
 {
     pub fn new(namespace: &[u8], keys: &[&[u8]]) -> Self {
         let l = keys.len();
@@ -39,12 +42,13 @@ where
         Path {
             storage_key,
             data: PhantomData,
+            codec: PhantomData,
         }
     }
 
     /// save will serialize the model and store, returns an error on serialization issues
     pub fn save(&self, store: &mut dyn Storage, data: &T) -> StdResult<()> {
-        store.set(&self.storage_key, &to_vec(data)?);
+        store.set(&self.storage_key, &C::encode(data)?);
         Ok(())
     }
 
@@ -55,14 +59,19 @@ where
     /// load will return an error if no data is set at the given key, or on parse error
     pub fn load(&self, store: &dyn Storage) -> StdResult<T> {
         let value = store.get(&self.storage_key);
-        must_deserialize(&value)
+        must_deserialize::<T, C>(&value)
     }
 
     /// may_load will parse the data stored at the key if present, returns Ok(None) if no data there.
     /// returns an error on issues parsing
     pub fn may_load(&self, store: &dyn Storage) -> StdResult<Option<T>> {
         let value = store.get(&self.storage_key);
-        may_deserialize(&value)
+        may_deserialize::<T, C>(&value)
+    }
+
+    /// has returns true if any data is stored at this path. It never parses the value.
+    pub fn has(&self, store: &dyn Storage) -> bool {
+        store.get(&self.storage_key).is_some()
     }
 
     /// Loads the data, perform the specified action, and store the result
@@ -79,4 +88,21 @@ where
         self.save(store, &output)?;
         Ok(output)
     }
+
+    /// Reads the data at this path from the storage of another contract with a raw query.
+    /// Returns Ok(None) if no data is set there.
+    pub fn query(
+        &self,
+        querier: &QuerierWrapper,
+        remote_contract: HumanAddr,
+    ) -> StdResult<Option<T>> {
+        let value = querier.query_wasm_raw(remote_contract, self.storage_key.as_slice())?;
+        may_deserialize::<T, C>(&value)
+    }
+
+    /// Like `query`, but returns an error if no data is set there, like `load`
+    pub fn query_must(&self, querier: &QuerierWrapper, remote_contract: HumanAddr) -> StdResult<T> {
+        let value = querier.query_wasm_raw(remote_contract, self.storage_key.as_slice())?;
+        must_deserialize::<T, C>(&value)
+    }
 }
@@ -3,11 +3,13 @@ use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::marker::PhantomData;
 
-use cosmwasm_std::{Order, StdResult, Storage, KV};
+use cosmwasm_std::{Order, StdError, StdResult, Storage, KV};
 use std::ops::Deref;
 
+use crate::de::KeyDeserialize;
 use crate::helpers::nested_namespaces_with_key;
 use crate::iter_helpers::{concat, deserialize_kv, trim};
+use crate::keys::{Prefixer, PrimaryKey};
 use crate::Endian;
 
 /// Bound is used to defines the two ends of a range, more explicit than Option<u8>
@@ -40,10 +42,45 @@ impl Bound {
     pub fn exclusive_int<T: Endian>(limit: T) -> Self {
         Bound::Exclusive(limit.to_be_bytes().into())
     }
+
+    /// Turns a typed key, like Option<(&[u8], U64Key)> into an inclusive bound.
+    /// Composite keys are length-prefixed just like they are in storage.
+    pub fn inclusive_key<'a, K: PrimaryKey<'a>>(limit: K) -> Self {
+        Bound::Inclusive(limit.joined_key())
+    }
+
+    /// Turns a typed key, like Option<(&[u8], U64Key)> into an exclusive bound.
+    /// Composite keys are length-prefixed just like they are in storage.
+    pub fn exclusive_key<'a, K: PrimaryKey<'a>>(limit: K) -> Self {
+        Bound::Exclusive(limit.joined_key())
+    }
+
+    /// Bound on the leading elements of the remaining key, eg. the age when ranging over
+    /// (age, pk) below a `sub_prefix`. Used as `min`, it includes all keys starting with `limit`.
+    pub fn inclusive_prefix<'a, P: Prefixer<'a>>(limit: P) -> Self {
+        Bound::Inclusive(nested_namespaces_with_key(&[], &limit.prefix(), b""))
+    }
+
+    /// Bound on the leading elements of the remaining key, eg. the age when ranging over
+    /// (age, pk) below a `sub_prefix`. Used as `max`, it excludes all keys starting with `limit`.
+    pub fn exclusive_prefix<'a, P: Prefixer<'a>>(limit: P) -> Self {
+        Bound::Exclusive(nested_namespaces_with_key(&[], &limit.prefix(), b""))
+    }
 }
 
+/// Page is one chunk of a paginated range, as returned by `Prefix::page`.
+/// If there may be more entries, `next_key` holds the raw key of the last entry,
+/// which is passed as `start_after` to load the following page.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Page<T> {
+    pub items: Vec<KV<T>>,
+    pub next_key: Option<Vec<u8>>,
+}
+
+/// Prefix is a range-able subset of a `Map`. `K` is the type of the remaining key under this prefix,
+/// which is only used by `range_de` and `keys_de` to deserialize the keys.
 #[derive(Debug, Clone)]
-pub struct Prefix<T>
+pub struct Prefix<T, K = Vec<u8>>
 where
     T: Serialize + DeserializeOwned,
 {
@@ -51,10 +88,17 @@ where
     storage_prefix: Vec<u8>,
     // see https://doc.rust-lang.org/std/marker/struct.PhantomData.html#unused-type-parameters for why this is needed
     data: PhantomData<T>,
-    de_fn: fn(KV) -> StdResult<KV<T>>,
+    key_type: PhantomData<K>,
+    // namespace of the primary map, passed to de_fn (used by indexes that point to the pk)
+    pk_name: Vec<u8>,
+    de_fn: DeserializeFn<T>,
 }
 
-impl<T> Deref for Prefix<T>
+/// Turns a raw (key, value) pair from storage into the (pk, data) returned by `range`.
+/// It gets the storage and primary namespace, so an index can load the data it points to.
+pub(crate) type DeserializeFn<T> = fn(&dyn Storage, &[u8], KV) -> StdResult<KV<T>>;
+
+impl<T, K> Deref for Prefix<T, K>
 where
     T: Serialize + DeserializeOwned,
 {
@@ -65,24 +109,27 @@ where
     }
 }
 
-impl<T> Prefix<T>
+impl<T, K> Prefix<T, K>
 where
     T: Serialize + DeserializeOwned,
 {
     pub fn new(top_name: &[u8], sub_names: &[&[u8]]) -> Self {
-        Prefix::new_de_fn(top_name, sub_names, deserialize_kv)
+        Prefix::new_de_fn(top_name, sub_names, top_name, deserialize_kv)
     }
 
     pub fn new_de_fn(
         top_name: &[u8],
         sub_names: &[&[u8]],
-        de_fn: fn(KV) -> StdResult<KV<T>>,
+        pk_name: &[u8],
+        de_fn: DeserializeFn<T>,
     ) -> Self {
         // FIXME: we can use a custom function here, probably make this cleaner
         let storage_prefix = nested_namespaces_with_key(&[top_name], sub_names, b"");
         Prefix {
             storage_prefix,
             data: PhantomData,
+            key_type: PhantomData,
+            pk_name: pk_name.to_vec(),
             de_fn,
         }
     }
@@ -97,8 +144,154 @@ where
     where
         T: 'a,
     {
+        let de_fn = self.de_fn;
+        let pk_name = self.pk_name.clone();
+        let mapped = range_with_prefix(store, &self.storage_prefix, min, max, order)
+            .map(move |kv| de_fn(store, &pk_name, kv));
+        Box::new(mapped)
+    }
+
+    /// Loads up to `limit` entries after `start_after` in the given order (for Descending,
+    /// "after" means "lower"). The cursor is the raw key under this prefix, which is not always
+    /// the key returned in `items` (indexes return the pk there), so always use `next_key`.
+    ///
+    /// Returns an error if `limit` is 0, as such a page could not tell whether it is the last one.
+    pub fn page(
+        &self,
+        store: &dyn Storage,
+        start_after: Option<Vec<u8>>,
+        limit: usize,
+        order: Order,
+    ) -> StdResult<Page<T>> {
+        if limit == 0 {
+            return Err(StdError::generic_err(
+                "Page limit must be greater than zero",
+            ));
+        }
+        let start_after = start_after.map(Bound::Exclusive);
+        let (min, max) = match order {
+            Order::Ascending => (start_after, None),
+            Order::Descending => (None, start_after),
+        };
+        // load one more, so we know if there is a next page
+        let mut raw: Vec<KV> = range_with_prefix(store, &self.storage_prefix, min, max, order)
+            .take(limit.saturating_add(1))
+            .collect();
+        let next_key = if raw.len() > limit {
+            raw.truncate(limit);
+            raw.last().map(|(k, _)| k.clone())
+        } else {
+            None
+        };
+        let items = raw
+            .into_iter()
+            .map(|kv| (self.de_fn)(store, &self.pk_name, kv))
+            .collect::<StdResult<_>>()?;
+        Ok(Page { items, next_key })
+    }
+
+    /// Returns only the keys under this prefix, as raw bytes relative to the prefix
+    /// (like the keys returned by `range`). This never parses the values.
+    pub fn keys<'a>(
+        &self,
+        store: &'a dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = Vec<u8>> + 'a> {
+        let mapped =
+            range_with_prefix(store, &self.storage_prefix, min, max, order).map(|(k, _)| k);
+        Box::new(mapped)
+    }
+
+    /// Returns the complete storage keys (including all namespaces) under this prefix,
+    /// which can be used directly with `Storage::get`, `Storage::remove` or a raw query.
+    /// This never parses the values.
+    pub fn keys_raw<'a>(
+        &self,
+        store: &'a dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = Vec<u8>> + 'a> {
+        let mapped =
+            range_with_full_keys(store, &self.storage_prefix, min, max, order).map(|(k, _)| k);
+        Box::new(mapped)
+    }
+
+    /// Returns true if there are no entries under this prefix. This reads at most one raw entry.
+    pub fn is_empty(&self, store: &dyn Storage) -> bool {
+        range_with_full_keys(store, &self.storage_prefix, None, None, Order::Ascending)
+            .next()
+            .is_none()
+    }
+
+    /// Counts the entries between min and max, without parsing any values
+    pub fn count(&self, store: &dyn Storage, min: Option<Bound>, max: Option<Bound>) -> usize {
+        self.keys(store, min, max, Order::Ascending).count()
+    }
+
+    /// Removes the entries under this prefix, in ascending order.
+    /// If limit is set, it removes at most that many entries, so a large prefix can be cleared
+    /// over several transactions. Returns the number of entries removed, if it is less than
+    /// limit, the prefix is now empty.
+    pub fn clear(&self, store: &mut dyn Storage, limit: Option<usize>) -> usize {
+        // we cannot remove while iterating, so collect the keys first
+        let keys: Vec<_> = {
+            let keys = self.keys_raw(store, None, None, Order::Ascending);
+            match limit {
+                Some(limit) => keys.take(limit).collect(),
+                None => keys.collect(),
+            }
+        };
+        for key in keys.iter() {
+            store.remove(key);
+        }
+        keys.len()
+    }
+}
+
+impl<T, K> Prefix<T, K>
+where
+    T: Serialize + DeserializeOwned,
+    K: KeyDeserialize,
+{
+    /// Like `range`, but deserializes the keys into `K::Output` rather than returning raw bytes
+    pub fn range_de<'a>(
+        &self,
+        store: &'a dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = StdResult<(K::Output, T)>> + 'a>
+    where
+        T: 'a,
+        K::Output: 'a,
+    {
+        let de_fn = self.de_fn;
+        let pk_name = self.pk_name.clone();
         let mapped =
-            range_with_prefix(store, &self.storage_prefix, min, max, order).map(self.de_fn);
+            range_with_prefix(store, &self.storage_prefix, min, max, order).map(move |kv| {
+                let (k, v) = de_fn(store, &pk_name, kv)?;
+                Ok((K::from_vec(k)?, v))
+            });
+        Box::new(mapped)
+    }
+
+    /// Returns only the deserialized keys under this prefix.
+    /// Note that this reads the raw storage keys, it never parses the values.
+    pub fn keys_de<'a>(
+        &self,
+        store: &'a dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = StdResult<K::Output>> + 'a>
+    where
+        K::Output: 'a,
+    {
+        let mapped = range_with_prefix(store, &self.storage_prefix, min, max, order)
+            .map(|(k, _)| K::from_vec(k));
         Box::new(mapped)
     }
 }
@@ -110,11 +303,7 @@ pub fn range_with_prefix<'a>(
     end: Option<Bound>,
     order: Order,
 ) -> Box<dyn Iterator<Item = KV> + 'a> {
-    let start = calc_start_bound(namespace, start);
-    let end = calc_end_bound(namespace, end);
-
-    // get iterator from storage
-    let base_iterator = storage.range(Some(&start), Some(&end), order);
+    let base_iterator = range_with_full_keys(storage, namespace, start, end, order);
 
     // make a copy for the closure to handle lifetimes safely
     let prefix = namespace.to_vec();
@@ -122,6 +311,22 @@ pub fn range_with_prefix<'a>(
     Box::new(mapped)
 }
 
+/// Like range_with_prefix, but the keys still contain the namespace, so they can be used
+/// directly with Storage::get or Storage::remove
+fn range_with_full_keys<'a>(
+    storage: &'a dyn Storage,
+    namespace: &[u8],
+    start: Option<Bound>,
+    end: Option<Bound>,
+    order: Order,
+) -> Box<dyn Iterator<Item = KV> + 'a> {
+    let start = calc_start_bound(namespace, start);
+    let end = calc_end_bound(namespace, end);
+
+    // get iterator from storage
+    storage.range(Some(&start), Some(&end), order)
+}
+
 fn calc_start_bound(namespace: &[u8], bound: Option<Bound>) -> Vec<u8> {
     match bound {
         None => namespace.to_vec(),
@@ -168,13 +373,116 @@ mod test {
     use super::*;
     use cosmwasm_std::testing::MockStorage;
 
+    #[test]
+    fn keys_and_count() {
+        let mut store = MockStorage::new();
+        let prefix: Prefix<u64> = Prefix::new(b"foo", &[]);
+        let other: Prefix<u64> = Prefix::new(b"food", &[]);
+
+        // the values are not valid json, so anything parsing them would fail
+        store.set(&concat(&prefix, b"bar"), b"not json");
+        store.set(&concat(&prefix, b"ra"), b"not json");
+        store.set(&concat(&prefix, b"zi"), b"not json");
+        store.set(&concat(&other, b"bar"), b"not json");
+
+        let keys: Vec<_> = prefix.keys(&store, None, None, Order::Ascending).collect();
+        assert_eq!(keys, vec![b"bar".to_vec(), b"ra".to_vec(), b"zi".to_vec()]);
+
+        let keys: Vec<_> = prefix
+            .keys(
+                &store,
+                Some(Bound::exclusive(b"bar".to_vec())),
+                None,
+                Order::Descending,
+            )
+            .collect();
+        assert_eq!(keys, vec![b"zi".to_vec(), b"ra".to_vec()]);
+
+        // raw keys include the namespace and can be read back directly
+        let raw: Vec<_> = prefix
+            .keys_raw(&store, None, None, Order::Ascending)
+            .collect();
+        assert_eq!(raw.len(), 3);
+        assert_eq!(raw[0], concat(&prefix, b"bar"));
+        assert_eq!(store.get(&raw[2]), Some(b"not json".to_vec()));
+
+        assert_eq!(prefix.count(&store, None, None), 3);
+        assert_eq!(other.count(&store, None, None), 1);
+        assert_eq!(
+            prefix.count(
+                &store,
+                Some(Bound::inclusive(b"ra".to_vec())),
+                Some(Bound::exclusive(b"zi".to_vec()))
+            ),
+            1
+        );
+    }
+
+    #[test]
+    fn clear_with_limit() {
+        let mut store = MockStorage::new();
+        let prefix: Prefix<u64> = Prefix::new(b"foo", &[]);
+        let other: Prefix<u64> = Prefix::new(b"food", &[]);
+
+        for k in &[b"a", b"b", b"c", b"d", b"e"] {
+            store.set(&concat(&prefix, *k), b"1");
+        }
+        store.set(&concat(&other, b"a"), b"2");
+
+        // take a few bites
+        assert_eq!(2, prefix.clear(&mut store, Some(2)));
+        let keys: Vec<_> = prefix.keys(&store, None, None, Order::Ascending).collect();
+        assert_eq!(keys, vec![b"c".to_vec(), b"d".to_vec(), b"e".to_vec()]);
+        assert_eq!(3, prefix.clear(&mut store, Some(10)));
+        assert_eq!(0, prefix.count(&store, None, None));
+        assert_eq!(0, prefix.clear(&mut store, None));
+
+        // nothing else was touched
+        assert_eq!(1, other.count(&store, None, None));
+        assert_eq!(1, other.clear(&mut store, None));
+        assert_eq!(0, other.count(&store, None, None));
+    }
+
+    #[test]
+    fn page_limits() {
+        let mut store = MockStorage::new();
+        let prefix: Prefix<u64> = Prefix::new(b"foo", &[]);
+        for (k, v) in &[(b"a", b"1"), (b"b", b"2"), (b"c", b"3")] {
+            store.set(&concat(&prefix, *k), *v);
+        }
+
+        // an empty page cannot tell if there is more
+        prefix.page(&store, None, 0, Order::Ascending).unwrap_err();
+
+        // a huge limit just loads everything
+        let page = prefix
+            .page(&store, None, usize::MAX, Order::Ascending)
+            .unwrap();
+        assert_eq!(
+            page.items,
+            vec![(b"a".to_vec(), 1), (b"b".to_vec(), 2), (b"c".to_vec(), 3)]
+        );
+        assert_eq!(page.next_key, None);
+
+        // a page ending exactly at the last entry has no next page
+        let page = prefix
+            .page(&store, Some(b"a".to_vec()), 2, Order::Ascending)
+            .unwrap();
+        assert_eq!(page.next_key, None);
+        let page = prefix.page(&store, None, 3, Order::Descending).unwrap();
+        assert_eq!(page.items.len(), 3);
+        assert_eq!(page.next_key, None);
+    }
+
     #[test]
     fn ensure_proper_range_bounds() {
         let mut store = MockStorage::new();
         // manually create this - not testing nested prefixes here
-        let prefix = Prefix {
+        let prefix: Prefix<u64> = Prefix {
             storage_prefix: b"foo".to_vec(),
-            data: PhantomData::<u64>,
+            data: PhantomData,
+            key_type: PhantomData,
+            pk_name: vec![],
             de_fn: deserialize_kv,
         };
 
@@ -0,0 +1,299 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use cosmwasm_std::{Order, StdError, StdResult, Storage};
+
+use crate::codec::{Codec, JsonCodec};
+use crate::item::Item;
+use crate::keys::U64Key;
+use crate::map::Map;
+use crate::snapshot::{ChangeSet, Checkpoints, Strategy};
+use crate::Bound;
+
+/// Item that maintains a snapshot of one or more checkpoints.
+/// We can query historical data as well as current state.
+/// What data is snapshotted depends on the Strategy.
+/// The value (and the changelog) is encoded with `C`, which is JSON unless specified otherwise.
+pub struct SnapshotItem<'a, T, C = JsonCodec> {
+    primary: Item<'a, T, C>,
+
+    checkpoints: Checkpoints<'a, C>,
+
+    // this stores all changes by height. Must differentiate between no data written,
+    // and explicit None (just inserted)
+    changelog: Map<'a, U64Key, ChangeSet<T>, C>,
+}
+
+impl<'a, T, C> SnapshotItem<'a, T, C> {
+    /// Usage: SnapshotItem::new("total", "total__check", "total__change", Strategy::EveryBlock)
+    ///
+    /// The primary storage key is used just like in `Item`, so an existing `Item` can be
+    /// turned into a `SnapshotItem` without moving the current value.
+    pub const fn new(
+        storage_key: &'a str,
+        checkpoints: &'a str,
+        changelog: &'a str,
+        strategy: Strategy,
+    ) -> Self {
+        SnapshotItem {
+            primary: Item::new(storage_key),
+            checkpoints: Checkpoints::new(checkpoints, changelog, strategy),
+            changelog: Map::new(changelog),
+        }
+    }
+
+    pub fn add_checkpoint(&self, store: &mut dyn Storage, height: u64) -> StdResult<()> {
+        self.checkpoints.add(store, height)
+    }
+
+    pub fn remove_checkpoint(&self, store: &mut dyn Storage, height: u64) -> StdResult<()> {
+        self.checkpoints.remove(store, height)
+    }
+}
+
+impl<'a, T, C> SnapshotItem<'a, T, C>
+where
+    T: Serialize + DeserializeOwned + Clone,
+    C: Codec,
+{
+    /// should_checkpoint looks at the strategy and determines if we want to checkpoint
+    fn should_checkpoint(&self, store: &dyn Storage) -> StdResult<bool> {
+        match self.checkpoints.strategy() {
+            Strategy::EveryBlock => Ok(true),
+            Strategy::Never => Ok(false),
+            Strategy::Selected => self.should_checkpoint_selected(store),
+        }
+    }
+
+    /// this is just pulled out from above for the selected block
+    fn should_checkpoint_selected(&self, store: &dyn Storage) -> StdResult<bool> {
+        // most recent checkpoint
+        if let Some(height) = self.checkpoints.latest(store)? {
+            // any changelog since then?
+            let start = Bound::inclusive(U64Key::from(height));
+            let first = self
+                .changelog
+                .keys(store, Some(start), None, Order::Ascending)
+                .next();
+            if first.is_none() {
+                // there must be at least one open checkpoint and no changelog since then
+                return Ok(true);
+            }
+        }
+        // otherwise, we don't save this
+        Ok(false)
+    }
+
+    /// load old value and store changelog
+    fn write_change(&self, store: &mut dyn Storage, height: u64) -> StdResult<()> {
+        // if there is already data in the changelog for this block, do not write more
+        if self
+            .changelog
+            .may_load(store, U64Key::from(height))?
+            .is_some()
+        {
+            return Ok(());
+        }
+        // otherwise, store the previous value
+        let old = self.primary.may_load(store)?;
+        self.changelog
+            .save(store, U64Key::from(height), &ChangeSet { old })
+    }
+
+    pub fn save(&self, store: &mut dyn Storage, data: &T, height: u64) -> StdResult<()> {
+        if self.should_checkpoint(store)? {
+            self.write_change(store, height)?;
+        }
+        self.primary.save(store, data)
+    }
+
+    pub fn remove(&self, store: &mut dyn Storage, height: u64) -> StdResult<()> {
+        if self.should_checkpoint(store)? {
+            self.write_change(store, height)?;
+        }
+        self.primary.remove(store);
+        Ok(())
+    }
+
+    /// load will return an error if no data is set, or on parse error
+    pub fn load(&self, store: &dyn Storage) -> StdResult<T> {
+        self.primary.load(store)
+    }
+
+    /// may_load will parse the data stored if present, returns Ok(None) if no data there.
+    /// returns an error on issues parsing
+    pub fn may_load(&self, store: &dyn Storage) -> StdResult<Option<T>> {
+        self.primary.may_load(store)
+    }
+
+    // may_load_at_height reads historical data from given checkpoints.
+    // Only returns `Ok` if we have the data to be able to give the correct answer
+    // (Strategy::EveryBlock or Strategy::Selected and h is registered as checkpoint)
+    //
+    // If there is no checkpoint for that height, then we return StdError::NotFound
+    pub fn may_load_at_height(&self, store: &dyn Storage, height: u64) -> StdResult<Option<T>> {
+        self.assert_checkpointed(store, height)?;
+
+        // this will look for the first snapshot >= given height
+        // If None, there is no snapshot since that time.
+        let start = Bound::inclusive(U64Key::new(height));
+        let first = self
+            .changelog
+            .range(store, Some(start), None, Order::Ascending)
+            .next();
+
+        if let Some(r) = first {
+            // if we found a match, return this last one
+            r.map(|(_, v)| v.old)
+        } else {
+            // otherwise, return current value
+            self.may_load(store)
+        }
+    }
+
+    // If there is no checkpoint for that height, then we return StdError::NotFound
+    pub fn assert_checkpointed(&self, store: &dyn Storage, height: u64) -> StdResult<()> {
+        self.checkpoints.assert_checkpointed(store, height)
+    }
+
+    /// Loads the data, perform the specified action, and store the result
+    /// in the database. This is shorthand for some common sequences, which may be useful.
+    ///
+    /// If the data exists, `action(Some(value))` is called. Otherwise `action(None)` is called.
+    pub fn update<A, E>(&self, store: &mut dyn Storage, height: u64, action: A) -> Result<T, E>
+    where
+        A: FnOnce(Option<T>) -> Result<T, E>,
+        E: From<StdError>,
+    {
+        let input = self.may_load(store)?;
+        let output = action(input)?;
+        self.save(store, &output, height)?;
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+
+    type TestItem = SnapshotItem<'static, u64>;
+    const NEVER: TestItem =
+        SnapshotItem::new("never", "never__check", "never__change", Strategy::Never);
+    const EVERY: TestItem = SnapshotItem::new(
+        "every",
+        "every__check",
+        "every__change",
+        Strategy::EveryBlock,
+    );
+    const SELECT: TestItem = SnapshotItem::new(
+        "select",
+        "select__check",
+        "select__change",
+        Strategy::Selected,
+    );
+
+    // Fills an item with the following writes:
+    // 1: 5
+    // 2: 7
+    // 3: 8
+    // 4: 1
+    // 5: None
+    // Final value -> None
+    // Value at beginning of 3 -> 7
+    // Value at beginning of 5 -> 1
+    fn init_data(item: &TestItem, storage: &mut dyn Storage) {
+        item.save(storage, &5, 1).unwrap();
+        item.save(storage, &7, 2).unwrap();
+
+        // checkpoint 3
+        item.add_checkpoint(storage, 3).unwrap();
+
+        // also use update to set - to ensure this works
+        item.update(storage, 3, |_| -> StdResult<u64> { Ok(8) })
+            .unwrap();
+
+        item.save(storage, &1, 4).unwrap();
+
+        // checkpoint 5
+        item.add_checkpoint(storage, 5).unwrap();
+        item.remove(storage, 5).unwrap();
+        // and delete it later (unknown if all data present)
+        item.remove_checkpoint(storage, 5).unwrap();
+    }
+
+    #[test]
+    fn never_works_like_normal_item() {
+        let mut storage = MockStorage::new();
+        init_data(&NEVER, &mut storage);
+        assert_eq!(None, NEVER.may_load(&storage).unwrap());
+
+        // historical queries return error
+        NEVER.may_load_at_height(&storage, 3).unwrap_err();
+        NEVER.may_load_at_height(&storage, 5).unwrap_err();
+    }
+
+    #[test]
+    fn every_blocks_stores_present_and_past() {
+        let mut storage = MockStorage::new();
+        init_data(&EVERY, &mut storage);
+        assert_eq!(None, EVERY.may_load(&storage).unwrap());
+
+        // historical queries return historical values
+        assert_eq!(None, EVERY.may_load_at_height(&storage, 1).unwrap());
+        assert_eq!(Some(5), EVERY.may_load_at_height(&storage, 2).unwrap());
+        assert_eq!(Some(7), EVERY.may_load_at_height(&storage, 3).unwrap());
+        assert_eq!(Some(8), EVERY.may_load_at_height(&storage, 4).unwrap());
+        assert_eq!(Some(1), EVERY.may_load_at_height(&storage, 5).unwrap());
+        assert_eq!(None, EVERY.may_load_at_height(&storage, 6).unwrap());
+    }
+
+    #[test]
+    fn selected_shows_3_not_5() {
+        let mut storage = MockStorage::new();
+        init_data(&SELECT, &mut storage);
+        assert_eq!(None, SELECT.may_load(&storage).unwrap());
+
+        // historical queries return historical values
+        assert_eq!(Some(7), SELECT.may_load_at_height(&storage, 3).unwrap());
+        // never checkpointed
+        SELECT.may_load_at_height(&storage, 1).unwrap_err();
+        // deleted checkpoint
+        SELECT.may_load_at_height(&storage, 5).unwrap_err();
+    }
+
+    #[test]
+    fn handle_multiple_writes_in_one_block() {
+        let mut storage = MockStorage::new();
+
+        EVERY.save(&mut storage, &5, 1).unwrap();
+        EVERY.save(&mut storage, &7, 2).unwrap();
+
+        // update and save - query at 3 => 7, at 4 => 12
+        EVERY
+            .update(&mut storage, 3, |_| -> StdResult<u64> { Ok(9) })
+            .unwrap();
+        EVERY.save(&mut storage, &12, 3).unwrap();
+        assert_eq!(Some(7), EVERY.may_load_at_height(&storage, 3).unwrap());
+        assert_eq!(Some(12), EVERY.may_load_at_height(&storage, 4).unwrap());
+
+        // save and remove - query at 4 => 12, at 5 => None
+        EVERY.save(&mut storage, &17, 4).unwrap();
+        EVERY.remove(&mut storage, 4).unwrap();
+        assert_eq!(Some(12), EVERY.may_load_at_height(&storage, 4).unwrap());
+        assert_eq!(None, EVERY.may_load_at_height(&storage, 5).unwrap());
+    }
+
+    #[test]
+    fn keeps_item_storage_layout() {
+        let mut storage = MockStorage::new();
+        let plain: Item<u64> = Item::new("every");
+        plain.save(&mut storage, &42).unwrap();
+
+        // the current value is read from the same key as a plain Item
+        assert_eq!(Some(42), EVERY.may_load(&storage).unwrap());
+        EVERY.save(&mut storage, &43, 10).unwrap();
+        assert_eq!(43, plain.load(&storage).unwrap());
+        assert_eq!(Some(42), EVERY.may_load_at_height(&storage, 10).unwrap());
+    }
+}
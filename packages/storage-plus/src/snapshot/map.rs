@@ -0,0 +1,836 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use cosmwasm_std::{Order, StdError, StdResult, Storage};
+
+use crate::codec::{Codec, JsonCodec};
+use crate::de::KeyDeserialize;
+use crate::helpers::namespaces_with_key;
+use crate::keys::{PrimaryKey, U64Key};
+use crate::map::Map;
+use crate::migrate::Progress;
+use crate::path::Path;
+use crate::prefix::Prefix;
+use crate::snapshot::{ChangeSet, Checkpoints, Pruned, Strategy};
+use crate::{Bound, Prefixer};
+
+/// Map that maintains a snapshots of one or more checkpoints.
+/// We can query historical data as well as current state.
+/// What data is snapshotted depends on the Strategy.
+/// The values (and the changelog) are encoded with `C`, which is JSON unless specified otherwise.
+pub struct SnapshotMap<'a, K, T, C = JsonCodec> {
+    primary: Map<'a, K, T, C>,
+
+    checkpoints: Checkpoints<'a, C>,
+
+    // this stores all changes (key, height). Must differentiate between no data written,
+    // and explicit None (just inserted)
+    changelog: Map<'a, (K, U64Key), ChangeSet<T>, C>,
+
+    // if set, changelog entries older than this many blocks are pruned on write
+    retention: Option<u64>,
+}
+
+/// How many changelog keys the retention window visits on every write
+const RETENTION_PRUNE_STEPS: usize = 4;
+
+impl<'a, K, T, C> SnapshotMap<'a, K, T, C> {
+    /// Usage: SnapshotMap::new(snapshot_names!("foobar"), Strategy::EveryBlock)
+    pub const fn new(
+        pk: &'a str,
+        checkpoints: &'a str,
+        changelog: &'a str,
+        strategy: Strategy,
+    ) -> Self {
+        SnapshotMap {
+            primary: Map::new(pk),
+            checkpoints: Checkpoints::new(checkpoints, changelog, strategy),
+            changelog: Map::new(changelog),
+            retention: None,
+        }
+    }
+
+    /// Keeps only the last `blocks` blocks of history. Whenever a key is written at height h,
+    /// queries below h - blocks are rejected from then on, and the changelog below that height
+    /// is pruned a few keys at a time, going round all keys of the map over many writes.
+    ///
+    /// Usage: SnapshotMap::new(...).with_retention(100_000)
+    pub const fn with_retention(self, blocks: u64) -> Self {
+        SnapshotMap {
+            retention: Some(blocks),
+            ..self
+        }
+    }
+
+    pub fn add_checkpoint(&self, store: &mut dyn Storage, height: u64) -> StdResult<()> {
+        self.checkpoints.add(store, height)
+    }
+
+    pub fn remove_checkpoint(&self, store: &mut dyn Storage, height: u64) -> StdResult<()> {
+        self.checkpoints.remove(store, height)
+    }
+}
+
+impl<'a, K, T, C> SnapshotMap<'a, K, T, C>
+where
+    T: Serialize + DeserializeOwned + Clone,
+    C: Codec,
+    K: PrimaryKey<'a> + Prefixer<'a> + KeyDeserialize,
+{
+    pub fn key(&self, k: K) -> Path<T, C> {
+        self.primary.key(k)
+    }
+
+    pub fn prefix(&self, p: K::Prefix) -> Prefix<T> {
+        self.primary.prefix(p)
+    }
+
+    pub fn range<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: cosmwasm_std::Order,
+    ) -> Box<dyn Iterator<Item = StdResult<cosmwasm_std::KV<T>>> + 'c>
+    where
+        T: 'c,
+    {
+        self.primary.no_prefix().range(store, min, max, order)
+    }
+
+    /// Returns only the keys (as raw bytes, like `range`), without parsing any of the values
+    pub fn keys<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = Vec<u8>> + 'c> {
+        self.primary.keys(store, min, max, order)
+    }
+
+    /// Returns the complete storage keys (including the namespace), without parsing any of the values
+    pub fn keys_raw<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = Vec<u8>> + 'c> {
+        self.primary.keys_raw(store, min, max, order)
+    }
+
+    /// Counts the current entries between min and max, without parsing any of the values
+    pub fn count(&self, store: &dyn Storage, min: Option<Bound>, max: Option<Bound>) -> usize {
+        self.primary.count(store, min, max)
+    }
+
+    /// should_checkpoint looks at the strategy and determines if we want to checkpoint
+    fn should_checkpoint(&self, store: &dyn Storage, k: &K) -> StdResult<bool> {
+        match self.checkpoints.strategy() {
+            Strategy::EveryBlock => Ok(true),
+            Strategy::Never => Ok(false),
+            Strategy::Selected => self.should_checkpoint_selected(store, k),
+        }
+    }
+
+    /// this is just pulled out from above for the selected block
+    fn should_checkpoint_selected(&self, store: &dyn Storage, k: &K) -> StdResult<bool> {
+        // most recent checkpoint
+        if let Some(height) = self.checkpoints.latest(store)? {
+            // any changelog for the given key since then?
+            let start = Bound::inclusive(U64Key::from(height));
+            let first = self
+                .changelog
+                .prefix(k.clone())
+                .range(store, Some(start), None, Order::Ascending)
+                .next()
+                .transpose()?;
+            if first.is_none() {
+                // there must be at least one open checkpoint and no changelog for the given address since then
+                return Ok(true);
+            }
+        }
+        // otherwise, we don't save this
+        Ok(false)
+    }
+
+    /// load old value and store changelog
+    fn write_change(&self, store: &mut dyn Storage, k: K, height: u64) -> StdResult<()> {
+        // if there is already data in the changelog for this key and block, do not write more
+        if self.changelog.has(store, (k.clone(), U64Key::from(height))) {
+            return Ok(());
+        }
+        // otherwise, store the previous value
+        let old = self.primary.may_load(store, k.clone())?;
+        self.changelog
+            .save(store, (k, U64Key::from(height)), &ChangeSet { old })
+    }
+
+    /// store changelog, with the old value the caller already loaded
+    fn write_loaded_change(
+        &self,
+        store: &mut dyn Storage,
+        k: K,
+        height: u64,
+        old: Option<&T>,
+    ) -> StdResult<()> {
+        if self.changelog.has(store, (k.clone(), U64Key::from(height))) {
+            return Ok(());
+        }
+        let old = old.cloned();
+        self.changelog
+            .save(store, (k, U64Key::from(height)), &ChangeSet { old })
+    }
+
+    /// moves the cutoff to the retention window, and continues pruning where the last write stopped
+    fn apply_retention(&self, store: &mut dyn Storage, height: u64) -> StdResult<()> {
+        let before = match self.retention {
+            Some(blocks) if height > blocks => height - blocks,
+            _ => return Ok(()),
+        };
+        let pruned = self.checkpoints.pruned(store)?;
+        let progress = self.prune_changelog(store, before, pruned.cursor, RETENTION_PRUNE_STEPS)?;
+        self.checkpoints.save_pruned(
+            store,
+            Pruned {
+                before,
+                cursor: progress.next_key,
+            },
+        )
+    }
+
+    pub fn save(&self, store: &mut dyn Storage, k: K, data: &T, height: u64) -> StdResult<()> {
+        if self.should_checkpoint(store, &k)? {
+            self.write_change(store, k.clone(), height)?;
+        }
+        self.apply_retention(store, height)?;
+        self.primary.save(store, k, data)
+    }
+
+    pub fn remove(&self, store: &mut dyn Storage, k: K, height: u64) -> StdResult<()> {
+        if self.should_checkpoint(store, &k)? {
+            self.write_change(store, k.clone(), height)?;
+        }
+        self.apply_retention(store, height)?;
+        self.primary.remove(store, k);
+        Ok(())
+    }
+
+    /// replace writes data to key (or removes it on None) at the given height. old_data must be
+    /// the current stored value (from a previous load), it goes to the changelog without reading
+    /// the value again.
+    pub(crate) fn replace(
+        &self,
+        store: &mut dyn Storage,
+        k: K,
+        data: Option<&T>,
+        old_data: Option<&T>,
+        height: u64,
+    ) -> StdResult<()> {
+        if self.should_checkpoint(store, &k)? {
+            self.write_loaded_change(store, k.clone(), height, old_data)?;
+        }
+        self.apply_retention(store, height)?;
+        match data {
+            Some(data) => self.primary.save(store, k, data),
+            None => {
+                self.primary.remove(store, k);
+                Ok(())
+            }
+        }
+    }
+
+    /// Applies many writes at the given height in one pass: `Some(data)` saves the value,
+    /// `None` removes the key. Every old value is read only once. Removing a key that is not
+    /// there is skipped, so it leaves no changelog. Returns the previous value of every item,
+    /// in the same order.
+    pub fn update_many<I>(
+        &self,
+        store: &mut dyn Storage,
+        items: I,
+        height: u64,
+    ) -> StdResult<Vec<Option<T>>>
+    where
+        I: IntoIterator<Item = (K, Option<T>)>,
+    {
+        let mut olds = vec![];
+        for (k, data) in items {
+            let old = self.may_load(store, k.clone())?;
+            if data.is_some() || old.is_some() {
+                self.replace(store, k, data.as_ref(), old.as_ref(), height)?;
+            }
+            olds.push(old);
+        }
+        Ok(olds)
+    }
+
+    /// Removes the changelog entries written below `before_height`, visiting at most `limit`
+    /// changelog keys. Every step either removes one entry, or skips the rest of the history
+    /// of one key. Pass the returned `next_key` as `start_after` to continue, it is None once
+    /// all keys were visited.
+    ///
+    /// Afterwards `may_load_at_height` returns an error for any height below `before_height`,
+    /// while everything from `before_height` on stays queryable.
+    pub fn prune(
+        &self,
+        store: &mut dyn Storage,
+        before_height: u64,
+        start_after: Option<Vec<u8>>,
+        limit: usize,
+    ) -> StdResult<Progress> {
+        if limit == 0 {
+            return Err(StdError::generic_err(
+                "Prune limit must be greater than zero",
+            ));
+        }
+        self.checkpoints.set_pruned_before(store, before_height)?;
+        self.prune_changelog(store, before_height, start_after, limit)
+    }
+
+    fn prune_changelog(
+        &self,
+        store: &mut dyn Storage,
+        before_height: u64,
+        start_after: Option<Vec<u8>>,
+        limit: usize,
+    ) -> StdResult<Progress> {
+        let mut cursor = start_after;
+        let mut removed = 0;
+        for _ in 0..limit {
+            let start = cursor.clone().map(Bound::Exclusive);
+            let key = match self
+                .changelog
+                .keys(store, start, None, Order::Ascending)
+                .next()
+            {
+                Some(key) => key,
+                None => {
+                    return Ok(Progress {
+                        moved: removed,
+                        next_key: None,
+                    })
+                }
+            };
+            // the changelog key is the joined primary key followed by the height (8 bytes)
+            let (pk, height) = key.split_at(key.len() - 8);
+            if U64Key::from_slice(height)? < before_height {
+                store.remove(&namespaces_with_key(&[self.changelog.namespace()], &key));
+                removed += 1;
+                cursor = Some(key);
+            } else {
+                // the rest of the history of this key is kept
+                cursor = Some([pk, &u64::MAX.to_be_bytes()].concat());
+            }
+        }
+        Ok(Progress {
+            moved: removed,
+            next_key: cursor,
+        })
+    }
+
+    /// load will return an error if no data is set at the given key, or on parse error
+    pub fn load(&self, store: &dyn Storage, k: K) -> StdResult<T> {
+        self.primary.load(store, k)
+    }
+
+    /// may_load will parse the data stored at the key if present, returns Ok(None) if no data there.
+    /// returns an error on issues parsing
+    pub fn may_load(&self, store: &dyn Storage, k: K) -> StdResult<Option<T>> {
+        self.primary.may_load(store, k)
+    }
+
+    /// has returns true if the key is set, without parsing the value
+    pub fn has(&self, store: &dyn Storage, k: K) -> bool {
+        self.primary.has(store, k)
+    }
+
+    // may_load_at_height reads historical data from given checkpoints.
+    // Only returns `Ok` if we have the data to be able to give the correct answer
+    // (Strategy::EveryBlock or Strategy::Selected and h is registered as checkpoint)
+    //
+    // If there is no checkpoint for that height, then we return StdError::NotFound
+    pub fn may_load_at_height(
+        &self,
+        store: &dyn Storage,
+        k: K,
+        height: u64,
+    ) -> StdResult<Option<T>> {
+        self.assert_checkpointed(store, height)?;
+
+        // this will look for the first snapshot of the given address >= given height
+        // If None, there is no snapshot since that time.
+        let start = Bound::inclusive(U64Key::new(height));
+        let first = self
+            .changelog
+            .prefix(k.clone())
+            .range(store, Some(start), None, Order::Ascending)
+            .next();
+
+        if let Some(r) = first {
+            // if we found a match, return this last one
+            r.map(|(_, v)| v.old)
+        } else {
+            // otherwise, return current value
+            self.may_load(store, k)
+        }
+    }
+
+    // If there is no checkpoint for that height, then we return StdError::NotFound
+    pub fn assert_checkpointed(&self, store: &dyn Storage, height: u64) -> StdResult<()> {
+        self.checkpoints.assert_checkpointed(store, height)
+    }
+
+    /// Loads the data, perform the specified action, and store the result
+    /// in the database. This is shorthand for some common sequences, which may be useful.
+    ///
+    /// If the data exists, `action(Some(value))` is called. Otherwise `action(None)` is called.
+    ///
+    /// This is a bit more customized than needed to only read "old" value 1 time, not 2 per naive approach
+    pub fn update<A, E>(
+        &self,
+        store: &mut dyn Storage,
+        k: K,
+        height: u64,
+        action: A,
+    ) -> Result<T, E>
+    where
+        A: FnOnce(Option<T>) -> Result<T, E>,
+        E: From<StdError>,
+    {
+        let input = self.may_load(store, k.clone())?;
+        let output = action(input)?;
+        self.save(store, k, &output, height)?;
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+
+    type TestMap = SnapshotMap<'static, &'static [u8], u64>;
+    const NEVER: TestMap =
+        SnapshotMap::new("never", "never__check", "never__change", Strategy::Never);
+    const EVERY: TestMap = SnapshotMap::new(
+        "every",
+        "every__check",
+        "every__change",
+        Strategy::EveryBlock,
+    );
+    const SELECT: TestMap = SnapshotMap::new(
+        "select",
+        "select__check",
+        "select__change",
+        Strategy::Selected,
+    );
+
+    // Fills a map &[u8] -> u64 with the following writes:
+    // 1: A = 5
+    // 2: B = 7
+    // 3: C = 1, A = 8
+    // 4: B = None, C = 13
+    // 5: A = None, D = 22
+    // Final values -> C = 13, D = 22
+    // Values at beginning of 3 -> A = 5, B = 7
+    // Values at beginning of 5 -> A = 8, C = 13
+    fn init_data(map: &TestMap, storage: &mut dyn Storage) {
+        map.save(storage, b"A", &5, 1).unwrap();
+        map.save(storage, b"B", &7, 2).unwrap();
+
+        // checkpoint 3
+        map.add_checkpoint(storage, 3).unwrap();
+
+        // also use update to set - to ensure this works
+        map.save(storage, b"C", &1, 3).unwrap();
+        map.update(storage, b"A", 3, |_| -> StdResult<u64> { Ok(8) })
+            .unwrap();
+
+        map.remove(storage, b"B", 4).unwrap();
+        map.save(storage, b"C", &13, 4).unwrap();
+
+        // checkpoint 5
+        map.add_checkpoint(storage, 5).unwrap();
+        map.remove(storage, b"A", 5).unwrap();
+        map.update(storage, b"D", 5, |_| -> StdResult<u64> { Ok(22) })
+            .unwrap();
+        // and delete it later (unknown if all data present)
+        map.remove_checkpoint(storage, 5).unwrap();
+    }
+
+    const FINAL_VALUES: &[(&[u8], Option<u64>)] = &[
+        (b"A", None),
+        (b"B", None),
+        (b"C", Some(13)),
+        (b"D", Some(22)),
+    ];
+
+    const VALUES_START_3: &[(&[u8], Option<u64>)] =
+        &[(b"A", Some(5)), (b"B", Some(7)), (b"C", None), (b"D", None)];
+
+    const VALUES_START_5: &[(&[u8], Option<u64>)] = &[
+        (b"A", Some(8)),
+        (b"B", None),
+        (b"C", Some(13)),
+        (b"D", None),
+    ];
+
+    fn assert_final_values(map: &TestMap, storage: &dyn Storage) {
+        for (k, v) in FINAL_VALUES.iter().cloned() {
+            assert_eq!(v, map.may_load(storage, k).unwrap());
+        }
+    }
+
+    fn assert_values_at_height(
+        map: &TestMap,
+        storage: &dyn Storage,
+        height: u64,
+        values: &[(&[u8], Option<u64>)],
+    ) {
+        for (k, v) in values.iter().cloned() {
+            assert_eq!(v, map.may_load_at_height(storage, k, height).unwrap());
+        }
+    }
+
+    fn assert_missing_checkpoint(map: &TestMap, storage: &dyn Storage, height: u64) {
+        for k in &[b"A", b"B", b"C", b"D"] {
+            assert!(map.may_load_at_height(storage, *k, height).is_err());
+        }
+    }
+
+    #[test]
+    fn never_works_like_normal_map() {
+        let mut storage = MockStorage::new();
+        init_data(&NEVER, &mut storage);
+        assert_final_values(&NEVER, &storage);
+
+        // historical queries return error
+        assert_missing_checkpoint(&NEVER, &storage, 3);
+        assert_missing_checkpoint(&NEVER, &storage, 5);
+    }
+
+    #[test]
+    fn every_blocks_stores_present_and_past() {
+        let mut storage = MockStorage::new();
+        init_data(&EVERY, &mut storage);
+        assert_final_values(&EVERY, &mut storage);
+
+        // historical queries return historical values
+        assert_values_at_height(&EVERY, &storage, 3, VALUES_START_3);
+        assert_values_at_height(&EVERY, &storage, 5, VALUES_START_5);
+    }
+
+    #[test]
+    fn selected_shows_3_not_5() {
+        let mut storage = MockStorage::new();
+        init_data(&SELECT, &mut storage);
+        assert_final_values(&SELECT, &mut storage);
+
+        // historical queries return historical values
+        assert_values_at_height(&SELECT, &storage, 3, VALUES_START_3);
+        // never checkpointed
+        assert_missing_checkpoint(&NEVER, &storage, 1);
+        // deleted checkpoint
+        assert_missing_checkpoint(&NEVER, &storage, 5);
+    }
+
+    #[test]
+    fn count_only_current_keys() {
+        let mut storage = MockStorage::new();
+        init_data(&EVERY, &mut storage);
+
+        // changelog entries are not counted, only C and D are left
+        assert_eq!(2, EVERY.count(&storage, None, None));
+        let keys: Vec<_> = EVERY.keys(&storage, None, None, Order::Ascending).collect();
+        assert_eq!(keys, vec![b"C".to_vec(), b"D".to_vec()]);
+        assert_eq!(
+            1,
+            EVERY.count(&storage, Some(Bound::exclusive(b"C".to_vec())), None)
+        );
+    }
+
+    #[test]
+    fn handle_multiple_writes_in_one_block() {
+        let mut storage = MockStorage::new();
+
+        println!("SETUP");
+        EVERY.save(&mut storage, b"A", &5, 1).unwrap();
+        EVERY.save(&mut storage, b"B", &7, 2).unwrap();
+        EVERY.save(&mut storage, b"C", &2, 2).unwrap();
+
+        // update and save - A query at 3 => 5, at 4 => 12
+        EVERY
+            .update(&mut storage, b"A", 3, |_| -> StdResult<u64> { Ok(9) })
+            .unwrap();
+        EVERY.save(&mut storage, b"A", &12, 3).unwrap();
+        assert_eq!(
+            Some(5),
+            EVERY.may_load_at_height(&storage, b"A", 2).unwrap()
+        );
+        assert_eq!(
+            Some(5),
+            EVERY.may_load_at_height(&storage, b"A", 3).unwrap()
+        );
+        assert_eq!(
+            Some(12),
+            EVERY.may_load_at_height(&storage, b"A", 4).unwrap()
+        );
+
+        // save and remove - B query at 4 => 7, at 5 => None
+        EVERY.save(&mut storage, b"B", &17, 4).unwrap();
+        EVERY.remove(&mut storage, b"B", 4).unwrap();
+        assert_eq!(
+            Some(7),
+            EVERY.may_load_at_height(&storage, b"B", 3).unwrap()
+        );
+        assert_eq!(
+            Some(7),
+            EVERY.may_load_at_height(&storage, b"B", 4).unwrap()
+        );
+        assert_eq!(None, EVERY.may_load_at_height(&storage, b"B", 5).unwrap());
+
+        // remove and update - C query at 5 => 2, at 6 => 16
+        EVERY.remove(&mut storage, b"C", 5).unwrap();
+        EVERY
+            .update(&mut storage, b"C", 5, |_| -> StdResult<u64> { Ok(16) })
+            .unwrap();
+        assert_eq!(
+            Some(2),
+            EVERY.may_load_at_height(&storage, b"C", 4).unwrap()
+        );
+        assert_eq!(
+            Some(2),
+            EVERY.may_load_at_height(&storage, b"C", 5).unwrap()
+        );
+        assert_eq!(
+            Some(16),
+            EVERY.may_load_at_height(&storage, b"C", 6).unwrap()
+        );
+    }
+
+    #[test]
+    fn prune_old_history() {
+        let mut storage = MockStorage::new();
+        init_data(&EVERY, &mut storage);
+        let changes = |storage: &dyn Storage| {
+            EVERY
+                .changelog
+                .keys_raw(storage, None, None, Order::Ascending)
+                .count()
+        };
+        // A@1, B@2, A@3, C@3, B@4, C@4, A@5, D@5
+        assert_eq!(8, changes(&storage));
+
+        // limit is respected, A@1 and A@3 go first
+        let progress = EVERY.prune(&mut storage, 4, None, 2).unwrap();
+        assert_eq!(2, progress.moved);
+        assert_eq!(6, changes(&storage));
+        // A@5 is kept, B@2 removed, B@4 kept
+        let progress = EVERY.prune(&mut storage, 4, progress.next_key, 3).unwrap();
+        assert_eq!(1, progress.moved);
+        // C@3 removed, C@4 and D@5 kept, then done
+        let progress = EVERY.prune(&mut storage, 4, progress.next_key, 10).unwrap();
+        assert_eq!(1, progress.moved);
+        assert_eq!(None, progress.next_key);
+        assert_eq!(4, changes(&storage));
+
+        // pruned heights cannot be queried anymore, later ones are unchanged
+        assert_missing_checkpoint(&EVERY, &storage, 3);
+        assert_values_at_height(&EVERY, &storage, 5, VALUES_START_5);
+        assert_final_values(&EVERY, &storage);
+
+        // the cutoff never moves back
+        let progress = EVERY.prune(&mut storage, 2, None, 10).unwrap();
+        assert_eq!(0, progress.moved);
+        assert_missing_checkpoint(&EVERY, &storage, 3);
+
+        // a limit of zero is rejected
+        EVERY.prune(&mut storage, 4, None, 0).unwrap_err();
+
+        // the cutoff has its own length-prefixed key, next to no checkpoints data
+        assert_eq!(None, storage.get(b"every__check__pruned"));
+        let pruned: Map<&[u8], Pruned> = Map::new("__pruned");
+        assert_eq!(4, pruned.load(&storage, b"every__change").unwrap().before);
+    }
+
+    #[test]
+    fn prune_visits_limited_keys() {
+        let mut storage = MockStorage::new();
+        init_data(&SELECT, &mut storage);
+        // checkpoints are still found after pruning
+        SELECT.prune(&mut storage, 4, None, 100).unwrap();
+        assert_eq!(Some(3), SELECT.checkpoints.latest(&storage).unwrap());
+
+        // one step per call, nothing to prune below 1 still visits every key once
+        let mut storage = MockStorage::new();
+        init_data(&EVERY, &mut storage);
+        let mut calls = 0;
+        let mut next_key = None;
+        loop {
+            let progress = EVERY.prune(&mut storage, 1, next_key, 1).unwrap();
+            assert_eq!(0, progress.moved);
+            calls += 1;
+            next_key = progress.next_key;
+            if next_key.is_none() {
+                break;
+            }
+        }
+        // A, B, C, D and the final call that finds nothing more
+        assert_eq!(5, calls);
+
+        // one step per call, until all history below 6 is gone
+        let mut total = 0;
+        let mut next_key = None;
+        loop {
+            let progress = EVERY.prune(&mut storage, 6, next_key, 1).unwrap();
+            total += progress.moved;
+            next_key = progress.next_key;
+            if next_key.is_none() {
+                break;
+            }
+        }
+        assert_eq!(8, total);
+        assert_final_values(&EVERY, &storage);
+    }
+
+    #[test]
+    fn retention_window() {
+        let mut storage = MockStorage::new();
+        let map: TestMap = SnapshotMap::new(
+            "every",
+            "every__check",
+            "every__change",
+            Strategy::EveryBlock,
+        )
+        .with_retention(10);
+        let changes = |storage: &dyn Storage| {
+            map.changelog
+                .keys_raw(storage, None, None, Order::Ascending)
+                .count()
+        };
+
+        map.save(&mut storage, b"A", &1, 1).unwrap();
+        map.save(&mut storage, b"A", &2, 5).unwrap();
+        map.save(&mut storage, b"B", &3, 8).unwrap();
+        assert_eq!(Some(1), map.may_load_at_height(&storage, b"A", 2).unwrap());
+
+        // writing at 14 moves the cutoff to 4 and removes A@1
+        map.save(&mut storage, b"A", &4, 14).unwrap();
+        assert_missing_checkpoint(&map, &storage, 3);
+        assert_eq!(Some(1), map.may_load_at_height(&storage, b"A", 5).unwrap());
+        assert_eq!(Some(2), map.may_load_at_height(&storage, b"A", 14).unwrap());
+        assert_eq!(None, map.may_load_at_height(&storage, b"B", 4).unwrap());
+        assert_eq!(Some(3), map.may_load_at_height(&storage, b"B", 9).unwrap());
+        let changes_a: Vec<_> = map
+            .changelog
+            .prefix_de(b"A")
+            .keys_de(&storage, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert_eq!(changes_a, vec![5, 14]);
+
+        // B is never written again, but its history is pruned by writes to other keys
+        assert_eq!(3, changes(&storage));
+        for height in 20..25 {
+            map.save(&mut storage, b"C", &height, height).unwrap();
+        }
+        // only the changes within the window (from 14 on) are left
+        let keys: Vec<_> = map
+            .changelog
+            .keys_de(&storage, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        let mut expected = vec![(b"A".to_vec(), 14)];
+        expected.extend((20..25).map(|h| (b"C".to_vec(), h)));
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn update_many_writes_changelog() {
+        let mut storage = MockStorage::new();
+        EVERY.save(&mut storage, b"A", &5, 1).unwrap();
+
+        let olds = EVERY
+            .update_many(
+                &mut storage,
+                vec![(b"A".as_ref(), None), (b"B", Some(7)), (b"C", None)],
+                3,
+            )
+            .unwrap();
+        assert_eq!(olds, vec![Some(5), None, None]);
+
+        assert_eq!(None, EVERY.may_load(&storage, b"A").unwrap());
+        assert_eq!(Some(7), EVERY.may_load(&storage, b"B").unwrap());
+        assert!(!EVERY.has(&storage, b"A"));
+        assert!(EVERY.has(&storage, b"B"));
+        assert_eq!(
+            Some(5),
+            EVERY.may_load_at_height(&storage, b"A", 3).unwrap()
+        );
+        assert_eq!(None, EVERY.may_load_at_height(&storage, b"A", 4).unwrap());
+        assert_eq!(None, EVERY.may_load_at_height(&storage, b"B", 3).unwrap());
+
+        // removing a missing key leaves no history
+        let changes = EVERY
+            .changelog
+            .prefix_de(b"C")
+            .keys_de(&storage, None, None, Order::Ascending)
+            .count();
+        assert_eq!(0, changes);
+    }
+
+    /// counts the reads of a single raw key
+    struct ReadCounter {
+        inner: MockStorage,
+        key: Vec<u8>,
+        reads: std::cell::Cell<usize>,
+    }
+
+    impl Storage for ReadCounter {
+        fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+            if key == self.key.as_slice() {
+                self.reads.set(self.reads.get() + 1);
+            }
+            self.inner.get(key)
+        }
+
+        fn range<'a>(
+            &'a self,
+            start: Option<&[u8]>,
+            end: Option<&[u8]>,
+            order: Order,
+        ) -> Box<dyn Iterator<Item = cosmwasm_std::KV> + 'a> {
+            self.inner.range(start, end, order)
+        }
+
+        fn set(&mut self, key: &[u8], value: &[u8]) {
+            self.inner.set(key, value)
+        }
+
+        fn remove(&mut self, key: &[u8]) {
+            self.inner.remove(key)
+        }
+    }
+
+    #[test]
+    fn update_many_reads_once() {
+        let mut storage = ReadCounter {
+            inner: MockStorage::new(),
+            key: EVERY.key(b"A").to_vec(),
+            reads: Default::default(),
+        };
+        EVERY.save(&mut storage, b"A", &5, 1).unwrap();
+        storage.reads.set(0);
+
+        let olds = EVERY
+            .update_many(&mut storage, vec![(b"A".as_ref(), Some(8))], 3)
+            .unwrap();
+        assert_eq!(olds, vec![Some(5)]);
+        assert_eq!(1, storage.reads.get());
+        assert_eq!(
+            Some(5),
+            EVERY.may_load_at_height(&storage, b"A", 3).unwrap()
+        );
+    }
+}
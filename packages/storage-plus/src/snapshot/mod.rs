@@ -0,0 +1,158 @@
+#![cfg(feature = "iterator")]
+mod item;
+mod map;
+
+pub use item::SnapshotItem;
+pub use map::SnapshotMap;
+
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{Order, StdError, StdResult, Storage};
+
+use crate::codec::{Codec, JsonCodec};
+use crate::keys::U64Key;
+use crate::map::Map;
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Strategy {
+    EveryBlock,
+    Never,
+    /// Only writes for linked blocks - does a few more reads to save some writes.
+    /// Probably uses more gas, but less total disk usage.
+    ///
+    /// Note that you need a trusted source (eg. own contract) to set/remove checkpoints.
+    /// Useful when the checkpoint setting happens in the same contract as the snapshotting.
+    Selected,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub(crate) struct ChangeSet<T> {
+    pub old: Option<T>,
+}
+
+/// How far history has been pruned, stored for every snapshotted type
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Pruned {
+    /// history below this height is no longer available
+    pub before: u64,
+    /// where the retention window continues pruning on the next write
+    pub cursor: Option<Vec<u8>>,
+}
+
+// all pruning states share this namespace, keyed by the changelog namespace
+const PRUNED_NAMESPACE: &str = "__pruned";
+
+/// Checkpoints holds the strategy and the list of selected heights,
+/// which is shared by all snapshotted types.
+pub(crate) struct Checkpoints<'a, C = JsonCodec> {
+    // maps height to number of checkpoints (only used for selected)
+    checkpoints: Map<'a, U64Key, u32>,
+
+    // the key of the pruning state, which is the changelog namespace, as types sharing
+    // the checkpoints each prune their own changelog
+    namespace: &'a [u8],
+
+    // the pruning state, encoded with the codec of the snapshotted type
+    pruned: Map<'a, &'a [u8], Pruned, C>,
+
+    // How aggressive we are about checkpointing all data
+    strategy: Strategy,
+}
+
+impl<'a, C> Checkpoints<'a, C> {
+    pub const fn new(checkpoints: &'a str, changelog: &'a str, strategy: Strategy) -> Self {
+        Checkpoints {
+            checkpoints: Map::new(checkpoints),
+            namespace: changelog.as_bytes(),
+            pruned: Map::new(PRUNED_NAMESPACE),
+            strategy,
+        }
+    }
+
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    pub fn add(&self, store: &mut dyn Storage, height: u64) -> StdResult<()> {
+        self.checkpoints
+            .update::<_, StdError>(store, height.into(), |count| {
+                Ok(count.unwrap_or_default() + 1)
+            })?;
+        Ok(())
+    }
+
+    pub fn remove(&self, store: &mut dyn Storage, height: u64) -> StdResult<()> {
+        let count = self
+            .checkpoints
+            .may_load(store, height.into())?
+            .unwrap_or_default();
+        if count <= 1 {
+            self.checkpoints.remove(store, height.into());
+            Ok(())
+        } else {
+            self.checkpoints.save(store, height.into(), &(count - 1))
+        }
+    }
+
+    /// Returns the most recent checkpoint height, if any
+    pub fn latest(&self, store: &dyn Storage) -> StdResult<Option<u64>> {
+        let checkpoint = self
+            .checkpoints
+            .keys_de(store, None, None, Order::Descending)
+            .next()
+            .transpose()?;
+        Ok(checkpoint)
+    }
+}
+
+impl<'a, C: Codec> Checkpoints<'a, C> {
+    pub fn pruned(&self, store: &dyn Storage) -> StdResult<Pruned> {
+        Ok(self
+            .pruned
+            .may_load(store, self.namespace)?
+            .unwrap_or_default())
+    }
+
+    /// Saves the pruning state. `before` never moves backwards.
+    pub fn save_pruned(&self, store: &mut dyn Storage, pruned: Pruned) -> StdResult<()> {
+        let before = pruned.before.max(self.pruned(store)?.before);
+        self.pruned
+            .save(store, self.namespace, &Pruned { before, ..pruned })
+    }
+
+    /// Returns the height below which history is no longer available (0 if never pruned)
+    pub fn pruned_before(&self, store: &dyn Storage) -> StdResult<u64> {
+        Ok(self.pruned(store)?.before)
+    }
+
+    /// Marks all history below height as gone. This never moves backwards.
+    pub fn set_pruned_before(&self, store: &mut dyn Storage, height: u64) -> StdResult<()> {
+        let pruned = self.pruned(store)?;
+        if height > pruned.before {
+            self.save_pruned(
+                store,
+                Pruned {
+                    before: height,
+                    ..pruned
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    // If there is no checkpoint for that height, then we return StdError::NotFound
+    pub fn assert_checkpointed(&self, store: &dyn Storage, height: u64) -> StdResult<()> {
+        if height < self.pruned_before(store)? {
+            return Err(StdError::not_found("pruned checkpoint"));
+        }
+        let has = match self.strategy {
+            Strategy::EveryBlock => true,
+            Strategy::Never => false,
+            Strategy::Selected => self.checkpoints.may_load(store, height.into())?.is_some(),
+        };
+        match has {
+            true => Ok(()),
+            false => Err(StdError::not_found("checkpoint")),
+        }
+    }
+}